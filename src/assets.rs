@@ -0,0 +1,35 @@
+use std::fs;
+use std::io;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use crate::container::Asset;
+use crate::daemon::Attachment;
+use crate::image;
+
+/// Packs assets into a gzipped tar whose paths are their targets, relative to
+/// the root of the container, which the daemon extracts over its filesystem.
+/// Directories are packed with their contents and symlinks are kept as links.
+pub fn pack(assets: &[Asset]) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    builder.follow_symlinks(false);
+
+    for asset in assets {
+        let error = |err: io::Error| io::Error::new(err.kind(), format!("asset {}: {}", asset.source.display(), err));
+
+        let target = asset.target.strip_prefix("/").unwrap_or(&asset.target);
+        match fs::metadata(&asset.source).map_err(error)?.is_dir() {
+            true => builder.append_dir_all(target, &asset.source).map_err(error)?,
+            false => builder.append_path_with_name(&asset.source, target).map_err(error)?,
+        }
+    }
+
+    builder.into_inner()?.finish()
+}
+
+/// Packed assets as a document sent along with a container. Attachments are text,
+/// so the archive is in base64. A container may have several of them, extracted in order.
+pub fn attachment(packed: &[u8]) -> Attachment {
+    Attachment { media_type: image::JOKER_ASSETS.to_owned(), data: STANDARD.encode(packed) }
+}
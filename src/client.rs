@@ -0,0 +1,396 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::PreparedContainer;
+use crate::compose::StackInfo;
+use crate::container::{ContainerInfo, RunOptions, StatsSample};
+use crate::cron::{CronExpression, ScheduleInfo};
+use crate::daemon::{Daemon, DaemonAddress, DaemonStatus, Requests, Selection};
+use crate::errors::{InvalidOptionError, JokerError};
+use crate::signals::Signal;
+use crate::store::ImageInfo;
+use crate::transport::{Connection, TcpTransport, Transport};
+
+/// A client of a joker daemon for other Rust programs. It does what the
+/// commands of the CLI do, but returns the results instead of printing them.
+///
+/// ```no_run
+/// use joker::client::JokerClient;
+///
+/// let client = JokerClient::from_config()?;
+/// client.run(&["./app"], &Default::default(), None)?;
+/// println!("{}", client.logs("app")?);
+/// # Ok::<(), joker::errors::JokerError>(())
+/// ```
+#[derive(Clone)]
+pub struct JokerClient {
+    daemon: Daemon,
+    transport: Arc<dyn Transport>,
+}
+
+impl From<Daemon> for JokerClient {
+    fn from(daemon: Daemon) -> Self {
+        JokerClient { daemon, transport: Arc::new(TcpTransport::default()) }
+    }
+}
+
+impl JokerClient {
+    /// Makes a client of a daemon at an explicit address, an ip address or a hostname
+    /// with a port, without reading the CLI config.
+    pub fn new(name: &str, socket_address: DaemonAddress) -> Self {
+        JokerClient {
+            daemon: Daemon { name: name.to_owned(), socket_address },
+            transport: Arc::new(TcpTransport::default()),
+        }
+    }
+
+    /// Makes a client of the current daemon of the CLI config, or of the daemon of `JOKER_DAEMON`.
+    pub fn from_config() -> Result<Self, JokerError> {
+        Self::from_selection(&Selection::default())
+    }
+
+    /// Makes a client of the daemon of a selection, e.g. the one of `--config` and `--daemon`.
+    pub fn from_selection(selection: &Selection) -> Result<Self, JokerError> {
+        Ok(JokerClient::from(selection.current_daemon()?))
+    }
+
+    /// Makes a client of a daemon of the CLI config, without making it the current one.
+    pub fn for_daemon(name: &str) -> Result<Self, JokerError> {
+        Ok(JokerClient::from(Selection::default().find_daemon(name)?))
+    }
+
+    /// Reaches the daemon over another transport than TCP, e.g. a `MockTransport` in tests.
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Adds a daemon to the CLI config, replacing the address of a daemon with the same name.
+    pub fn add_daemon(name: &str, socket_address: DaemonAddress) -> Result<(), JokerError> {
+        Selection::default().add_daemon(name, socket_address)
+    }
+
+    /// Makes a daemon of the CLI config the current one and returns a client of it.
+    pub fn checkout(name: &str) -> Result<Self, JokerError> {
+        Ok(JokerClient::from(Selection::default().checkout(name)?))
+    }
+
+    /// The daemon the client talks to.
+    pub fn daemon(&self) -> &Daemon {
+        &self.daemon
+    }
+
+    /// Runs containers like `joker run`: binaries with a `.joker` config, OCI bundle
+    /// directories or images of the local store. All of them are checked before
+    /// anything is sent. An SBOM can only be given for a single container.
+    /// Returns the names the daemon knows the containers by.
+    pub fn run(&self, containers: &[&str], options: &RunOptions, sbom: Option<&Path>) -> Result<Vec<String>, JokerError> {
+        let prepared = crate::prepare_containers(containers, options, sbom)?;
+        let names = prepared.iter()
+            .map(crate::container_name)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.send(prepared)?;
+        Ok(names)
+    }
+
+    /// Returns the output of a container.
+    pub fn logs(&self, container_name: &str) -> Result<String, JokerError> {
+        let mut connection = self.connect(Requests::Logs)?;
+
+        // writing container name to a daemon
+        connection.write_all(&container_name.len().to_le_bytes()).map_err(JokerError::Connection)?;
+        connection.write_all(container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Returns the output of a container after its first `offset` bytes, and the length of
+    /// the whole output to ask for the next part from. The daemon answers output shorter than
+    /// the offset, which belongs to a new run of the container, as a whole.
+    pub fn logs_from(&self, container_name: &str, offset: u64) -> Result<(Vec<u8>, u64), JokerError> {
+        let mut connection = self.connect(Requests::LogsFrom)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+        crate::write_all_to_stream(&mut connection, offset.to_string().as_bytes()).map_err(JokerError::Connection)?;
+
+        let output = crate::read_result_from_stream(&mut connection)?;
+        let length = crate::read_all_from_stream(&mut connection).map_err(JokerError::Connection)?;
+        let length = String::from_utf8_lossy(&length).parse().map_err(|_| JokerError::Protocol("invalid length of the output".to_owned()))?;
+        Ok((output, length))
+    }
+
+    /// Returns the events of the daemon.
+    pub fn trace(&self) -> Result<String, JokerError> {
+        read_string(&mut self.connect(Requests::Trace)?)
+    }
+
+    /// Returns the state of the containers of the daemon.
+    pub fn list(&self) -> Result<Vec<ContainerInfo>, JokerError> {
+        let data = crate::read_all_from_stream(&mut self.connect(Requests::List)?).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Returns the resource usage of a container: the latest sample, or the samples
+    /// the daemon kept over the given time, oldest first.
+    pub fn stats(&self, container_name: &str, history: Option<Duration>) -> Result<Vec<StatsSample>, JokerError> {
+        let mut connection = self.connect(Requests::Stats)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+        let seconds = history.map_or(0, |history| history.as_secs());
+        crate::write_all_to_stream(&mut connection, seconds.to_string().as_bytes()).map_err(JokerError::Connection)?;
+
+        let data = crate::read_result_from_stream(&mut connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Checks that the daemon answers. Returns its state and the round-trip time of the request.
+    pub fn ping(&self) -> Result<(DaemonStatus, Duration), JokerError> {
+        let started = Instant::now();
+        let mut connection = self.connect(Requests::Ping)?;
+        let data = crate::read_all_from_stream(&mut connection).map_err(JokerError::Connection)?;
+        let latency = started.elapsed();
+
+        let status = serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))?;
+        Ok((status, latency))
+    }
+
+    /// Returns the state of a container.
+    pub fn inspect(&self, container_name: &str) -> Result<ContainerInfo, JokerError> {
+        let mut connection = self.connect(Requests::Inspect)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        let data = crate::read_all_from_stream(&mut connection).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Stops a container, keeping it on the daemon.
+    pub fn stop(&self, container_name: &str) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::Stop)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        crate::read_result_from_stream(&mut connection)?;
+        Ok(())
+    }
+
+    /// Stops a container if it's running and starts it again, like a restart of its policy.
+    pub fn restart(&self, container_name: &str) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::Restart)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        crate::read_result_from_stream(&mut connection)?;
+        Ok(())
+    }
+
+    /// Sends a signal to the process of a running container. A resize is followed
+    /// by the new size of the terminal, for the daemon to resize the one of the container.
+    pub fn signal(&self, container_name: &str, signal: Signal) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::Signal)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+        crate::write_all_to_stream(&mut connection, signal.to_string().as_bytes()).map_err(JokerError::Connection)?;
+        if let Signal::WindowChange(size) = signal {
+            crate::write_all_to_stream(&mut connection, size.to_string().as_bytes()).map_err(JokerError::Connection)?;
+        }
+
+        crate::read_result_from_stream(&mut connection)?;
+        Ok(())
+    }
+
+    /// Makes the daemon run a container it already has on a cron expression.
+    /// Returns the id of the schedule.
+    pub fn schedule(&self, expression: &CronExpression, container_name: &str) -> Result<String, JokerError> {
+        let mut connection = self.connect(Requests::ScheduleAdd)?;
+        crate::write_all_to_stream(&mut connection, expression.to_string().as_bytes()).map_err(JokerError::Connection)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        let id = crate::read_result_from_stream(&mut connection)?;
+        Ok(String::from_utf8_lossy(&id).into_owned())
+    }
+
+    /// Returns the schedules of the daemon with the outcome of their last runs.
+    pub fn schedules(&self) -> Result<Vec<ScheduleInfo>, JokerError> {
+        let data = crate::read_all_from_stream(&mut self.connect(Requests::ScheduleList)?).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Removes a schedule. A run already started isn't stopped.
+    pub fn unschedule(&self, id: &str) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::ScheduleRemove)?;
+        crate::write_all_to_stream(&mut connection, id.as_bytes()).map_err(JokerError::Connection)?;
+
+        crate::read_result_from_stream(&mut connection)?;
+        Ok(())
+    }
+
+    /// Stops a container and removes it from the daemon.
+    pub fn remove(&self, container_name: &str) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::Remove)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        crate::read_result_from_stream(&mut connection)?;
+        Ok(())
+    }
+
+    /// Returns the state of the containers of a stack deployed on the daemon.
+    pub fn stacks(&self) -> Result<Vec<StackInfo>, JokerError> {
+        let data = crate::read_all_from_stream(&mut self.connect(Requests::StackList)?).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Stops and removes all the containers of a stack. Returns the answer of the daemon.
+    pub fn stack_down(&self, stack_name: &str) -> Result<String, JokerError> {
+        let mut connection = self.connect(Requests::StackDown)?;
+        crate::write_all_to_stream(&mut connection, stack_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Returns the SBOM a container was run with, as it was attached.
+    pub fn sbom(&self, container_name: &str) -> Result<Vec<u8>, JokerError> {
+        let mut connection = self.connect(Requests::InspectSbom)?;
+        crate::write_all_to_stream(&mut connection, container_name.as_bytes()).map_err(JokerError::Connection)?;
+
+        crate::read_result_from_stream(&mut connection)
+    }
+
+    /// Returns the images of the daemon's store.
+    pub fn images(&self) -> Result<Vec<ImageInfo>, JokerError> {
+        let data = crate::read_all_from_stream(&mut self.connect(Requests::Images)?).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Removes an image from the daemon's store, given by its store name,
+    /// e.g. `docker.io/library/alpine:latest`. Returns the answer of the daemon.
+    pub fn remove_image(&self, reference: &str) -> Result<String, JokerError> {
+        let mut connection = self.connect(Requests::RemoveImage)?;
+        crate::write_all_to_stream(&mut connection, reference.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Sends a config file to the daemon under its name.
+    pub fn send_config(&self, config_name: &str, config: &[u8]) -> Result<(), JokerError> {
+        let mut connection = self.connect(Requests::Send)?;
+        crate::write_all_to_stream(&mut connection, config_name.as_bytes()).map_err(JokerError::Connection)?;
+        crate::write_all_to_stream(&mut connection, config).map_err(JokerError::Connection)?;
+
+        Ok(())
+    }
+
+    /// Returns the public keys the daemon trusts, base64-encoded.
+    pub fn trusted_keys(&self) -> Result<Vec<String>, JokerError> {
+        let data = crate::read_all_from_stream(&mut self.connect(Requests::TrustList)?).map_err(JokerError::Connection)?;
+        serde_json::from_slice(&data).map_err(|err| JokerError::Protocol(err.to_string()))
+    }
+
+    /// Makes the daemon trust a base64-encoded ed25519 public key, which is checked first.
+    /// Returns the answer of the daemon.
+    pub fn trust(&self, public_key: &str) -> Result<String, JokerError> {
+        crate::signature::decode_public_key(public_key).map_err(|err| JokerError::Parse(err.into()))?;
+        let mut connection = self.connect(Requests::TrustAdd)?;
+        crate::write_all_to_stream(&mut connection, public_key.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Makes the daemon stop trusting a key, given by its id. Returns the answer of the daemon.
+    pub fn untrust(&self, key_id: &str) -> Result<String, JokerError> {
+        let mut connection = self.connect(Requests::TrustRemove)?;
+        crate::write_all_to_stream(&mut connection, key_id.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Creates a named volume. Returns the answer of the daemon.
+    pub fn create_volume(&self, volume_name: &str) -> Result<String, JokerError> {
+        self.named_request(Requests::VolumeCreate, "volume name", volume_name)
+    }
+
+    /// Returns the volumes of the daemon, as it lists them.
+    pub fn volumes(&self) -> Result<String, JokerError> {
+        read_string(&mut self.connect(Requests::VolumeList)?)
+    }
+
+    /// Removes a volume. Returns the answer of the daemon.
+    pub fn remove_volume(&self, volume_name: &str) -> Result<String, JokerError> {
+        self.named_request(Requests::VolumeRemove, "volume name", volume_name)
+    }
+
+    /// Creates a secret. Its value is sent only this once, the daemon never sends it back.
+    /// Returns the answer of the daemon.
+    pub fn create_secret(&self, secret_name: &str, value: &[u8]) -> Result<String, JokerError> {
+        check_name("secret name", secret_name)?;
+        if value.is_empty() {
+            return Err(JokerError::Other("Error: a secret can't be empty.".into()));
+        }
+        let mut connection = self.connect(Requests::SecretCreate)?;
+        crate::write_all_to_stream(&mut connection, secret_name.as_bytes()).map_err(JokerError::Connection)?;
+        crate::write_all_to_stream(&mut connection, value).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Returns the names of the secrets of the daemon, as it lists them.
+    pub fn secrets(&self) -> Result<String, JokerError> {
+        read_string(&mut self.connect(Requests::SecretList)?)
+    }
+
+    /// Removes a secret. Returns the answer of the daemon.
+    pub fn remove_secret(&self, secret_name: &str) -> Result<String, JokerError> {
+        self.named_request(Requests::SecretRemove, "secret name", secret_name)
+    }
+
+    /// Sends prepared containers to the daemon, running their local hooks around it.
+    pub(crate) fn send(&self, prepared: Vec<PreparedContainer>) -> Result<(), JokerError> {
+        crate::send_containers(self.transport.as_ref(), &self.daemon, prepared)
+    }
+
+    /// Makes a request about a volume or a secret, which is named by its only message.
+    fn named_request(&self, request: Requests, option: &str, name: &str) -> Result<String, JokerError> {
+        check_name(option, name)?;
+        let mut connection = self.connect(request)?;
+        crate::write_all_to_stream(&mut connection, name.as_bytes()).map_err(JokerError::Connection)?;
+
+        read_string(&mut connection)
+    }
+
+    /// Connects to the daemon and writes the type of a request. The CLI speaks
+    /// the requests which stream files, like `commit` or `export`, over it itself.
+    pub(crate) fn connect(&self, request: Requests) -> Result<Box<dyn Connection>, JokerError> {
+        let mut connection = self.transport.connect(&self.daemon).map_err(JokerError::Connection)?;
+        connection.write_all(&[request as u8]).map_err(JokerError::Connection)?;
+
+        Ok(connection)
+    }
+}
+
+/// Calls an operation with the clients of several daemons at once, each in its own thread,
+/// and returns its results in the order of the clients. The errors are turned into
+/// messages, as a `JokerError` can't be sent between threads.
+pub fn broadcast<T: Send>(
+    clients: &[JokerClient],
+    operation: impl Fn(&JokerClient) -> Result<T, JokerError> + Sync,
+) -> Vec<Result<T, String>> {
+    std::thread::scope(|scope| {
+        let operation = &operation;
+        let handles = clients.iter()
+            .map(|client| scope.spawn(move || operation(client).map_err(|err| err.to_string())))
+            .collect::<Vec<_>>();
+
+        handles.into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err("Error: the operation panicked.".to_owned())))
+            .collect()
+    })
+}
+
+/// Checks the name of a volume or a secret, which the daemon uses as a file name.
+fn check_name(option: &str, name: &str) -> Result<(), JokerError> {
+    match crate::container::is_valid_name(name) {
+        true => Ok(()),
+        false => Err(InvalidOptionError { option: option.to_owned(), value: name.to_owned() }.into()),
+    }
+}
+
+fn read_string(connection: &mut impl Read) -> Result<String, JokerError> {
+    let data = crate::read_all_from_stream(connection).map_err(JokerError::Connection)?;
+    String::from_utf8(data).map_err(|err| JokerError::Protocol(err.to_string()))
+}
@@ -0,0 +1,164 @@
+use std::io::{self, Write};
+use clap::{Arg, Command, ValueHint};
+use clap_complete::Shell;
+
+/// The names the completion scripts ask `joker __complete` for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Names {
+    Daemons,
+    Containers,
+}
+
+/// Completes the names of daemons and containers in bash, and every other word
+/// with the generated `_joker` function. An empty answer means the word isn't a name.
+const BASH_HOOK: &str = r#"
+_joker_names() {
+    local names
+    names=$(joker __complete -- "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null)
+    if [[ -n "$names" ]]; then
+        COMPREPLY=($(compgen -W "$names" -- "${COMP_WORDS[COMP_CWORD]}"))
+    else
+        _joker "$@"
+    fi
+}
+complete -F _joker_names -o nosort -o bashdefault -o default joker
+"#;
+
+const ZSH_HOOK: &str = r#"
+_joker_names() {
+    local names
+    names=$(joker __complete -- "${(@)words[2,CURRENT]}" 2>/dev/null)
+    if [[ -n "$names" ]]; then
+        compadd -- ${(f)names}
+    else
+        _joker "$@"
+    fi
+}
+compdef _joker_names joker
+"#;
+
+const FISH_HOOK: &str = r#"
+complete -c joker -x -n 'test -n "$(joker __complete -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)"' -a '(joker __complete -- (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'
+"#;
+
+/// Writes the completion script of the command for a shell. The scripts of bash,
+/// zsh and fish also complete the names of daemons and containers.
+pub fn generate(shell: Shell, command: &mut Command, out: &mut impl Write) -> io::Result<()> {
+    clap_complete::generate(shell, command, "joker", out);
+
+    let hook = match shell {
+        Shell::Bash => BASH_HOOK,
+        Shell::Zsh => ZSH_HOOK,
+        Shell::Fish => FISH_HOOK,
+        _ => "",
+    };
+    out.write_all(hook.as_bytes())
+}
+
+/// Finds whether the last of the words after `joker`, the one being completed,
+/// is the name of a daemon or of a container.
+pub fn names_at(command: &Command, words: &[String]) -> Option<Names> {
+    let (current, before) = words.split_last()?;
+    let mut command = command.clone();
+    command.build();
+    let mut command = &command;
+
+    let mut positionals = 0;
+    let mut option = None;
+    for word in before {
+        // the value of an option
+        if option.take().is_some() {
+            continue;
+        }
+
+        if let Some(long) = word.strip_prefix("--") {
+            if !long.contains('=') {
+                option = command.get_arguments().find(|arg| arg.get_long() == Some(long)).filter(|arg| takes_value(arg));
+            }
+        } else if let Some(shorts) = word.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            option = shorts.chars().last()
+                .filter(|_| shorts.chars().count() == 1)
+                .and_then(|short| command.get_arguments().find(|arg| arg.get_short() == Some(short)))
+                .filter(|arg| takes_value(arg));
+        } else if let Some(subcommand) = command.find_subcommand(word).filter(|_| positionals == 0) {
+            command = subcommand;
+        } else {
+            positionals += 1;
+        }
+    }
+
+    let arg = match option {
+        Some(arg) => arg,
+        None if current.starts_with('-') => return None,
+        None => {
+            let args = command.get_positionals().filter(|arg| !arg.is_hide_set()).collect::<Vec<_>>();
+            let last = *args.last()?;
+            match args.get(positionals) {
+                Some(arg) => arg,
+                None if last.get_num_args().is_some_and(|range| range.max_values() > 1) => last,
+                None => return None,
+            }
+        }
+    };
+    names_of(arg)
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn names_of(arg: &Arg) -> Option<Names> {
+    let value_names = arg.get_value_names().unwrap_or_default();
+    if arg.get_id() == "DAEMON_NAME" || value_names.iter().any(|name| name == "DAEMON_NAME") {
+        Some(Names::Daemons)
+    } else if arg.get_id() == "CONTAINER_NAME" && arg.get_value_hint() != ValueHint::AnyPath {
+        Some(Names::Containers)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(line: &str) -> Option<Names> {
+        let mut words = line.split(' ').map(str::to_owned).collect::<Vec<_>>();
+        if line.ends_with(' ') {
+            words.pop();
+            words.push(String::new());
+        }
+        names_at(&crate::cli(), &words)
+    }
+
+    #[test]
+    fn completes_containers_after_commands_taking_one() {
+        assert_eq!(names("logs "), Some(Names::Containers));
+        assert_eq!(names("stop w"), Some(Names::Containers));
+        assert_eq!(names("inspect --format json "), Some(Names::Containers));
+    }
+
+    #[test]
+    fn completes_daemons_as_arguments_and_option_values() {
+        assert_eq!(names("checkout "), Some(Names::Daemons));
+        assert_eq!(names("logs --daemon "), Some(Names::Daemons));
+    }
+
+    #[test]
+    fn leaves_other_words_to_the_script() {
+        assert_eq!(names(""), None);
+        assert_eq!(names("logs -"), None);
+        assert_eq!(names("pull "), None);
+        assert_eq!(names("logs web "), None);
+    }
+
+    #[test]
+    fn generates_a_script_for_every_shell() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell, Shell::Elvish] {
+            let mut script = Vec::new();
+            generate(shell, &mut crate::cli(), &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains("checkout"), "{}", shell);
+        }
+    }
+}
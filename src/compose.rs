@@ -0,0 +1,292 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use crate::container::{self, Asset, Dependency, DependencyCondition, HealthcheckOptions, NetworkMode, RunOptions};
+use crate::errors::InvalidOptionError;
+use crate::{interpolation, schema};
+
+pub const DEFAULT_COMPOSE_FILE: &str = "joker-compose.toml";
+
+/// A stack of containers deployed together by `joker up`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComposeFile {
+    /// The name of the stack. Defaults to the name of the directory of the file.
+    pub name: Option<String>,
+    pub services: BTreeMap<String, Service>,
+}
+
+/// A container of a stack and the options it's run with,
+/// named like the options of `joker run`.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Service {
+    /// A binary with a `.joker` config, an OCI bundle directory or a pulled image.
+    /// Paths are relative to the compose file.
+    pub container: String,
+    pub env: Vec<String>,
+    pub env_file: Vec<PathBuf>,
+    pub labels: Vec<String>,
+    pub volumes: Vec<String>,
+    /// Local files shipped with the container, as SOURCE:CONTAINER_PATH.
+    /// Relative sources are relative to the compose file.
+    pub assets: Vec<String>,
+    pub secrets: Vec<String>,
+    pub read_only: bool,
+    pub publish: Vec<String>,
+    pub devices: Vec<String>,
+    pub network: Option<String>,
+    pub network_aliases: Vec<String>,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub uid_maps: Vec<String>,
+    pub gid_maps: Vec<String>,
+    pub restart: Option<String>,
+    pub restart_backoff: Option<String>,
+    pub timeout: Option<String>,
+    pub healthcheck: Option<Healthcheck>,
+    pub runtime: Option<String>,
+    pub depends_on: DependsOn,
+}
+
+/// The services started before a service, either as a list or as a table
+/// with the condition to wait for: `depends_on = ["db"]` or
+/// `depends_on.db = { condition = "healthy" }`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum DependsOn {
+    List(Vec<String>),
+    Conditions(BTreeMap<String, DependsOnCondition>),
+}
+
+impl Default for DependsOn {
+    fn default() -> Self {
+        DependsOn::List(Vec::new())
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DependsOnCondition {
+    pub condition: Option<String>,
+}
+
+impl DependsOn {
+    pub fn services(&self) -> Vec<&str> {
+        match self {
+            DependsOn::List(services) => services.iter().map(String::as_str).collect(),
+            DependsOn::Conditions(services) => services.keys().map(String::as_str).collect(),
+        }
+    }
+
+    pub fn conditions(&self) -> Result<Vec<(&str, DependencyCondition)>, InvalidOptionError> {
+        match self {
+            DependsOn::List(services) => Ok(services.iter()
+                .map(|service| (service.as_str(), DependencyCondition::default()))
+                .collect()),
+            DependsOn::Conditions(services) => services.iter()
+                .map(|(service, condition)| {
+                    let condition = condition.condition.as_deref()
+                        .map(str::parse)
+                        .transpose()?
+                        .unwrap_or_default();
+                    Ok((service.as_str(), condition))
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Healthcheck {
+    pub command: Option<String>,
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub retries: Option<u32>,
+    pub disable: bool,
+}
+
+/// A starter compose file for `joker init` with a single service.
+pub fn starter_compose_file(service: &str, container: &str) -> String {
+    format!(r#"# name = "{service}"
+
+[services.{service}]
+container = "{container}"
+# env = ["RUST_LOG=info"]
+# volumes = ["{service}-data:/data"]
+# publish = ["8080:8080"]
+# restart = "on-failure"
+# depends_on = []
+"#, service = service, container = container)
+}
+
+/// A stack deployed on a daemon, as listed by `joker stack ls`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StackInfo {
+    pub name: String,
+    pub containers: Vec<String>,
+}
+
+/// A compose file read from the disk, with the directory its paths are relative to.
+pub struct Stack {
+    pub name: String,
+    pub directory: PathBuf,
+    pub file: ComposeFile,
+}
+
+impl Stack {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = fs::read_to_string(path)?;
+        let syntax_error = |err: toml::de::Error| format!("Error: bad compose file {}: {}", path.display(), err);
+        toml::from_str::<ComposeFile>(&text).map_err(syntax_error)?;
+
+        // Only the values are expanded, the variables of the environment can't change the file itself
+        let mut values = toml::Value::Table(toml::from_str(&text).map_err(syntax_error)?);
+        interpolation::interpolate_toml(&mut values, &|path| schema::line_of(&text, path))
+            .map_err(|err| format!("Error: {}: {}", path.display(), err))?;
+        if let Some(value) = schema::find_line_break(&values, "") {
+            return Err(format!("Error: {}: {} can't span several lines.", path.display(), value).into());
+        }
+        let file: ComposeFile = values.try_into().map_err(syntax_error)?;
+
+        let directory = path.parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."))
+            .to_owned();
+        let name = match &file.name {
+            Some(name) => name.clone(),
+            None => directory.canonicalize()?
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        if !container::is_valid_name(&name) {
+            return Err(InvalidOptionError { option: "stack name".to_owned(), value: name }.into());
+        }
+        if file.services.is_empty() {
+            return Err(format!("Error: {} has no services.", path.display()).into());
+        }
+        for (service, options) in &file.services {
+            for dependency in options.depends_on.services() {
+                if !file.services.contains_key(dependency) {
+                    return Err(format!("Error: service {} depends on unknown service {}.", service, dependency).into());
+                }
+            }
+        }
+
+        Ok(Stack { name, directory, file })
+    }
+
+    /// Orders the services so that every service comes after the ones it depends on.
+    /// Services which don't depend on each other keep the order of their names.
+    pub fn start_order(&self) -> Result<Vec<&str>, Box<dyn std::error::Error>> {
+        let mut order = Vec::with_capacity(self.file.services.len());
+        let mut started = BTreeSet::new();
+
+        while order.len() < self.file.services.len() {
+            let ready = self.file.services.iter()
+                .map(|(name, service)| (name.as_str(), service))
+                .filter(|(name, _)| !started.contains(name))
+                .filter(|(_, service)| service.depends_on.services().iter().all(|dependency| started.contains(dependency)))
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+
+            if ready.is_empty() {
+                let cycle = self.file.services.keys()
+                    .filter(|name| !started.contains(name.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                return Err(format!("Error: services {} depend on each other.", cycle.join(", ")).into());
+            }
+            started.extend(ready.iter().copied());
+            order.extend(ready);
+        }
+
+        Ok(order)
+    }
+
+    /// The name of the container of a service, unique among the stacks of a daemon.
+    pub fn container_name(&self, service: &str) -> String {
+        format!("{}-{}", self.name, service)
+    }
+
+    /// The path or the image reference of the container of a service.
+    pub fn container_path(&self, service: &Service) -> String {
+        let path = self.directory.join(&service.container);
+        match path.exists() {
+            true => path.to_string_lossy().into_owned(),
+            false => service.container.clone(),
+        }
+    }
+
+    /// Makes the options of `joker run` for a service. The container is named after
+    /// the stack and the service, and on the bridge network it's reachable by the name
+    /// of its service from the other containers.
+    pub fn run_options(&self, name: &str, service: &Service) -> Result<RunOptions, Box<dyn std::error::Error>> {
+        if !container::is_valid_name(name) {
+            return Err(InvalidOptionError { option: "service name".to_owned(), value: name.to_owned() }.into());
+        }
+
+        let mut env = Vec::new();
+        for env_file in &service.env_file {
+            env.extend(container::parse_env_file(&fs::read_to_string(self.directory.join(env_file))?)?);
+        }
+        env.extend(parse_all(&service.env)?);
+
+        let network = service.network.as_deref().map(str::parse::<NetworkMode>).transpose()?;
+        let mut network_aliases = service.network_aliases.clone();
+        if network.unwrap_or_default() == NetworkMode::Bridge && !network_aliases.iter().any(|alias| alias == name) {
+            network_aliases.push(name.to_owned());
+        }
+
+        let healthcheck = service.healthcheck.clone().unwrap_or_default();
+        let duration = |option: &str, value: &Option<String>| {
+            value.as_deref().map(|value| container::parse_duration(option, value)).transpose()
+        };
+
+        Ok(RunOptions {
+            cap_add: service.cap_add.clone(),
+            cap_drop: service.cap_drop.clone(),
+            uid_maps: parse_all(&service.uid_maps)?,
+            gid_maps: parse_all(&service.gid_maps)?,
+            mounts: parse_all(&service.volumes)?,
+            assets: parse_all::<Asset>(&service.assets)?
+                .into_iter()
+                .map(|asset| asset.resolve(&self.directory))
+                .collect(),
+            secrets: parse_all(&service.secrets)?,
+            read_only: service.read_only,
+            publish: parse_all(&service.publish)?,
+            devices: parse_all(&service.devices)?,
+            network,
+            network_aliases,
+            env,
+            labels: parse_all(&service.labels)?,
+            restart: service.restart.as_deref().map(str::parse).transpose()?,
+            restart_backoff: duration("restart_backoff", &service.restart_backoff)?,
+            timeout: duration("timeout", &service.timeout)?,
+            healthcheck: HealthcheckOptions {
+                command: healthcheck.command,
+                interval: duration("healthcheck.interval", &healthcheck.interval)?,
+                timeout: duration("healthcheck.timeout", &healthcheck.timeout)?,
+                retries: healthcheck.retries,
+                disable: healthcheck.disable,
+            },
+            runtime: service.runtime.as_deref().map(str::parse).transpose()?,
+            name: Some(self.container_name(name)),
+            stack: Some(self.name.clone()),
+            depends_on: service.depends_on.conditions()?
+                .into_iter()
+                .map(|(service, condition)| Dependency { container: self.container_name(service), condition })
+                .collect(),
+        })
+    }
+}
+
+fn parse_all<T: FromStr<Err = InvalidOptionError>>(values: &[String]) -> Result<Vec<T>, InvalidOptionError> {
+    values.iter().map(|value| value.parse()).collect()
+}
@@ -0,0 +1,1741 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use crate::errors::{ConfigParseError, InvalidOptionError};
+
+/// Capabilities kept by a container when its config doesn't say otherwise.
+pub const DEFAULT_CAPABILITIES: &[&str] = &[
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_SYS_CHROOT",
+];
+
+/// All the capabilities known to the Linux kernel.
+pub const KNOWN_CAPABILITIES: &[&str] = &[
+    "CAP_AUDIT_CONTROL",
+    "CAP_AUDIT_READ",
+    "CAP_AUDIT_WRITE",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_KILL",
+    "CAP_LEASE",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_MAC_ADMIN",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MKNOD",
+    "CAP_NET_ADMIN",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_RAW",
+    "CAP_PERFMON",
+    "CAP_SETFCAP",
+    "CAP_SETGID",
+    "CAP_SETPCAP",
+    "CAP_SETUID",
+    "CAP_SYSLOG",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_NICE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_WAKE_ALARM",
+];
+
+pub const CONTAINER_NAME_KEY: &str = "Container name";
+pub const IPC_NAMESPACE_KEY: &str = "IPC namespace name";
+pub const USER_NAMESPACE_KEY: &str = "User namespace name";
+pub const MOUNT_NAMESPACE_KEY: &str = "Mount namespace name";
+pub const PID_NAMESPACE_KEY: &str = "PID namespace name";
+pub const NETWORK_NAMESPACE_KEY: &str = "Network namespace name";
+pub const TIME_NAMESPACE_KEY: &str = "Time namespace name";
+pub const UTS_NAMESPACE_KEY: &str = "UTS namespace name";
+pub const CGROUP_KEY: &str = "Cgroup name";
+pub const STACK_KEY: &str = "Stack";
+pub const DEPENDS_ON_KEY: &str = "Depends on";
+pub const CAPABILITIES_KEY: &str = "Capabilities";
+pub const CAPABILITIES_ADD_KEY: &str = "Capabilities add";
+pub const CAPABILITIES_DROP_KEY: &str = "Capabilities drop";
+pub const NO_NEW_PRIVILEGES_KEY: &str = "No new privileges";
+pub const UID_MAPPINGS_KEY: &str = "UID mappings";
+pub const GID_MAPPINGS_KEY: &str = "GID mappings";
+pub const MOUNT_KEY: &str = "Mount";
+pub const ASSET_KEY: &str = "Asset";
+pub const SECRET_KEY: &str = "Secret";
+pub const READ_ONLY_ROOTFS_KEY: &str = "Read-only rootfs";
+pub const PUBLISH_KEY: &str = "Publish";
+pub const DEVICE_KEY: &str = "Device";
+pub const NETWORK_KEY: &str = "Network";
+pub const NETWORK_ALIASES_KEY: &str = "Network aliases";
+pub const ENV_KEY: &str = "Env";
+pub const LABEL_KEY: &str = "Label";
+pub const RESTART_POLICY_KEY: &str = "Restart policy";
+pub const RESTART_MAX_RETRIES_KEY: &str = "Restart max retries";
+pub const RESTART_BACKOFF_KEY: &str = "Restart backoff";
+pub const TIMEOUT_KEY: &str = "Timeout";
+
+/// The status of a container killed by the daemon at the end of its timeout.
+pub const TIMED_OUT_STATUS: &str = "timed-out";
+
+/// The exit code of an attached `run` whose container timed out, as of `timeout(1)`.
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+pub const HEALTHCHECK_COMMAND_KEY: &str = "Healthcheck command";
+pub const HEALTHCHECK_INTERVAL_KEY: &str = "Healthcheck interval";
+pub const HEALTHCHECK_TIMEOUT_KEY: &str = "Healthcheck timeout";
+pub const HEALTHCHECK_RETRIES_KEY: &str = "Healthcheck retries";
+pub const RESTART_ON_UNHEALTHY_KEY: &str = "Restart on unhealthy";
+pub const RUNTIME_KEY: &str = "Runtime";
+pub const ARGS_KEY: &str = "Args";
+pub const MEMORY_LIMIT_KEY: &str = "Memory limit";
+pub const CPU_LIMIT_KEY: &str = "CPU limit";
+pub const PIDS_LIMIT_KEY: &str = "PIDs limit";
+pub const PRE_RUN_HOOK_KEY: &str = "Pre-run hook";
+pub const POST_RUN_HOOK_KEY: &str = "Post-run hook";
+pub const DAEMON_PRE_RUN_HOOK_KEY: &str = "Daemon pre-run hook";
+pub const DAEMON_POST_RUN_HOOK_KEY: &str = "Daemon post-run hook";
+pub const ON_EXIT_HOOK_KEY: &str = "On-exit hook";
+pub const HOOK_FAILURE_KEY: &str = "Hook failure";
+
+/// The directory the secrets of a container are mounted into by default.
+pub const SECRETS_DIRECTORY: &str = "/run/secrets";
+
+/// The first bytes of every WebAssembly module.
+pub const WASM_MAGIC: &[u8] = b"\0asm";
+
+pub const DEFAULT_HEALTHCHECK_INTERVAL: Duration = Duration::from_secs(30);
+pub const DEFAULT_HEALTHCHECK_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_HEALTHCHECK_RETRIES: u32 = 3;
+
+/// The delay before the first restart, when the config doesn't set one.
+/// The daemon doubles it after every restart in a row.
+pub const DEFAULT_RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A container config, as written in a `.joker` file.
+/// Every line has a form of `Key: value`. The order of the entries
+/// is preserved, so the config sent to a daemon stays close to the
+/// one written by the user.
+#[derive(Clone, Default)]
+pub struct ContainerConfig {
+    entries: Vec<(String, String)>,
+}
+
+/// Options of the `run` command which override a container config.
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub uid_maps: Vec<IdMapping>,
+    pub gid_maps: Vec<IdMapping>,
+    pub mounts: Vec<Mount>,
+    /// Local files packaged with the container, on top of the assets of its config.
+    pub assets: Vec<Asset>,
+    pub secrets: Vec<SecretMount>,
+    pub read_only: bool,
+    pub publish: Vec<PortMapping>,
+    pub devices: Vec<Device>,
+    pub network: Option<NetworkMode>,
+    pub network_aliases: Vec<String>,
+    pub env: Vec<EnvVar>,
+    pub labels: Vec<Label>,
+    pub restart: Option<RestartPolicy>,
+    pub restart_backoff: Option<Duration>,
+    /// The time after which the daemon kills the container.
+    pub timeout: Option<Duration>,
+    pub healthcheck: HealthcheckOptions,
+    pub runtime: Option<Runtime>,
+    /// The name of the container and of the stack it belongs to, given by `joker up`.
+    pub name: Option<String>,
+    pub stack: Option<String>,
+    /// The containers of the stack which are started first.
+    pub depends_on: Vec<Dependency>,
+}
+
+/// Options of the `run` command which override the health check of a container.
+#[derive(Clone, Default)]
+pub struct HealthcheckOptions {
+    pub command: Option<String>,
+    pub interval: Option<Duration>,
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    pub disable: bool,
+}
+
+/// What the daemon does when a container process exits, written as
+/// `no`, `always` or `on-failure[:MAX_RETRIES]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    OnFailure { max_retries: Option<u32> },
+    Always,
+}
+
+impl FromStr for RestartPolicy {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: RESTART_POLICY_KEY.to_owned(), value: s.to_owned() };
+
+        match s.trim().split_once(':') {
+            None if s.trim() == "no" => Ok(RestartPolicy::No),
+            None if s.trim() == "always" => Ok(RestartPolicy::Always),
+            None if s.trim() == "on-failure" => Ok(RestartPolicy::OnFailure { max_retries: None }),
+            Some(("on-failure", retries)) => {
+                let max_retries = retries.parse().map_err(|_| error())?;
+                Ok(RestartPolicy::OnFailure { max_retries: Some(max_retries) })
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+impl Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestartPolicy::No => write!(f, "no"),
+            RestartPolicy::OnFailure { .. } => write!(f, "on-failure"),
+            RestartPolicy::Always => write!(f, "always"),
+        }
+    }
+}
+
+/// The state of a container as reported by the daemon.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ContainerInfo {
+    pub name: String,
+    pub status: String,
+    pub pid: Option<u32>,
+    pub exit_code: Option<i32>,
+    pub restart_policy: String,
+    pub restart_count: u32,
+    /// `starting`, `healthy` or `unhealthy`, if the container has a health check.
+    pub health: Option<String>,
+    pub failing_streak: u32,
+    /// `native` or `wasm`.
+    pub runtime: String,
+    pub labels: BTreeMap<String, String>,
+}
+
+/// A sample of the resource usage of a container, taken by the daemon.
+/// The byte counters are totals since the container started.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct StatsSample {
+    /// A Unix timestamp, in seconds.
+    pub timestamp: u64,
+    /// The CPU time used since the previous sample, in percents of a CPU.
+    pub cpu_percent: f64,
+    pub memory: u64,
+    /// The memory limit of the container, 0 if it has none.
+    pub memory_limit: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// An environment variable of a container process, written as `KEY=VALUE`.
+/// On the command line a bare `KEY` takes the value from the local environment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvVar {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for EnvVar {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "environment variable".to_owned(), value: s.to_owned() };
+
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key.trim(), value.to_owned()),
+            None => (s.trim(), std::env::var(s.trim()).map_err(|_| error())?),
+        };
+
+        // Every entry of a config takes a single line
+        if !is_valid_env_key(key) || value.contains('\n') {
+            return Err(error());
+        }
+
+        Ok(EnvVar { key: key.to_owned(), value })
+    }
+}
+
+impl Display for EnvVar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// A label of a container the daemon keeps for querying, written as `KEY=VALUE`,
+/// e.g. `team=payments`. Keys may also have dots and slashes, e.g. `app.kubernetes.io/name`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Label {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "label".to_owned(), value: s.to_owned() };
+
+        let (key, value) = s.split_once('=').ok_or_else(error)?;
+        let key = key.trim();
+        if !is_valid_label_key(key) || value.contains('\n') {
+            return Err(error());
+        }
+
+        Ok(Label { key: key.to_owned(), value: value.to_owned() })
+    }
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// A condition of `joker ps --filter`: `label=KEY=VALUE`, `label=KEY` for any value
+/// of the label, or `status=STATUS`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ContainerFilter {
+    Label { key: String, value: Option<String> },
+    Status(String),
+}
+
+impl ContainerFilter {
+    pub fn matches(&self, container: &ContainerInfo) -> bool {
+        match self {
+            ContainerFilter::Label { key, value: None } => container.labels.contains_key(key),
+            ContainerFilter::Label { key, value: Some(value) } => container.labels.get(key) == Some(value),
+            ContainerFilter::Status(status) => container.status.eq_ignore_ascii_case(status),
+        }
+    }
+}
+
+impl FromStr for ContainerFilter {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "--filter".to_owned(), value: s.to_owned() };
+
+        match s.trim().split_once('=').ok_or_else(error)? {
+            ("label", label) => match label.split_once('=') {
+                Some((key, value)) if is_valid_label_key(key) => {
+                    Ok(ContainerFilter::Label { key: key.to_owned(), value: Some(value.to_owned()) })
+                }
+                None if is_valid_label_key(label) => Ok(ContainerFilter::Label { key: label.to_owned(), value: None }),
+                _ => Err(error()),
+            },
+            ("status", status) if !status.is_empty() => Ok(ContainerFilter::Status(status.to_owned())),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// Parses an env file: a `KEY=VALUE` pair per line,
+/// empty lines and lines starting with `#` are skipped.
+pub fn parse_env_file(text: &str) -> Result<Vec<EnvVar>, ConfigParseError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(index, line)| {
+            if !line.contains('=') {
+                return Err(ConfigParseError { line: index + 1, message: "expected a `KEY=VALUE` pair".to_owned() });
+            }
+            line.trim().parse().map_err(|err: InvalidOptionError| ConfigParseError {
+                line: index + 1,
+                message: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The network a container is connected to.
+/// On the `bridge` network of a daemon every container gets its own ip
+/// address and can reach the other containers by name through the
+/// resolver embedded into the daemon.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NetworkMode {
+    #[default]
+    Bridge,
+    Host,
+    None,
+}
+
+impl FromStr for NetworkMode {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "bridge" => Ok(NetworkMode::Bridge),
+            "host" => Ok(NetworkMode::Host),
+            "none" => Ok(NetworkMode::None),
+            _ => Err(InvalidOptionError { option: NETWORK_KEY.to_owned(), value: s.to_owned() }),
+        }
+    }
+}
+
+impl Display for NetworkMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkMode::Bridge => write!(f, "bridge"),
+            NetworkMode::Host => write!(f, "host"),
+            NetworkMode::None => write!(f, "none"),
+        }
+    }
+}
+
+/// How the daemon executes a container: as a `native` process in its
+/// own namespaces, or as a `wasm` module under wasmtime, which needs no
+/// namespaces and so works on daemons where they aren't available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Runtime {
+    #[default]
+    Native,
+    Wasm,
+}
+
+impl FromStr for Runtime {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "native" => Ok(Runtime::Native),
+            "wasm" => Ok(Runtime::Wasm),
+            _ => Err(InvalidOptionError { option: RUNTIME_KEY.to_owned(), value: s.to_owned() }),
+        }
+    }
+}
+
+impl Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Runtime::Native => write!(f, "native"),
+            Runtime::Wasm => write!(f, "wasm"),
+        }
+    }
+}
+
+/// What happens when a hook command fails: `fatal` stops the container
+/// from being run (or, for an on-exit hook, marks it as failed), `warn` only reports it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HookFailure {
+    #[default]
+    Fatal,
+    Warn,
+}
+
+impl FromStr for HookFailure {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "fatal" => Ok(HookFailure::Fatal),
+            "warn" => Ok(HookFailure::Warn),
+            _ => Err(InvalidOptionError { option: HOOK_FAILURE_KEY.to_owned(), value: s.to_owned() }),
+        }
+    }
+}
+
+impl Display for HookFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HookFailure::Fatal => write!(f, "fatal"),
+            HookFailure::Warn => write!(f, "warn"),
+        }
+    }
+}
+
+/// The hooks of a container which the CLI runs on the local machine:
+/// before the container is sent to a daemon and once the daemon has it.
+/// The daemon-side hooks stay in the config, as the daemon runs them.
+#[derive(Clone, Debug, Default)]
+pub struct LocalHooks {
+    pub pre_run: Option<String>,
+    pub post_run: Option<String>,
+    pub failure: HookFailure,
+}
+
+/// A container which is started before another container of a stack, written as
+/// `NAME[:started|healthy]`. The daemon starts the dependent container as soon as
+/// the dependency is `started`, or only once its health check passes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dependency {
+    pub container: String,
+    pub condition: DependencyCondition,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DependencyCondition {
+    #[default]
+    Started,
+    Healthy,
+}
+
+impl FromStr for DependencyCondition {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "started" => Ok(DependencyCondition::Started),
+            "healthy" => Ok(DependencyCondition::Healthy),
+            _ => Err(InvalidOptionError { option: "dependency condition".to_owned(), value: s.to_owned() }),
+        }
+    }
+}
+
+impl Display for DependencyCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DependencyCondition::Started => write!(f, "started"),
+            DependencyCondition::Healthy => write!(f, "healthy"),
+        }
+    }
+}
+
+impl FromStr for Dependency {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: DEPENDS_ON_KEY.to_owned(), value: s.to_owned() };
+
+        let (container, condition) = match s.trim().split_once(':') {
+            Some((container, condition)) => (container, condition.parse().map_err(|_| error())?),
+            None => (s.trim(), DependencyCondition::default()),
+        };
+        if !is_valid_name(container) {
+            return Err(error());
+        }
+
+        Ok(Dependency { container: container.to_owned(), condition })
+    }
+}
+
+impl Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.container, self.condition)
+    }
+}
+
+/// A range of ids mapped into a user namespace, written as
+/// `CONTAINER_ID:HOST_ID:COUNT`. The daemon passes it to
+/// `newuidmap`/`newgidmap`, so that an unprivileged daemon can give
+/// a container its own root user.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdMapping {
+    pub container_id: u32,
+    pub host_id: u32,
+    pub count: u32,
+}
+
+impl FromStr for IdMapping {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "id mapping".to_owned(), value: s.to_owned() };
+
+        let parts = s.trim().split(':')
+            .map(|part| part.parse::<u32>().map_err(|_| error()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match parts[..] {
+            [container_id, host_id, count] if count > 0 => {
+                container_id.checked_add(count - 1).ok_or_else(error)?;
+                host_id.checked_add(count - 1).ok_or_else(error)?;
+                Ok(IdMapping { container_id, host_id, count })
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+impl Display for IdMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.container_id, self.host_id, self.count)
+    }
+}
+
+/// A directory mounted into a container, written as
+/// `SOURCE:CONTAINER_PATH[:ro|rw]`. The source is either an absolute path
+/// on the daemon host or a name of a volume managed by the daemon.
+/// Mounts are read-write by default.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mount {
+    pub source: MountSource,
+    pub target: PathBuf,
+    pub read_only: bool,
+}
+
+/// The source of a mount.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MountSource {
+    Host(PathBuf),
+    Volume(String),
+}
+
+impl FromStr for Mount {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "mount".to_owned(), value: s.to_owned() };
+
+        let parts = s.trim().split(':').collect::<Vec<_>>();
+        let (source, target, read_only) = match parts[..] {
+            [source, target] => (source, target, false),
+            [source, target, "ro"] => (source, target, true),
+            [source, target, "rw"] => (source, target, false),
+            _ => return Err(error()),
+        };
+
+        // A host path belongs to the daemon host, so it can't be resolved here
+        let source = if Path::new(source).is_absolute() {
+            MountSource::Host(PathBuf::from(source))
+        } else if is_valid_name(source) {
+            MountSource::Volume(source.to_owned())
+        } else {
+            return Err(error());
+        };
+
+        if !Path::new(target).is_absolute() {
+            return Err(error());
+        }
+
+        Ok(Mount { source, target: PathBuf::from(target), read_only })
+    }
+}
+
+impl Display for Mount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            MountSource::Host(path) => write!(f, "{}", path.display())?,
+            MountSource::Volume(name) => write!(f, "{}", name)?,
+        }
+        write!(
+            f,
+            ":{}:{}",
+            self.target.display(),
+            if self.read_only { "ro" } else { "rw" },
+        )
+    }
+}
+
+/// A local file or directory which is packaged and sent with a container, and placed
+/// at a path of its filesystem, written as `SOURCE:TARGET`, e.g. `./static:/srv/static`.
+/// Unlike a mount, the source is on this machine, so the daemon host doesn't need it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Asset {
+    pub source: PathBuf,
+    pub target: PathBuf,
+}
+
+impl Asset {
+    /// Makes a relative source relative to a directory instead, e.g. to the one of its config.
+    pub fn resolve(self, base: &Path) -> Self {
+        match self.source.is_absolute() {
+            true => self,
+            false => Asset { source: base.join(&self.source), target: self.target },
+        }
+    }
+}
+
+impl FromStr for Asset {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "asset".to_owned(), value: s.to_owned() };
+
+        let (source, target) = s.trim().rsplit_once(':').ok_or_else(error)?;
+        let target = Path::new(target);
+        // The target can't escape the filesystem of the container or replace it as a whole
+        let escapes = target.components().any(|component| matches!(component, Component::ParentDir));
+        if source.is_empty() || !target.is_absolute() || target.parent().is_none() || escapes {
+            return Err(error());
+        }
+
+        Ok(Asset { source: PathBuf::from(source), target: target.to_owned() })
+    }
+}
+
+impl Display for Asset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.source.display(), self.target.display())
+    }
+}
+
+/// A secret of the daemon made available inside a container, written as
+/// `NAME[:TARGET]`. An absolute target is the path of a file holding the secret,
+/// `/run/secrets/NAME` by default, and any other target is the name of an
+/// environment variable. The daemon keeps secrets encrypted and decrypts them
+/// only into the memory of a running container, so they never end up in a config.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretMount {
+    pub name: String,
+    pub target: SecretTarget,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretTarget {
+    File(PathBuf),
+    Env(String),
+}
+
+impl FromStr for SecretMount {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: SECRET_KEY.to_owned(), value: s.to_owned() };
+
+        let (name, target) = match s.trim().split_once(':') {
+            Some((name, target)) if Path::new(target).is_absolute() => (name, SecretTarget::File(PathBuf::from(target))),
+            Some((name, target)) if is_valid_env_key(target) => (name, SecretTarget::Env(target.to_owned())),
+            Some(_) => return Err(error()),
+            None => (s.trim(), SecretTarget::File(Path::new(SECRETS_DIRECTORY).join(s.trim()))),
+        };
+        if !is_valid_name(name) {
+            return Err(error());
+        }
+
+        Ok(SecretMount { name: name.to_owned(), target })
+    }
+}
+
+impl Display for SecretMount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.target {
+            SecretTarget::File(path) => write!(f, "{}:{}", self.name, path.display()),
+            SecretTarget::Env(key) => write!(f, "{}:{}", self.name, key),
+        }
+    }
+}
+
+/// A device of the daemon host made available inside a container, written as
+/// `HOST_PATH[:CONTAINER_PATH][:PERMISSIONS]`. The container path defaults to
+/// the host one and the permissions, a combination of `r`ead, `w`rite and
+/// `m`knod, default to `rwm`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    pub permissions: String,
+}
+
+impl FromStr for Device {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "device".to_owned(), value: s.to_owned() };
+
+        let is_permissions = |part: &str| {
+            !part.is_empty() && part.chars().all(|c| "rwm".contains(c))
+        };
+
+        let parts = s.trim().split(':').collect::<Vec<_>>();
+        let (source, target, permissions) = match parts[..] {
+            [source] => (source, source, "rwm"),
+            [source, permissions] if is_permissions(permissions) => (source, source, permissions),
+            [source, target] => (source, target, "rwm"),
+            [source, target, permissions] if is_permissions(permissions) => (source, target, permissions),
+            _ => return Err(error()),
+        };
+
+        if !source.starts_with("/dev/") || !Path::new(target).is_absolute() {
+            return Err(error());
+        }
+
+        // Keep the permissions in a canonical order without duplicates
+        let permissions = "rwm".chars().filter(|&c| permissions.contains(c)).collect();
+
+        Ok(Device { source: PathBuf::from(source), target: PathBuf::from(target), permissions })
+    }
+}
+
+impl Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source.display(), self.target.display(), self.permissions)
+    }
+}
+
+/// A port of the daemon host forwarded to a port inside the network
+/// namespace of a container, written as
+/// `[HOST_IP:]HOST_PORT:CONTAINER_PORT[/tcp|udp]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PortMapping {
+    pub host_ip: Option<IpAddr>,
+    pub host_port: u16,
+    pub container_port: u16,
+    pub protocol: Protocol,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl FromStr for PortMapping {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "port mapping".to_owned(), value: s.to_owned() };
+
+        let (ports, protocol) = match s.trim().rsplit_once('/') {
+            Some((ports, "tcp")) => (ports, Protocol::Tcp),
+            Some((ports, "udp")) => (ports, Protocol::Udp),
+            Some(_) => return Err(error()),
+            None => (s.trim(), Protocol::Tcp),
+        };
+
+        // The host ip may be an IPv6 address, so the ports are taken from the end
+        let (rest, container_port) = ports.rsplit_once(':').ok_or_else(error)?;
+        let (host_ip, host_port) = match rest.rsplit_once(':') {
+            Some((ip, port)) => {
+                let ip = ip.trim_start_matches('[').trim_end_matches(']');
+                (Some(ip.parse().map_err(|_| error())?), port)
+            }
+            None => (None, rest),
+        };
+
+        let parse_port = |port: &str| port.parse::<u16>().ok().filter(|&port| port != 0).ok_or_else(error);
+
+        Ok(PortMapping {
+            host_ip,
+            host_port: parse_port(host_port)?,
+            container_port: parse_port(container_port)?,
+            protocol,
+        })
+    }
+}
+
+impl Display for PortMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.host_ip {
+            Some(IpAddr::V6(ip)) => write!(f, "[{}]:", ip)?,
+            Some(ip) => write!(f, "{}:", ip)?,
+            None => {}
+        }
+        let protocol = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        };
+        write!(f, "{}:{}/{}", self.host_port, self.container_port, protocol)
+    }
+}
+
+impl IdMapping {
+    fn overlaps(&self, other: &IdMapping) -> bool {
+        let ranges_overlap = |a: u32, b: u32| {
+            (a as u64) < b as u64 + other.count as u64 && (b as u64) < a as u64 + self.count as u64
+        };
+        ranges_overlap(self.container_id, other.container_id)
+            || ranges_overlap(self.host_id, other.host_id)
+    }
+}
+
+impl ContainerConfig {
+    /// Parses a config from the contents of a `.joker` file.
+    pub fn parse(text: &str) -> Result<Self, ConfigParseError> {
+        let mut entries = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (key, value) = line.split_once(':').ok_or_else(|| ConfigParseError {
+                line: index + 1,
+                message: "expected a `Key: value` pair".to_owned(),
+            })?;
+
+            entries.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+
+        Ok(ContainerConfig { entries })
+    }
+
+    /// Returns the last value of the key, if any.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns all the values of a key which may be repeated.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries.iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Appends an entry, keeping the other entries with the same key.
+    pub fn push(&mut self, key: &str, value: String) {
+        self.entries.push((key.to_owned(), value));
+    }
+
+    /// Returns a comma-separated value of the key as a list.
+    pub fn get_list(&self, key: &str) -> Option<Vec<String>> {
+        self.get(key).map(|value| {
+            value.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+    }
+
+    /// Sets the value of the key. Replaces the first entry with such key
+    /// and removes any other ones, or appends a new entry.
+    pub fn set(&mut self, key: &str, value: String) {
+        match self.entries.iter().position(|(k, _)| k == key) {
+            Some(position) => {
+                self.entries[position].1 = value;
+                let mut seen = false;
+                self.entries.retain(|(k, _)| {
+                    if k != key {
+                        return true;
+                    }
+                    let first = !seen;
+                    seen = true;
+                    first
+                });
+            }
+            None => self.entries.push((key.to_owned(), value)),
+        }
+    }
+
+    /// Removes all the entries with the key.
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| k != key);
+    }
+
+    /// Applies the options of the `run` command and resolves the values
+    /// which the daemon expects to be explicit.
+    pub fn apply(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        self.apply_capabilities(options)?;
+        self.apply_id_mappings(UID_MAPPINGS_KEY, &options.uid_maps)?;
+        self.apply_id_mappings(GID_MAPPINGS_KEY, &options.gid_maps)?;
+        // After the mappings, so that the ones of the command line are checked too
+        self.apply_runtime(options)?;
+        self.apply_env(&options.env)?;
+        self.apply_args()?;
+        self.apply_host_options(options)?;
+
+        if options.read_only {
+            self.set(READ_ONLY_ROOTFS_KEY, "true".to_owned());
+        }
+        if let Some(value) = self.get(READ_ONLY_ROOTFS_KEY) {
+            parse_bool(READ_ONLY_ROOTFS_KEY, value)?;
+        }
+
+        if self.get(NO_NEW_PRIVILEGES_KEY).is_none() {
+            self.set(NO_NEW_PRIVILEGES_KEY, "true".to_owned());
+        }
+        parse_bool(NO_NEW_PRIVILEGES_KEY, self.get(NO_NEW_PRIVILEGES_KEY).unwrap_or_default())?;
+
+        Ok(())
+    }
+
+    /// Applies only the options resolved by the daemon host itself: mounts,
+    /// devices, networking and supervision of the container process.
+    /// The rest of a container spec may come from elsewhere, e.g. from
+    /// the `config.json` of an OCI bundle.
+    pub fn apply_host_options(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        self.apply_names(options)?;
+        self.apply_labels(&options.labels)?;
+        self.apply_mounts(&options.mounts)?;
+        // The assets are only checked here, they are packaged by `take_assets`
+        self.get_all(ASSET_KEY).try_for_each(|asset| asset.parse::<Asset>().map(drop))?;
+        self.apply_secrets(&options.secrets)?;
+        self.apply_port_mappings(&options.publish)?;
+        self.apply_devices(&options.devices)?;
+        self.apply_network(options)?;
+        self.apply_restart_policy(options)?;
+        self.apply_timeout(options)?;
+        self.apply_healthcheck(&options.healthcheck)?;
+        self.apply_hooks()?;
+        self.apply_limits()?;
+
+        Ok(())
+    }
+
+    /// Checks the arguments of a binary, a JSON array of strings.
+    fn apply_args(&mut self) -> Result<(), InvalidOptionError> {
+        if let Some(args) = self.get(ARGS_KEY) {
+            serde_json::from_str::<Vec<String>>(args)
+                .map_err(|_| InvalidOptionError { option: ARGS_KEY.to_owned(), value: args.to_owned() })?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks how the failures of the hooks are handled.
+    fn apply_hooks(&mut self) -> Result<(), InvalidOptionError> {
+        if let Some(failure) = self.get(HOOK_FAILURE_KEY) {
+            failure.parse::<HookFailure>()?;
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the resource limits the daemon puts on the cgroup of a container:
+    /// the memory in bytes, the number of CPUs, which may be fractional, and the number of processes.
+    fn apply_limits(&mut self) -> Result<(), InvalidOptionError> {
+        if let Some(memory) = self.get(MEMORY_LIMIT_KEY) {
+            let bytes = parse_size(MEMORY_LIMIT_KEY, memory)?;
+            self.set(MEMORY_LIMIT_KEY, bytes.to_string());
+        }
+        if let Some(cpus) = self.get(CPU_LIMIT_KEY) {
+            let error = || InvalidOptionError { option: CPU_LIMIT_KEY.to_owned(), value: cpus.to_owned() };
+            let cpus = cpus.parse::<f64>().map_err(|_| error())?;
+            if !cpus.is_finite() || cpus <= 0.0 {
+                return Err(error());
+            }
+        }
+        if let Some(pids) = self.get(PIDS_LIMIT_KEY) {
+            if !pids.parse::<u64>().is_ok_and(|pids| pids > 0) {
+                return Err(InvalidOptionError { option: PIDS_LIMIT_KEY.to_owned(), value: pids.to_owned() });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Computes the capability set of a container: the default one
+    /// (or the one from the config), then additions and drops from the
+    /// config, then additions and drops from the command line.
+    fn apply_capabilities(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        let mut capabilities = match self.get_list(CAPABILITIES_KEY) {
+            Some(list) => normalize_capabilities(CAPABILITIES_KEY, &list)?,
+            None => DEFAULT_CAPABILITIES.iter().map(|&cap| cap.to_owned()).collect(),
+        };
+
+        let config_add = self.get_list(CAPABILITIES_ADD_KEY).unwrap_or_default();
+        let config_drop = self.get_list(CAPABILITIES_DROP_KEY).unwrap_or_default();
+
+        let sources = [
+            (CAPABILITIES_ADD_KEY, &config_add, CAPABILITIES_DROP_KEY, &config_drop),
+            ("--cap-add", &options.cap_add, "--cap-drop", &options.cap_drop),
+        ];
+
+        for (add_option, add, drop_option, drop) in sources {
+            for cap in normalize_capabilities(drop_option, drop)? {
+                if cap == "ALL" {
+                    capabilities.clear();
+                } else {
+                    capabilities.retain(|c| *c != cap);
+                }
+            }
+            for cap in normalize_capabilities(add_option, add)? {
+                if cap == "ALL" {
+                    capabilities = KNOWN_CAPABILITIES.iter().map(|&c| c.to_owned()).collect();
+                } else if !capabilities.contains(&cap) {
+                    capabilities.push(cap);
+                }
+            }
+        }
+
+        capabilities.sort();
+
+        self.remove(CAPABILITIES_ADD_KEY);
+        self.remove(CAPABILITIES_DROP_KEY);
+        self.set(CAPABILITIES_KEY, capabilities.join(", "));
+
+        Ok(())
+    }
+
+    /// Adds the mounts from the command line to the ones of the config.
+    /// A mount from the command line replaces a mount of the config with
+    /// the same container path.
+    fn apply_mounts(&mut self, overrides: &[Mount]) -> Result<(), InvalidOptionError> {
+        let mut mounts = self.get_all(MOUNT_KEY)
+            .map(|mount| mount.parse())
+            .collect::<Result<Vec<Mount>, _>>()?;
+
+        for mount in overrides {
+            mounts.retain(|m| m.target != mount.target);
+            mounts.push(mount.clone());
+        }
+
+        self.remove(MOUNT_KEY);
+        for mount in mounts {
+            self.push(MOUNT_KEY, mount.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the secrets from the command line to the ones of the config.
+    /// A secret given for the same file or variable replaces the one of the config.
+    fn apply_secrets(&mut self, overrides: &[SecretMount]) -> Result<(), InvalidOptionError> {
+        let mut secrets = self.get_all(SECRET_KEY)
+            .map(|secret| secret.parse())
+            .collect::<Result<Vec<SecretMount>, _>>()?;
+
+        for secret in overrides {
+            secrets.retain(|s| s.target != secret.target);
+            secrets.push(secret.clone());
+        }
+
+        self.remove(SECRET_KEY);
+        for secret in secrets {
+            self.push(SECRET_KEY, secret.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the published ports from the command line to the ones of the config.
+    /// A host port can be published only once for each protocol.
+    fn apply_port_mappings(&mut self, overrides: &[PortMapping]) -> Result<(), InvalidOptionError> {
+        let mut mappings = self.get_all(PUBLISH_KEY)
+            .map(|mapping| mapping.parse())
+            .collect::<Result<Vec<PortMapping>, _>>()?;
+        mappings.extend_from_slice(overrides);
+
+        for (index, mapping) in mappings.iter().enumerate() {
+            let taken = mappings[..index].iter().any(|other| {
+                other.host_port == mapping.host_port && other.protocol == mapping.protocol
+            });
+            if taken {
+                return Err(InvalidOptionError { option: PUBLISH_KEY.to_owned(), value: mapping.to_string() });
+            }
+        }
+
+        self.remove(PUBLISH_KEY);
+        for mapping in mappings {
+            self.push(PUBLISH_KEY, mapping.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the devices from the command line to the ones of the config.
+    /// A device from the command line replaces a device of the config with
+    /// the same container path.
+    fn apply_devices(&mut self, overrides: &[Device]) -> Result<(), InvalidOptionError> {
+        let mut devices = self.get_all(DEVICE_KEY)
+            .map(|device| device.parse())
+            .collect::<Result<Vec<Device>, _>>()?;
+
+        for device in overrides {
+            devices.retain(|d| d.target != device.target);
+            devices.push(device.clone());
+        }
+
+        self.remove(DEVICE_KEY);
+        for device in devices {
+            self.push(DEVICE_KEY, device.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Names a container, the stack it's deployed with and the containers of the stack it waits for.
+    fn apply_names(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        for (key, value) in [(CONTAINER_NAME_KEY, &options.name), (STACK_KEY, &options.stack)] {
+            if let Some(value) = value {
+                if !is_valid_name(value) {
+                    return Err(InvalidOptionError { option: key.to_owned(), value: value.clone() });
+                }
+                self.set(key, value.clone());
+            }
+        }
+
+        if !options.depends_on.is_empty() {
+            self.remove(DEPENDS_ON_KEY);
+            for dependency in &options.depends_on {
+                self.push(DEPENDS_ON_KEY, dependency.to_string());
+            }
+        }
+        for dependency in self.get_all(DEPENDS_ON_KEY) {
+            dependency.parse::<Dependency>()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the daemon runs a health check of the container.
+    pub fn has_healthcheck(&self) -> bool {
+        self.get(HEALTHCHECK_COMMAND_KEY).is_some_and(|command| !command.is_empty())
+    }
+
+    /// Resolves the runtime of a container. A WebAssembly module has no
+    /// user namespace, so id mappings can't be given to it.
+    fn apply_runtime(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        let runtime = match options.runtime {
+            Some(runtime) => runtime,
+            None => self.runtime()?,
+        };
+        self.set(RUNTIME_KEY, runtime.to_string());
+
+        if runtime == Runtime::Wasm {
+            for key in [UID_MAPPINGS_KEY, GID_MAPPINGS_KEY] {
+                if let Some(mapping) = self.get(key).filter(|mapping| !mapping.is_empty()) {
+                    return Err(InvalidOptionError { option: key.to_owned(), value: mapping.to_owned() });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the hooks run by the CLI out of the config, so that the daemon only
+    /// receives its own ones, and checks how the failures of all the hooks are handled.
+    pub fn take_local_hooks(&mut self) -> Result<LocalHooks, InvalidOptionError> {
+        let failure = self.get(HOOK_FAILURE_KEY).map(str::parse).transpose()?.unwrap_or_default();
+        let hooks = LocalHooks {
+            pre_run: self.get(PRE_RUN_HOOK_KEY).filter(|command| !command.is_empty()).map(str::to_owned),
+            post_run: self.get(POST_RUN_HOOK_KEY).filter(|command| !command.is_empty()).map(str::to_owned),
+            failure,
+        };
+        self.remove(PRE_RUN_HOOK_KEY);
+        self.remove(POST_RUN_HOOK_KEY);
+
+        Ok(hooks)
+    }
+
+    /// Takes the assets out of the config, as they are sent packaged rather than
+    /// as entries. Relative sources are relative to the directory given. An asset
+    /// of the options replaces an asset of the config with the same target.
+    pub fn take_assets(&mut self, base: &Path, overrides: &[Asset]) -> Result<Vec<Asset>, InvalidOptionError> {
+        let mut assets = self.get_all(ASSET_KEY)
+            .map(|asset| asset.parse::<Asset>().map(|asset| asset.resolve(base)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for asset in overrides {
+            assets.retain(|a| a.target != asset.target);
+            assets.push(asset.clone());
+        }
+        self.remove(ASSET_KEY);
+
+        Ok(assets)
+    }
+
+    /// The runtime of a container, `native` unless the config says otherwise.
+    pub fn runtime(&self) -> Result<Runtime, InvalidOptionError> {
+        Ok(self.get(RUNTIME_KEY).map(str::parse).transpose()?.unwrap_or_default())
+    }
+
+    /// Resolves the network of a container and its names on the bridge network.
+    fn apply_network(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        let network = match options.network {
+            Some(network) => network,
+            None => self.get(NETWORK_KEY).map(str::parse).transpose()?.unwrap_or_default(),
+        };
+        self.set(NETWORK_KEY, network.to_string());
+
+        let mut aliases = self.get_list(NETWORK_ALIASES_KEY).unwrap_or_default();
+        for alias in &options.network_aliases {
+            if !aliases.contains(alias) {
+                aliases.push(alias.clone());
+            }
+        }
+        if let Some(alias) = aliases.iter().find(|alias| !is_valid_name(alias)) {
+            return Err(InvalidOptionError { option: NETWORK_ALIASES_KEY.to_owned(), value: alias.clone() });
+        }
+
+        if network != NetworkMode::Bridge {
+            // Only the bridge network has addresses to forward ports and resolve names to
+            if let Some(mapping) = self.get(PUBLISH_KEY) {
+                return Err(InvalidOptionError { option: PUBLISH_KEY.to_owned(), value: mapping.to_owned() });
+            }
+            if let Some(alias) = aliases.first() {
+                return Err(InvalidOptionError { option: NETWORK_ALIASES_KEY.to_owned(), value: alias.clone() });
+            }
+        }
+
+        if aliases.is_empty() {
+            self.remove(NETWORK_ALIASES_KEY);
+        } else {
+            self.set(NETWORK_ALIASES_KEY, aliases.join(", "));
+        }
+
+        Ok(())
+    }
+
+    /// Merges the environment of the config with the one from the command line.
+    /// A later variable replaces an earlier one with the same key.
+    fn apply_env(&mut self, overrides: &[EnvVar]) -> Result<(), InvalidOptionError> {
+        let mut env: Vec<EnvVar> = Vec::new();
+
+        // Unlike the command line, a config can't take values from the local environment
+        let config_env = self.get_all(ENV_KEY)
+            .map(|var| match var.contains('=') {
+                true => var.parse(),
+                false => Err(InvalidOptionError { option: ENV_KEY.to_owned(), value: var.to_owned() }),
+            })
+            .collect::<Result<Vec<EnvVar>, _>>()?;
+
+        for var in config_env.into_iter().chain(overrides.iter().cloned()) {
+            env.retain(|v| v.key != var.key);
+            env.push(var);
+        }
+
+        self.remove(ENV_KEY);
+        for var in env {
+            self.push(ENV_KEY, var.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the labels from the command line to the ones of the config.
+    /// A label from the command line replaces a label of the config with the same key.
+    fn apply_labels(&mut self, overrides: &[Label]) -> Result<(), InvalidOptionError> {
+        let mut labels = self.get_all(LABEL_KEY)
+            .map(|label| label.parse())
+            .collect::<Result<Vec<Label>, _>>()?;
+
+        for label in overrides {
+            labels.retain(|l| l.key != label.key);
+            labels.push(label.clone());
+        }
+
+        self.remove(LABEL_KEY);
+        for label in labels {
+            self.push(LABEL_KEY, label.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Resolves the restart policy of a container. The maximal number of
+    /// retries may be given either as a part of the policy or as a separate key.
+    fn apply_restart_policy(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        let policy = match options.restart {
+            Some(policy) => policy,
+            None => self.get(RESTART_POLICY_KEY).map(str::parse).transpose()?.unwrap_or_default(),
+        };
+        self.set(RESTART_POLICY_KEY, policy.to_string());
+
+        let max_retries = match policy {
+            RestartPolicy::OnFailure { max_retries: Some(max_retries) } => Some(max_retries),
+            RestartPolicy::OnFailure { max_retries: None } => self.get(RESTART_MAX_RETRIES_KEY)
+                .map(|retries| retries.parse().map_err(|_| InvalidOptionError {
+                    option: RESTART_MAX_RETRIES_KEY.to_owned(),
+                    value: retries.to_owned(),
+                }))
+                .transpose()?,
+            _ => None,
+        };
+
+        match max_retries {
+            Some(max_retries) => self.set(RESTART_MAX_RETRIES_KEY, max_retries.to_string()),
+            None => self.remove(RESTART_MAX_RETRIES_KEY),
+        }
+
+        if policy == RestartPolicy::No {
+            self.remove(RESTART_BACKOFF_KEY);
+            return Ok(());
+        }
+
+        let backoff = match options.restart_backoff {
+            Some(backoff) => backoff,
+            None => self.get(RESTART_BACKOFF_KEY)
+                .map(|backoff| parse_duration(RESTART_BACKOFF_KEY, backoff))
+                .transpose()?
+                .unwrap_or(DEFAULT_RESTART_BACKOFF),
+        };
+        self.set(RESTART_BACKOFF_KEY, format_duration(backoff));
+
+        Ok(())
+    }
+
+    /// Resolves the execution timeout of a container. Once it's over, the daemon
+    /// kills the container and reports it with the `timed-out` status.
+    fn apply_timeout(&mut self, options: &RunOptions) -> Result<(), InvalidOptionError> {
+        let timeout = match options.timeout {
+            Some(timeout) => Some(timeout),
+            None => self.get(TIMEOUT_KEY).map(|timeout| parse_duration(TIMEOUT_KEY, timeout)).transpose()?,
+        };
+
+        match timeout {
+            Some(timeout) if timeout.is_zero() => {
+                Err(InvalidOptionError { option: TIMEOUT_KEY.to_owned(), value: format_duration(timeout) })
+            }
+            Some(timeout) => {
+                self.set(TIMEOUT_KEY, format_duration(timeout));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves the health check of a container. The daemon runs the command
+    /// inside the container every interval and considers the container
+    /// unhealthy after the given number of failures in a row.
+    fn apply_healthcheck(&mut self, options: &HealthcheckOptions) -> Result<(), InvalidOptionError> {
+        let keys = [
+            HEALTHCHECK_COMMAND_KEY,
+            HEALTHCHECK_INTERVAL_KEY,
+            HEALTHCHECK_TIMEOUT_KEY,
+            HEALTHCHECK_RETRIES_KEY,
+            RESTART_ON_UNHEALTHY_KEY,
+        ];
+
+        if let Some(command) = &options.command {
+            self.set(HEALTHCHECK_COMMAND_KEY, command.clone());
+        }
+
+        if options.disable || !self.has_healthcheck() {
+            for key in keys {
+                self.remove(key);
+            }
+            return Ok(());
+        }
+
+        for (key, value, default) in [
+            (HEALTHCHECK_INTERVAL_KEY, options.interval, DEFAULT_HEALTHCHECK_INTERVAL),
+            (HEALTHCHECK_TIMEOUT_KEY, options.timeout, DEFAULT_HEALTHCHECK_TIMEOUT),
+        ] {
+            let duration = match value {
+                Some(duration) => duration,
+                None => self.get(key)
+                    .map(|duration| parse_duration(key, duration))
+                    .transpose()?
+                    .unwrap_or(default),
+            };
+            if duration.is_zero() {
+                return Err(InvalidOptionError { option: key.to_owned(), value: format_duration(duration) });
+            }
+            self.set(key, format_duration(duration));
+        }
+
+        let retries = match options.retries {
+            Some(retries) => retries,
+            None => self.get(HEALTHCHECK_RETRIES_KEY)
+                .map(|retries| retries.parse().map_err(|_| InvalidOptionError {
+                    option: HEALTHCHECK_RETRIES_KEY.to_owned(),
+                    value: retries.to_owned(),
+                }))
+                .transpose()?
+                .unwrap_or(DEFAULT_HEALTHCHECK_RETRIES),
+        };
+        self.set(HEALTHCHECK_RETRIES_KEY, retries.to_string());
+
+        let restart = self.get(RESTART_ON_UNHEALTHY_KEY)
+            .map(|restart| parse_bool(RESTART_ON_UNHEALTHY_KEY, restart))
+            .transpose()?
+            .unwrap_or(false);
+        self.set(RESTART_ON_UNHEALTHY_KEY, restart.to_string());
+
+        Ok(())
+    }
+
+    /// Replaces the id mappings of the config with the ones from the command
+    /// line, if any, and checks that the ranges don't overlap.
+    fn apply_id_mappings(&mut self, key: &str, overrides: &[IdMapping]) -> Result<(), InvalidOptionError> {
+        let mappings = if overrides.is_empty() {
+            self.get_list(key)
+                .unwrap_or_default()
+                .iter()
+                .map(|mapping| mapping.parse())
+                .collect::<Result<Vec<IdMapping>, _>>()?
+        } else {
+            overrides.to_vec()
+        };
+
+        for (index, mapping) in mappings.iter().enumerate() {
+            if mappings[..index].iter().any(|other| mapping.overlaps(other)) {
+                return Err(InvalidOptionError { option: key.to_owned(), value: mapping.to_string() });
+            }
+        }
+
+        if !mappings.is_empty() {
+            let mappings = mappings.iter().map(IdMapping::to_string).collect::<Vec<_>>();
+            self.set(key, mappings.join(", "));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for ContainerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (key, value) in &self.entries {
+            if value.is_empty() {
+                writeln!(f, "{}:", key)?;
+            } else {
+                writeln!(f, "{}: {}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Brings capability names to the `CAP_*` form and checks that they exist.
+/// Both `net_admin` and `CAP_NET_ADMIN` are accepted, `ALL` is kept as is.
+pub(crate) fn normalize_capabilities(option: &str, capabilities: &[String]) -> Result<Vec<String>, InvalidOptionError> {
+    capabilities.iter()
+        .map(|cap| {
+            let upper = cap.trim().to_uppercase();
+            if upper == "ALL" {
+                return Ok(upper);
+            }
+            let name = if upper.starts_with("CAP_") { upper } else { format!("CAP_{}", upper) };
+            if KNOWN_CAPABILITIES.contains(&name.as_str()) {
+                Ok(name)
+            } else {
+                Err(InvalidOptionError { option: option.to_owned(), value: cap.clone() })
+            }
+        })
+        .collect()
+}
+
+/// Checks the name of an environment variable, as a shell would accept it.
+fn is_valid_env_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks a label key, which may have a `/`-separated prefix like `com.example/tier`.
+fn is_valid_label_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | '/'))
+}
+
+/// Checks a name of an object managed by the daemon, such as a volume.
+/// Names consist of letters, digits, `_`, `.` and `-` and can't start with `.` or `-`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['.', '-'])
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// Parses durations like `500ms`, `30s`, `5m`, `1h` or `1m30s`.
+/// A bare number is a number of seconds.
+pub fn parse_duration(option: &str, value: &str) -> Result<Duration, InvalidOptionError> {
+    let error = || InvalidOptionError { option: option.to_owned(), value: value.to_owned() };
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(error());
+    }
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+        let number = rest[..digits].parse::<u64>().map_err(|_| error())?;
+        rest = &rest[digits..];
+
+        let unit = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let part = match &rest[..unit] {
+            "ms" => Duration::from_millis(number),
+            "s" => Duration::from_secs(number),
+            "m" => Duration::from_secs(number.checked_mul(60).ok_or_else(error)?),
+            "h" => Duration::from_secs(number.checked_mul(60 * 60).ok_or_else(error)?),
+            "d" => Duration::from_secs(number.checked_mul(60 * 60 * 24).ok_or_else(error)?),
+            _ => return Err(error()),
+        };
+        total = total.checked_add(part).ok_or_else(error)?;
+        rest = &rest[unit..];
+    }
+
+    Ok(total)
+}
+
+/// Parses sizes like `512M`, `1G` or `1536Ki`. A bare number is a number of bytes
+/// and the units are powers of 1024.
+pub fn parse_size(option: &str, value: &str) -> Result<u64, InvalidOptionError> {
+    let error = || InvalidOptionError { option: option.to_owned(), value: value.to_owned() };
+
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let number = value[..digits].parse::<u64>().map_err(|_| error())?;
+    let shift = match value[digits..].trim_end_matches(['i', 'B']) {
+        "" => 0,
+        "K" | "k" => 10,
+        "M" | "m" => 20,
+        "G" | "g" => 30,
+        "T" | "t" => 40,
+        _ => return Err(error()),
+    };
+
+    number.checked_mul(1 << shift).filter(|bytes| *bytes > 0).ok_or_else(error)
+}
+
+/// Formats a duration in the form accepted by `parse_duration`.
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() != 0 {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{}s", duration.as_secs())
+    }
+}
+
+/// Parses `true`/`false` values of the config.
+pub fn parse_bool(option: &str, value: &str) -> Result<bool, InvalidOptionError> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(InvalidOptionError { option: option.to_owned(), value: value.to_owned() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(config: &str, options: RunOptions) -> Result<ContainerConfig, InvalidOptionError> {
+        let mut config = ContainerConfig::parse(config).unwrap();
+        config.apply(&options).map(|_| config)
+    }
+
+    #[test]
+    fn a_wasm_container_takes_no_id_mappings() {
+        let wasm = || RunOptions { runtime: Some(Runtime::Wasm), ..Default::default() };
+        let mapping = || vec!["0:100000:65536".parse::<IdMapping>().unwrap()];
+
+        let err = apply("", RunOptions { uid_maps: mapping(), ..wasm() }).err().unwrap();
+        assert_eq!(err.option, UID_MAPPINGS_KEY);
+        let err = apply("", RunOptions { gid_maps: mapping(), ..wasm() }).err().unwrap();
+        assert_eq!(err.option, GID_MAPPINGS_KEY);
+        let err = apply("UID mappings: 0:100000:65536", wasm()).err().unwrap();
+        assert_eq!(err.option, UID_MAPPINGS_KEY);
+
+        let config = apply("", wasm()).unwrap();
+        assert_eq!(config.get(RUNTIME_KEY), Some("wasm"));
+        assert!(apply("", RunOptions { uid_maps: mapping(), ..Default::default() }).is_ok());
+    }
+
+    /// Checks that the accepted forms are written back as given and that the others are rejected.
+    fn check<T: FromStr<Err = InvalidOptionError> + Display>(accepted: &[(&str, &str)], rejected: &[&str]) {
+        for (text, written) in accepted {
+            match text.parse::<T>() {
+                Ok(value) => assert_eq!(value.to_string(), *written, "{}", text),
+                Err(err) => panic!("{} was rejected: {}", text, err),
+            }
+        }
+        for text in rejected {
+            assert!(text.parse::<T>().is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn parses_mounts() {
+        check::<Mount>(&[
+            ("/srv/data:/data", "/srv/data:/data:rw"),
+            ("cache:/cache:ro", "cache:/cache:ro"),
+            ("cache:/cache:rw", "cache:/cache:rw"),
+        ], &["/srv/data", "cache:data", "../data:/data", "cache:/cache:rx", "a:/b:ro:x", ""]);
+    }
+
+    #[test]
+    fn parses_port_mappings() {
+        check::<PortMapping>(&[
+            ("8080:80", "8080:80/tcp"),
+            ("53:53/udp", "53:53/udp"),
+            ("127.0.0.1:8080:80", "127.0.0.1:8080:80/tcp"),
+            ("[::1]:8080:80/tcp", "[::1]:8080:80/tcp"),
+        ], &["80", "0:80", "8080:0", "65536:80", "8080:80/sctp", "localhost:8080:80", "a:b"]);
+    }
+
+    #[test]
+    fn parses_devices() {
+        check::<Device>(&[
+            ("/dev/fuse", "/dev/fuse:/dev/fuse:rwm"),
+            ("/dev/snd:r", "/dev/snd:/dev/snd:r"),
+            ("/dev/sda:/dev/xvda", "/dev/sda:/dev/xvda:rwm"),
+            ("/dev/sda:/dev/xvda:mwrw", "/dev/sda:/dev/xvda:rwm"),
+        ], &["/etc/passwd", "/dev/sda:xvda", "/dev/sda:/dev/xvda:rx", "/dev/a:/dev/b:r:w"]);
+    }
+
+    #[test]
+    fn parses_environment_variables() {
+        check::<EnvVar>(&[
+            ("PORT=8080", "PORT=8080"),
+            ("_X=a=b", "_X=a=b"),
+            ("EMPTY=", "EMPTY="),
+        ], &["1PORT=80", "MY-VAR=1", "=1", "X=a\nb", "JOKER_SURELY_UNSET_VARIABLE"]);
+    }
+
+    #[test]
+    fn parses_id_mappings() {
+        check::<IdMapping>(&[
+            ("0:100000:65536", "0:100000:65536"),
+            ("4294967295:0:1", "4294967295:0:1"),
+        ], &["0:100000", "0:100000:0", "-1:0:1", "0:4294967295:2", "4294967295:0:2", "0:1:2:3"]);
+    }
+
+    #[test]
+    fn parses_secret_mounts() {
+        check::<SecretMount>(&[
+            ("token", "token:/run/secrets/token"),
+            ("token:/etc/token", "token:/etc/token"),
+            ("token:API_TOKEN", "token:API_TOKEN"),
+        ], &["../token", "token:api-token", ":/etc/token", ""]);
+    }
+
+    #[test]
+    fn parses_restart_policies() {
+        check::<RestartPolicy>(&[
+            ("no", "no"),
+            ("always", "always"),
+            ("on-failure", "on-failure"),
+            ("on-failure:3", "on-failure"),
+        ], &["sometimes", "always:3", "on-failure:", "on-failure:-1", "on-failure:4294967296"]);
+        assert_eq!("on-failure:3".parse::<RestartPolicy>().ok(), Some(RestartPolicy::OnFailure { max_retries: Some(3) }));
+    }
+
+    #[test]
+    fn parses_durations() {
+        for (text, duration) in [
+            ("30", Duration::from_secs(30)),
+            ("500ms", Duration::from_millis(500)),
+            ("1m30s", Duration::from_secs(90)),
+            ("2h", Duration::from_secs(7200)),
+            ("1d", Duration::from_secs(86400)),
+        ] {
+            assert_eq!(parse_duration("timeout", text).ok(), Some(duration), "{}", text);
+        }
+        // the overflowing ones used to panic
+        for text in ["", "5x", "m", "1.5s", "18446744073709551615m", "18446744073709551615h", "18446744073709551615s1s"] {
+            assert!(parse_duration("timeout", text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        for (text, size) in [("512", 512), ("1K", 1024), ("512M", 512 << 20), ("1Gi", 1 << 30), ("2TB", 2 << 40)] {
+            assert_eq!(parse_size("memory", text).ok(), Some(size), "{}", text);
+        }
+        for text in ["", "0", "1P", "M", "-1M", "17179869184G", "18446744073709551616"] {
+            assert!(parse_size("memory", text).is_err(), "{} was accepted", text);
+        }
+    }
+}
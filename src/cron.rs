@@ -0,0 +1,178 @@
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::errors::InvalidOptionError;
+
+const MONTHS: &[&str] = &["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+const WEEKDAYS: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// A field of a cron expression: its name for the errors, its range and
+/// the names its values may be written with, starting from the lowest value.
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+}
+
+const FIELDS: [Field; 5] = [
+    Field { name: "minute", min: 0, max: 59, names: &[] },
+    Field { name: "hour", min: 0, max: 23, names: &[] },
+    Field { name: "day of the month", min: 1, max: 31, names: &[] },
+    Field { name: "month", min: 1, max: 12, names: MONTHS },
+    // 7 is Sunday too
+    Field { name: "day of the week", min: 0, max: 7, names: WEEKDAYS },
+];
+
+/// When the daemon runs a scheduled container, written as the five fields of cron:
+/// minutes, hours, days of the month, months and days of the week, e.g. `0 3 * * *`.
+/// A field is `*`, a value, a range like `1-5` or a list like `1,15`, each with
+/// an optional step like `*/15`. Months and days of the week may be written as names
+/// like `JAN` or `MON`, and Sunday as 0 or 7. `@hourly`, `@daily`, `@weekly`, `@monthly`
+/// and `@yearly` are accepted too. The expression is only checked here, the daemon works
+/// out when it fires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronExpression {
+    text: String,
+}
+
+impl FromStr for CronExpression {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "cron expression".to_owned(), value: s.to_owned() };
+
+        let text = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *".to_owned(),
+            "@monthly" => "0 0 1 * *".to_owned(),
+            "@weekly" => "0 0 * * 0".to_owned(),
+            "@daily" | "@midnight" => "0 0 * * *".to_owned(),
+            "@hourly" => "0 * * * *".to_owned(),
+            text => text.split_whitespace().collect::<Vec<_>>().join(" "),
+        };
+
+        let parts = text.split(' ').collect::<Vec<_>>();
+        if parts.len() != FIELDS.len() {
+            return Err(error());
+        }
+
+        for (part, field) in parts.iter().zip(&FIELDS) {
+            check_field(part, field).ok_or_else(|| InvalidOptionError {
+                option: format!("{} of the cron expression", field.name),
+                value: part.to_string(),
+            })?;
+        }
+
+        Ok(CronExpression { text })
+    }
+}
+
+impl Display for CronExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// A container the daemon runs on a cron expression, as listed by `joker schedule list`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ScheduleInfo {
+    pub id: String,
+    pub expression: String,
+    pub container: String,
+    /// Unix timestamps, in seconds.
+    pub last_run: Option<u64>,
+    pub next_run: Option<u64>,
+    /// The status of the container after its last run, e.g. `exited` or `timed-out`.
+    pub last_status: Option<String>,
+    pub last_exit_code: Option<i32>,
+}
+
+/// Checks the values, ranges and steps of a field. Returns `None` if it's malformed.
+fn check_field(text: &str, field: &Field) -> Option<()> {
+    for item in text.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().ok().filter(|&step| step > 0)?)),
+            None => (item, None),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (field.min, field.max),
+            Some((start, end)) => (parse_value(start, field)?, parse_value(end, field)?),
+            // `5/10` means from 5 to the end of the range
+            None if step.is_some() => (parse_value(range, field)?, field.max),
+            None => {
+                let value = parse_value(range, field)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return None;
+        }
+    }
+
+    Some(())
+}
+
+fn parse_value(text: &str, field: &Field) -> Option<u32> {
+    let value = match field.names.iter().position(|name| name.eq_ignore_ascii_case(text)) {
+        Some(position) => field.min + position as u32,
+        None => text.parse().ok()?,
+    };
+
+    (field.min..=field.max).contains(&value).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<String, String> {
+        text.parse::<CronExpression>().map(|expression| expression.to_string()).map_err(|err| err.option)
+    }
+
+    #[test]
+    fn parses_values_ranges_lists_and_steps() {
+        assert_eq!(parse("0 3 * * *"), Ok("0 3 * * *".to_owned()));
+        assert_eq!(parse(" 0  9-17 1,15 * 1-5 "), Ok("0 9-17 1,15 * 1-5".to_owned()));
+        assert_eq!(parse("*/15 0-12/3 */2 * *"), Ok("*/15 0-12/3 */2 * *".to_owned()));
+        assert_eq!(parse("5/10 * * * *"), Ok("5/10 * * * *".to_owned()));
+    }
+
+    #[test]
+    fn parses_names_of_months_and_days() {
+        assert_eq!(parse("0 0 1 JAN,jul MON-FRI"), Ok("0 0 1 JAN,jul MON-FRI".to_owned()));
+        assert_eq!(parse("0 0 * * sun"), Ok("0 0 * * sun".to_owned()));
+    }
+
+    #[test]
+    fn accepts_sunday_as_7() {
+        assert!(parse("0 0 * * 7").is_ok());
+        assert!(parse("0 0 * * 5-7").is_ok());
+        assert_eq!(parse("0 0 * * 8"), Err("day of the week of the cron expression".to_owned()));
+    }
+
+    #[test]
+    fn expands_the_macros() {
+        assert_eq!(parse("@yearly"), Ok("0 0 1 1 *".to_owned()));
+        assert_eq!(parse("@annually"), Ok("0 0 1 1 *".to_owned()));
+        assert_eq!(parse("@monthly"), Ok("0 0 1 * *".to_owned()));
+        assert_eq!(parse("@weekly"), Ok("0 0 * * 0".to_owned()));
+        assert_eq!(parse("@daily"), Ok("0 0 * * *".to_owned()));
+        assert_eq!(parse("@midnight"), Ok("0 0 * * *".to_owned()));
+        assert_eq!(parse("@hourly"), Ok("0 * * * *".to_owned()));
+        assert_eq!(parse("@often"), Err("cron expression".to_owned()));
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        assert_eq!(parse("* * * *"), Err("cron expression".to_owned()));
+        assert_eq!(parse("60 * * * *"), Err("minute of the cron expression".to_owned()));
+        assert_eq!(parse("* 5-1 * * *"), Err("hour of the cron expression".to_owned()));
+        assert_eq!(parse("* * 0 * *"), Err("day of the month of the cron expression".to_owned()));
+        assert_eq!(parse("* * * FOO *"), Err("month of the cron expression".to_owned()));
+        assert_eq!(parse("*/0 * * * *"), Err("minute of the cron expression".to_owned()));
+        assert_eq!(parse("1,,2 * * * *"), Err("minute of the cron expression".to_owned()));
+    }
+}
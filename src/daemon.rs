@@ -1,42 +1,379 @@
-use std::collections::{HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+use std::fmt;
+use std::fmt::Display;
 use std::fs::File;
-use std::net::SocketAddr;
 use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use serde::{Serialize, Deserialize};
+use crate::errors::{InvalidOptionError, JokerError};
 
+#[derive(Clone, Copy)]
 pub enum Requests {
     Run = 0,
     Trace = 1,
     Logs = 2,
     Send = 3,
+    VolumeCreate = 4,
+    VolumeList = 5,
+    VolumeRemove = 6,
+    Inspect = 7,
+    List = 8,
+    RunBundle = 9,
+    Images = 10,
+    RemoveImage = 11,
+    RunImage = 12,
+    Commit = 13,
+    Checkpoint = 14,
+    Restore = 15,
+    Export = 16,
+    TrustAdd = 17,
+    TrustList = 18,
+    TrustRemove = 19,
+    InspectSbom = 20,
+    StackDown = 21,
+    StackList = 22,
+    SecretCreate = 23,
+    SecretList = 24,
+    SecretRemove = 25,
+    Remove = 26,
+    Stop = 27,
+    Ping = 28,
+    Signal = 29,
+    ScheduleAdd = 30,
+    ScheduleList = 31,
+    ScheduleRemove = 32,
+    Stats = 33,
+    Restart = 34,
+    LogsFrom = 35,
 }
 
+/// The file name of the CLI config, in the working directory or in the config directory.
 pub const PATH: &str = "config.cfg";
 
+/// The environment variable with the path of the CLI config, overridden by `--config`.
+pub const CONFIG_VAR: &str = "JOKER_CONFIG";
+
+/// The environment variable which overrides the current daemon of the config
+/// for a single command, with the name of a daemon or an `ip:port` address.
+pub const DAEMON_VAR: &str = "JOKER_DAEMON";
+
+/// A document sent along with a container for the daemon to check it,
+/// such as a signature or the manifest of an image.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Attachment {
+    pub media_type: String,
+    pub data: String,
+}
+
+/// The address of a daemon: an ip address or a hostname, and a port. A hostname
+/// is kept as it is and resolved on every connection, so a daemon behind dynamic
+/// DNS is found at its new address. It's written as `host:port` in the config.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct DaemonAddress {
+    host: String,
+    port: u16,
+}
+
+impl DaemonAddress {
+    pub fn new(host: &str, port: u16) -> Result<Self, InvalidOptionError> {
+        let host = host.trim();
+        let literal = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+        let is_hostname = !host.is_empty()
+            && host.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '.' | '_'));
+
+        match literal.parse::<IpAddr>() {
+            Ok(ip) => Ok(DaemonAddress { host: ip.to_string(), port }),
+            Err(_) if is_hostname => Ok(DaemonAddress { host: host.to_lowercase(), port }),
+            Err(_) => Err(InvalidOptionError { option: "host".to_owned(), value: host.to_owned() }),
+        }
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl From<SocketAddr> for DaemonAddress {
+    fn from(socket_address: SocketAddr) -> Self {
+        DaemonAddress { host: socket_address.ip().to_string(), port: socket_address.port() }
+    }
+}
+
+impl FromStr for DaemonAddress {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "address".to_owned(), value: s.to_owned() };
+        if let Ok(socket_address) = s.trim().parse::<SocketAddr>() {
+            return Ok(socket_address.into());
+        }
+
+        let (host, port) = s.trim().rsplit_once(':').ok_or_else(error)?;
+        DaemonAddress::new(host, port.parse().map_err(|_| error())?).map_err(|_| error())
+    }
+}
+
+impl TryFrom<String> for DaemonAddress {
+    type Error = InvalidOptionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<DaemonAddress> for String {
+    fn from(address: DaemonAddress) -> Self {
+        address.to_string()
+    }
+}
+
+impl Display for DaemonAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.host.contains(':') {
+            true => write!(f, "[{}]:{}", self.host, self.port),
+            false => write!(f, "{}:{}", self.host, self.port),
+        }
+    }
+}
+
+impl ToSocketAddrs for DaemonAddress {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        (self.host.as_str(), self.port).to_socket_addrs()
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Daemon {
     pub name: String,
-    pub socket_address: SocketAddr,
+    pub socket_address: DaemonAddress,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub current_daemon: Daemon,
-    pub daemons: HashMap<String, SocketAddr>,
+    pub daemons: HashMap<String, DaemonAddress>,
+    /// The labels of the daemons by their names, e.g. `env=prod`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, BTreeMap<String, String>>,
+    /// Named groups of daemons, by the names of the daemons.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Config {
+    /// Finds a daemon by its name, the current one included.
+    pub fn find_daemon(&self, name: &str) -> Option<Daemon> {
+        if self.current_daemon.name == name {
+            return Some(self.current_daemon.clone());
+        }
+
+        self.daemons.get(name).map(|socket_address| Daemon { name: name.to_owned(), socket_address: socket_address.clone() })
+    }
+
+    /// Returns every daemon of the config, the current one included, sorted by name.
+    pub fn all_daemons(&self) -> Vec<Daemon> {
+        let mut daemons = self.daemons.iter()
+            .filter(|(name, _)| **name != self.current_daemon.name)
+            .map(|(name, socket_address)| Daemon { name: name.clone(), socket_address: socket_address.clone() })
+            .chain(std::iter::once(self.current_daemon.clone()))
+            .collect::<Vec<_>>();
+        daemons.sort_by(|a, b| a.name.cmp(&b.name));
+        daemons
+    }
+
+    /// Returns the daemons of a group.
+    pub fn group(&self, group: &str) -> Result<Vec<Daemon>, JokerError> {
+        let names = self.groups.get(group)
+            .ok_or_else(|| JokerError::Other(format!("Error: no such group {}.", group).into()))?;
+
+        names.iter()
+            .map(|name| self.find_daemon(name).ok_or_else(|| JokerError::UnknownDaemon(name.clone())))
+            .collect()
+    }
+
+    /// Returns the labels of a daemon.
+    pub fn labels(&self, name: &str) -> BTreeMap<String, String> {
+        self.labels.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns the names of the groups a daemon belongs to.
+    pub fn groups_of(&self, name: &str) -> Vec<String> {
+        self.groups.iter()
+            .filter(|(_, names)| names.contains(name))
+            .map(|(group, _)| group.clone())
+            .collect()
+    }
 }
 
-pub fn get_config() -> Result<Config, io::Error> {
-    let config_file = File::open(PATH)?;
+/// Parses a change of the labels of a daemon: `KEY=VALUE` sets a label and `KEY-` removes it.
+pub fn parse_label_change(change: &str) -> Result<(String, Option<String>), InvalidOptionError> {
+    let error = || InvalidOptionError { option: "LABEL".to_owned(), value: change.to_owned() };
+    let (key, value) = match change.split_once('=') {
+        Some((key, value)) => (key, Some(value.to_owned())),
+        None => (change.strip_suffix('-').ok_or_else(error)?, None),
+    };
 
-    let config: Config = serde_json::from_reader(config_file)?;
+    let valid_key = !key.is_empty()
+        && key.chars().all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_' | '.' | '/'));
+    match valid_key {
+        true => Ok((key.to_owned(), value)),
+        false => Err(error()),
+    }
+}
+
+/// The state of a daemon, its answer to a ping.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub containers_running: u32,
+    /// Seconds since the daemon started.
+    pub uptime: u64,
+    /// The memory of the host, in bytes.
+    pub memory_total: u64,
+    pub memory_available: u64,
+    /// The number of CPUs of the host and its load average over a minute.
+    pub cpus: u32,
+    pub load_average: f64,
+}
 
-    Ok(config)
+/// The CLI config and the daemon a command uses, given by `--config` and `--daemon`.
+/// Those which aren't given come from `JOKER_CONFIG` and `JOKER_DAEMON`, so the default
+/// selection is the one of the environment.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    pub config: Option<PathBuf>,
+    pub daemon: Option<String>,
 }
 
-pub fn write_config(config: &Config) -> Result<(), io::Error> {
-    let config_file = File::create(PATH)?;
+impl Selection {
+    /// Returns the path of the CLI config: the selected one or the one of `JOKER_CONFIG`
+    /// if set, `config.cfg` of the working directory if it exists, `joker/config.cfg`
+    /// of the XDG config directory otherwise.
+    pub fn config_path(&self) -> PathBuf {
+        let selected = self.config.iter().cloned()
+            .chain(env::var_os(CONFIG_VAR).map(PathBuf::from))
+            .find(|path| !path.as_os_str().is_empty());
+        if let Some(path) = selected {
+            return path;
+        }
+        if Path::new(PATH).exists() {
+            return PathBuf::from(PATH);
+        }
+
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+        match config_home {
+            Some(config_home) => config_home.join("joker").join(PATH),
+            None => PathBuf::from(PATH),
+        }
+    }
+
+    pub fn get_config(&self) -> Result<Config, JokerError> {
+        let path = self.config_path();
+        let config_file = File::open(&path).map_err(|err| config_error(&path, err))?;
+
+        let config: Config = serde_json::from_reader(config_file).map_err(|err| config_error(&path, err))?;
+
+        Ok(config)
+    }
+
+    pub fn write_config(&self, config: &Config) -> Result<(), JokerError> {
+        let path = self.config_path();
+        if let Some(directory) = path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+            std::fs::create_dir_all(directory).map_err(|err| config_error(directory, err))?;
+        }
+        let config_file = File::create(&path).map_err(|err| config_error(&path, err))?;
+
+        serde_json::to_writer(config_file, config).map_err(|err| config_error(&path, err))?;
+
+        Ok(())
+    }
+
+    /// Finds a daemon of the config by its name.
+    pub fn find_daemon(&self, name: &str) -> Result<Daemon, JokerError> {
+        self.get_config()?.find_daemon(name).ok_or_else(|| JokerError::UnknownDaemon(name.to_owned()))
+    }
+
+    /// Adds a daemon to the config, replacing the address of a daemon with the same name.
+    pub fn add_daemon(&self, name: &str, socket_address: DaemonAddress) -> Result<(), JokerError> {
+        let mut config = self.get_config()?;
+        config.daemons.insert(name.to_owned(), socket_address);
+        self.write_config(&config)
+    }
 
-    serde_json::to_writer(config_file, config)?;
+    /// Makes a daemon of the config the current one, keeping the previous one among the daemons.
+    pub fn checkout(&self, name: &str) -> Result<Daemon, JokerError> {
+        let mut config = self.get_config()?;
+
+        let socket_address = config.daemons.get(name).cloned()
+            .ok_or_else(|| JokerError::UnknownDaemon(name.to_owned()))?;
+
+        let previous = std::mem::replace(&mut config.current_daemon, Daemon { name: name.to_owned(), socket_address });
+        config.daemons.insert(previous.name, previous.socket_address);
+        self.write_config(&config)?;
+
+        Ok(config.current_daemon)
+    }
+
+    /// Returns the daemon commands are sent to: the selected one or the one of `JOKER_DAEMON`
+    /// if set, the current daemon of the config otherwise. A `host:port` address which
+    /// isn't the name of a daemon doesn't need the config at all.
+    pub fn current_daemon(&self) -> Result<Daemon, JokerError> {
+        let name = self.daemon.iter().cloned()
+            .chain(env::var(DAEMON_VAR).ok())
+            .map(|name| name.trim().to_owned())
+            .find(|name| !name.is_empty());
+        let Some(name) = name else {
+            return Ok(self.get_config()?.current_daemon);
+        };
+
+        let config = self.get_config();
+        if let Some(daemon) = config.as_ref().ok().and_then(|config| config.find_daemon(&name)) {
+            return Ok(daemon);
+        }
+        if let Ok(socket_address) = name.parse::<DaemonAddress>() {
+            return Ok(Daemon { name, socket_address });
+        }
+
+        config?;
+        Err(JokerError::UnknownDaemon(name))
+    }
+}
+
+/// Names the config in an error, which may be far from the working directory.
+fn config_error(path: &Path, err: impl Into<io::Error>) -> JokerError {
+    let err = err.into();
+    JokerError::Config(io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+}
+
+/// Returns the path of the CLI config of the environment, see [`Selection::config_path`].
+pub fn config_path() -> PathBuf {
+    Selection::default().config_path()
+}
+
+pub fn get_config() -> Result<Config, JokerError> {
+    Selection::default().get_config()
+}
+
+/// Returns the daemon of `JOKER_DAEMON` or the current daemon of the config,
+/// see [`Selection::current_daemon`].
+pub fn current_daemon() -> Result<Daemon, JokerError> {
+    Selection::default().current_daemon()
+}
 
-    Ok(())
+pub fn write_config(config: &Config) -> Result<(), JokerError> {
+    Selection::default().write_config(config)
 }
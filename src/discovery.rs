@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::daemon::DaemonAddress;
+
+/// The DNS-SD service joker daemons advertise themselves as over mDNS.
+/// The TXT record of a daemon may hold its `version=`.
+pub const SERVICE: &str = "_joker._tcp.local";
+
+const MDNS_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+/// The class IN with the top bit asking for a unicast answer.
+const CLASS_IN_UNICAST: u16 = 0x8001;
+
+/// A daemon which answered a discovery query.
+#[derive(Serialize, Clone, Debug)]
+pub struct DiscoveredDaemon {
+    /// The instance name the daemon advertises itself with, e.g. `lab-3`.
+    pub name: String,
+    pub address: DaemonAddress,
+    pub version: Option<String>,
+}
+
+/// Asks the local network for the daemons advertising [`SERVICE`] and collects
+/// the answers which come within the timeout. The query is sent from an ephemeral
+/// port, so the daemons answer to it directly and nothing has to listen on 5353.
+/// Malformed answers are ignored.
+pub fn discover(timeout: Duration) -> io::Result<Vec<DiscoveredDaemon>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.send_to(&query(SERVICE), MDNS_ADDRESS)?;
+
+    let mut records = Records::default();
+    let deadline = Instant::now() + timeout;
+    let mut buffer = [0u8; 9000];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(left))?;
+
+        match socket.recv_from(&mut buffer) {
+            Ok((size, _)) => {
+                records.read(&buffer[..size]);
+            }
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(records.daemons())
+}
+
+/// A PTR query for the instances of a service.
+fn query(service: &str) -> Vec<u8> {
+    // an id of 0, no flags and a single question
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in service.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend(TYPE_PTR.to_be_bytes());
+    packet.extend(CLASS_IN_UNICAST.to_be_bytes());
+
+    packet
+}
+
+/// The records of the answers, by the lowercase names they describe.
+#[derive(Default)]
+struct Records {
+    instances: Vec<String>,
+    services: HashMap<String, (String, u16)>,
+    texts: HashMap<String, Vec<String>>,
+    addresses: HashMap<String, Vec<IpAddr>>,
+}
+
+impl Records {
+    /// Adds the records of an answer. Returns `None` if it's malformed.
+    fn read(&mut self, packet: &[u8]) -> Option<()> {
+        let mut reader = Reader { packet, position: 4 };
+        let questions = reader.u16()?;
+        let records = reader.u16()? as usize + reader.u16()? as usize + reader.u16()? as usize;
+
+        for _ in 0..questions {
+            reader.name()?;
+            reader.bytes(4)?;
+        }
+
+        for _ in 0..records {
+            let name = reader.name()?;
+            let record_type = reader.u16()?;
+            reader.bytes(6)?; // the class and the TTL
+            let length = reader.u16()? as usize;
+            let data_start = reader.position;
+            let data = reader.bytes(length)?;
+
+            match record_type {
+                TYPE_PTR if name == SERVICE => {
+                    let instance = Reader { packet, position: data_start }.name()?;
+                    if !self.instances.contains(&instance) {
+                        self.instances.push(instance);
+                    }
+                }
+                TYPE_SRV if data.len() > 6 => {
+                    let port = u16::from_be_bytes([data[4], data[5]]);
+                    let target = Reader { packet, position: data_start + 6 }.name()?;
+                    self.services.insert(name, (target, port));
+                }
+                TYPE_TXT => {
+                    let mut entries = Vec::new();
+                    let mut rest = data;
+                    while let Some((&size, tail)) = rest.split_first() {
+                        let entry = tail.get(..size as usize)?;
+                        entries.push(String::from_utf8_lossy(entry).into_owned());
+                        rest = &tail[size as usize..];
+                    }
+                    self.texts.insert(name, entries);
+                }
+                TYPE_A if data.len() == 4 => {
+                    let address = Ipv4Addr::new(data[0], data[1], data[2], data[3]);
+                    self.addresses.entry(name).or_default().push(IpAddr::V4(address));
+                }
+                TYPE_AAAA if data.len() == 16 => {
+                    let address = Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?);
+                    self.addresses.entry(name).or_default().push(IpAddr::V6(address));
+                }
+                _ => {}
+            }
+        }
+
+        Some(())
+    }
+
+    /// The daemons with both an instance and a service record. A daemon is reached
+    /// by an IPv4 address if it gave one, by any address otherwise, and by its
+    /// host name if it gave none.
+    fn daemons(&self) -> Vec<DiscoveredDaemon> {
+        let suffix = format!(".{}", SERVICE);
+
+        self.instances.iter()
+            .filter_map(|instance| {
+                let (target, port) = self.services.get(instance)?;
+                let addresses = self.addresses.get(target).map(Vec::as_slice).unwrap_or_default();
+                let address = match addresses.iter().find(|address| address.is_ipv4()).or(addresses.first()) {
+                    Some(&address) => DaemonAddress::from(SocketAddr::new(address, *port)),
+                    None => DaemonAddress::new(target, *port).ok()?,
+                };
+                let version = self.texts.get(instance).into_iter().flatten()
+                    .find_map(|entry| entry.strip_prefix("version="))
+                    .map(str::to_owned);
+
+                Some(DiscoveredDaemon {
+                    name: instance.strip_suffix(&suffix).unwrap_or(instance).to_owned(),
+                    address,
+                    version,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the fields of a DNS message in network byte order.
+struct Reader<'a> {
+    packet: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.packet.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a name, following the compression pointers, in lowercase and without the final dot.
+    fn name(&mut self) -> Option<String> {
+        let mut labels = Vec::new();
+        let mut position = self.position;
+        let mut end = None;
+
+        // a pointer only goes back, so a name can't have more of them than the packet has bytes
+        for _ in 0..self.packet.len() {
+            let length = *self.packet.get(position)? as usize;
+            match length {
+                0 => {
+                    self.position = end.unwrap_or(position + 1);
+                    return Some(labels.join(".").to_lowercase());
+                }
+                length if length & 0xC0 == 0xC0 => {
+                    let pointer = (length & 0x3F) << 8 | *self.packet.get(position + 1)? as usize;
+                    if pointer >= position {
+                        return None;
+                    }
+                    end.get_or_insert(position + 2);
+                    position = pointer;
+                }
+                length => {
+                    let label = self.packet.get(position + 1..position + 1 + length)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    position += 1 + length;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(name: &str) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for label in name.split('.') {
+            encoded.push(label.len() as u8);
+            encoded.extend(label.as_bytes());
+        }
+        encoded.push(0);
+        encoded
+    }
+
+    fn pointer(offset: usize) -> Vec<u8> {
+        vec![0xC0 | (offset >> 8) as u8, offset as u8]
+    }
+
+    /// The header of a response with that many answers and no question.
+    fn response(answers: u16) -> Vec<u8> {
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0];
+        packet.extend(answers.to_be_bytes());
+        packet.extend([0, 0, 0, 0]);
+        packet
+    }
+
+    /// Appends a record and returns the offset of its data.
+    fn record(packet: &mut Vec<u8>, name: &[u8], record_type: u16, data: &[u8]) -> usize {
+        packet.extend(name);
+        packet.extend(record_type.to_be_bytes());
+        packet.extend([0, 1, 0, 0, 0x11, 0x94]);
+        packet.extend((data.len() as u16).to_be_bytes());
+        packet.extend(data);
+        packet.len() - data.len()
+    }
+
+    /// A PTR and an SRV record of the instance `lab-3` on port 7788 of the host `lab-3.local`,
+    /// with the names compressed. Returns the offsets of the instance name and of the host name.
+    fn advertisement(packet: &mut Vec<u8>) -> (usize, usize) {
+        let service = packet.len();
+        let instance = record(packet, &labels(SERVICE), TYPE_PTR, &[&b"\x05lab-3"[..], &pointer(service)].concat());
+        // `local` is the last label of the service name, whose length bytes take the places of the dots
+        let local = service + "_joker._tcp.".len();
+        let srv = [&[0, 0, 0, 0][..], &7788u16.to_be_bytes(), b"\x05lab-3", &pointer(local)].concat();
+        let target = record(packet, &pointer(instance), TYPE_SRV, &srv) + 6;
+        (instance, target)
+    }
+
+    #[test]
+    fn follows_compressed_names_to_a_daemon() {
+        let mut packet = response(4);
+        let (instance, target) = advertisement(&mut packet);
+        record(&mut packet, &pointer(instance), TYPE_TXT, b"\x0dversion=0.3.0\x04blue");
+        record(&mut packet, &pointer(target), TYPE_A, &[10, 0, 0, 7]);
+
+        let mut records = Records::default();
+        assert_eq!(records.read(&packet), Some(()));
+        let daemons = records.daemons();
+        assert_eq!(daemons.len(), 1);
+        assert_eq!(daemons[0].name, "lab-3");
+        assert_eq!(daemons[0].address.to_string(), "10.0.0.7:7788");
+        assert_eq!(daemons[0].version.as_deref(), Some("0.3.0"));
+    }
+
+    #[test]
+    fn a_daemon_without_an_address_record_is_reached_by_its_host_name() {
+        let mut packet = response(2);
+        advertisement(&mut packet);
+
+        let mut records = Records::default();
+        assert_eq!(records.read(&packet), Some(()));
+        let daemons = records.daemons();
+        assert_eq!(daemons.len(), 1);
+        assert_eq!(daemons[0].address.to_string(), "lab-3.local:7788");
+        assert_eq!(daemons[0].version, None);
+    }
+
+    #[test]
+    fn pointers_which_dont_go_back_are_malformed() {
+        for name in [pointer(12), pointer(14), [&b"\x01a"[..], &pointer(12)].concat()] {
+            let mut packet = response(1);
+            record(&mut packet, &name, TYPE_A, &[10, 0, 0, 7]);
+            assert_eq!(Records::default().read(&packet), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn truncated_records_are_malformed() {
+        let mut packet = response(1);
+        record(&mut packet, &labels("lab-3._joker._tcp.local"), TYPE_TXT, b"\x0dversion=0.3");
+        assert_eq!(Records::default().read(&packet), None);
+
+        let mut packet = response(1);
+        record(&mut packet, &labels("lab-3.local"), TYPE_A, &[10, 0, 0, 7]);
+        packet.truncate(packet.len() - 2);
+        assert_eq!(Records::default().read(&packet), None);
+
+        assert_eq!(Records::default().read(&response(1)[..7]), None);
+    }
+
+    #[test]
+    fn a_service_without_an_instance_is_no_daemon() {
+        let mut packet = response(1);
+        let srv = [&[0, 0, 0, 0][..], &7788u16.to_be_bytes(), &labels("lab-3.local")].concat();
+        record(&mut packet, &labels("lab-3._joker._tcp.local"), TYPE_SRV, &srv);
+
+        let mut records = Records::default();
+        assert_eq!(records.read(&packet), Some(()));
+        assert!(records.daemons().is_empty());
+    }
+
+    #[test]
+    fn the_query_asks_for_the_instances_of_the_service() {
+        let query = query(SERVICE);
+        assert_eq!(&query[4..6], &[0, 1]);
+        assert_eq!(Reader { packet: &query, position: 12 }.name().as_deref(), Some(SERVICE));
+        assert_eq!(&query[query.len() - 4..], &[0, 12, 0x80, 1]);
+    }
+}
@@ -1,14 +1,139 @@
-use std::{error, fmt};
-use std::fmt::{Debug, Display};
+use std::{error, io};
+use thiserror::Error;
 
-/// This error represents the absence of the key in hashmap.
-#[derive(Debug, Clone)]
-pub struct AbsentHashMapKeyError;
+/// This error represents a malformed line in a container config.
+#[derive(Error, Debug, Clone)]
+#[error("Bad container config at line {line}: {message}.")]
+pub struct ConfigParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// This error represents an invalid value of a container option.
+#[derive(Error, Debug, Clone)]
+#[error("Invalid value {value:?} for {option}.")]
+pub struct InvalidOptionError {
+    pub option: String,
+    pub value: String,
+}
+
+/// This error represents an OCI bundle which can't be run.
+#[derive(Error, Debug, Clone)]
+#[error("Bad OCI bundle: {message}.")]
+pub struct InvalidBundleError {
+    pub message: String,
+}
+
+/// This error represents a malformed instruction of a Jokerfile.
+#[derive(Error, Debug, Clone)]
+#[error("Bad Jokerfile at line {line}: {message}.")]
+pub struct JokerfileParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// This error represents a variable of a config which can't be expanded.
+#[derive(Error, Debug, Clone)]
+#[error("Bad variable at line {line}: {message}.")]
+pub struct InterpolationError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The error of a joker command. Its variant tells the callers what went wrong
+/// and gives the exit code of the CLI, see `exit_code`.
+#[derive(Error, Debug)]
+pub enum JokerError {
+    /// The CLI config with the daemons can't be read or written.
+    #[error("Can't use the CLI config: {0}")]
+    Config(#[source] io::Error),
+    /// There is no daemon with such name in the CLI config.
+    #[error("Error: no such daemon {0}.")]
+    UnknownDaemon(String),
+    /// A container config, a compose file or an option is malformed.
+    #[error("{0}")]
+    Parse(#[source] Box<dyn error::Error>),
+    /// A daemon can't be reached or the connection to it was lost.
+    #[error("Connection to the daemon failed: {0}")]
+    Connection(#[source] io::Error),
+    /// A daemon answered something the CLI doesn't understand.
+    #[error("Unexpected answer of the daemon: {0}")]
+    Protocol(String),
+    /// A daemon refused a request, with its reason.
+    #[error("{0}")]
+    Rejected(String),
+    /// An external subcommand exited with a failure, whose exit code joker exits with too.
+    #[error("Plugin {name} exited with code {code}.")]
+    Plugin { name: String, code: i32 },
+    /// A container followed by an attached `run` exited with a failure, whose exit code joker exits with too.
+    #[error("Container {name} exited with code {code}.")]
+    ContainerExited { name: String, code: i32 },
+    /// Any other failure, such as a missing local file.
+    #[error("{0}")]
+    Other(#[source] Box<dyn error::Error>),
+}
+
+impl JokerError {
+    /// The exit code of the CLI for the error: 1 for a generic failure, 2 for a bad
+    /// config or option, 3 for the CLI config, 4 for a connection, 5 for a protocol error
+    /// and 6 for a request rejected by a daemon. A failed plugin or an attached container
+    /// gives its own exit code.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            JokerError::Other(_) => 1,
+            JokerError::Parse(_) | JokerError::UnknownDaemon(_) => 2,
+            JokerError::Config(_) => 3,
+            JokerError::Connection(_) => 4,
+            JokerError::Protocol(_) => 5,
+            JokerError::Rejected(_) => 6,
+            JokerError::Plugin { code, .. } | JokerError::ContainerExited { code, .. } => *code,
+        }
+    }
+}
 
-impl Display for AbsentHashMapKeyError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "No such key in a hashmap.")
+impl From<ConfigParseError> for JokerError {
+    fn from(err: ConfigParseError) -> Self {
+        JokerError::Parse(err.into())
     }
 }
 
-impl error::Error for AbsentHashMapKeyError {}
+impl From<InvalidOptionError> for JokerError {
+    fn from(err: InvalidOptionError) -> Self {
+        JokerError::Parse(err.into())
+    }
+}
+
+impl From<JokerfileParseError> for JokerError {
+    fn from(err: JokerfileParseError) -> Self {
+        JokerError::Parse(err.into())
+    }
+}
+
+impl From<InterpolationError> for JokerError {
+    fn from(err: InterpolationError) -> Self {
+        JokerError::Parse(err.into())
+    }
+}
+
+impl From<io::Error> for JokerError {
+    fn from(err: io::Error) -> Self {
+        JokerError::Other(err.into())
+    }
+}
+
+/// Keeps the variant of an error which went through a `Box<dyn Error>`.
+impl From<Box<dyn error::Error>> for JokerError {
+    fn from(err: Box<dyn error::Error>) -> Self {
+        let err = match err.downcast::<JokerError>() {
+            Ok(err) => return *err,
+            Err(err) => err,
+        };
+        match err.is::<ConfigParseError>()
+            || err.is::<InvalidOptionError>()
+            || err.is::<JokerfileParseError>()
+            || err.is::<InterpolationError>() {
+            true => JokerError::Parse(err),
+            false => JokerError::Other(err),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use flate2::read::GzDecoder;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use crate::container::DEFAULT_CAPABILITIES;
+use crate::sbom::{self, Sbom};
+use crate::store::Store;
+
+pub const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const OCI_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+pub const OCI_LAYER: &str = "application/vnd.oci.image.layer.v1.tar";
+pub const OCI_LAYER_GZIP: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+pub const DOCKER_MANIFEST_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+pub const DOCKER_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+pub const DOCKER_CONFIG: &str = "application/vnd.docker.container.image.v1+json";
+pub const DOCKER_LAYER_GZIP: &str = "application/vnd.docker.image.rootfs.diff.tar.gzip";
+pub const OCI_EMPTY: &str = "application/vnd.oci.empty.v1+json";
+pub const JOKER_ARTIFACT: &str = "application/vnd.joker.container.v1";
+pub const JOKER_BINARY: &str = "application/vnd.joker.binary.v1";
+pub const JOKER_CONFIG: &str = "application/vnd.joker.config.v1+text";
+/// The files shipped with a binary, a gzipped tar of their paths in the container.
+pub const JOKER_ASSETS: &str = "application/vnd.joker.assets.v1.tar+gzip";
+
+/// The annotation holding the file name of a layer.
+pub const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+/// The annotation holding the reference of a manifest of an image layout.
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// The media types of manifests accepted from a registry, most preferred first.
+pub const MANIFEST_TYPES: &[&str] = &[OCI_INDEX, OCI_MANIFEST, DOCKER_MANIFEST_LIST, DOCKER_MANIFEST];
+
+/// A reference to a blob: a manifest, an image config or a layer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// A list of manifests of the same image for different platforms.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Index {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub schema_version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// The parts of an image config describing the process to run.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ImageConfig {
+    #[serde(default)]
+    pub architecture: String,
+    #[serde(default)]
+    pub os: String,
+    #[serde(default)]
+    pub config: ProcessConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct ProcessConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+}
+
+impl Descriptor {
+    pub fn new(media_type: &str, data: &[u8]) -> Self {
+        Descriptor {
+            media_type: media_type.to_owned(),
+            digest: crate::store::digest(data),
+            size: data.len() as u64,
+            platform: None,
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+/// A container of the joker format, published as an OCI artifact:
+/// a binary with its `.joker` config, and optionally its SBOM and its packed assets.
+pub struct JokerArtifact {
+    pub name: String,
+    pub binary: Vec<u8>,
+    pub config: Vec<u8>,
+    pub sbom: Option<Sbom>,
+    pub assets: Option<Vec<u8>>,
+}
+
+impl JokerArtifact {
+    /// Builds the manifest of the artifact. The layers are the binary, named by
+    /// the title annotation, the config, the SBOM and the assets; the image config is empty.
+    pub fn manifest(&self) -> Manifest {
+        let mut binary = Descriptor::new(JOKER_BINARY, &self.binary);
+        binary.annotations.insert(TITLE_ANNOTATION.to_owned(), self.name.clone());
+
+        let mut layers = vec![binary, Descriptor::new(JOKER_CONFIG, &self.config)];
+        if let Some(sbom) = &self.sbom {
+            layers.push(Descriptor::new(&sbom.media_type, sbom.data.as_bytes()));
+        }
+        if let Some(assets) = &self.assets {
+            layers.push(Descriptor::new(JOKER_ASSETS, assets));
+        }
+
+        Manifest {
+            schema_version: 2,
+            media_type: Some(OCI_MANIFEST.to_owned()),
+            artifact_type: Some(JOKER_ARTIFACT.to_owned()),
+            config: Descriptor::new(OCI_EMPTY, b"{}"),
+            layers,
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    /// Reads an artifact back from the blobs of the local store.
+    pub fn from_store(store: &Store, manifest: &Manifest) -> io::Result<Self> {
+        let error = || io::Error::new(io::ErrorKind::InvalidData, "Bad joker artifact.");
+
+        let binary = manifest.layers.iter().find(|layer| layer.media_type == JOKER_BINARY).ok_or_else(error)?;
+        let config = manifest.layers.iter().find(|layer| layer.media_type == JOKER_CONFIG).ok_or_else(error)?;
+        let sbom = manifest.layers.iter().find(|layer| sbom::SBOM_TYPES.contains(&layer.media_type.as_str()));
+        let sbom = match sbom {
+            Some(layer) => Some(Sbom {
+                media_type: layer.media_type.clone(),
+                data: String::from_utf8(store.read_blob(&layer.digest)?).map_err(|_| error())?,
+            }),
+            None => None,
+        };
+        let assets = manifest.layers.iter()
+            .find(|layer| layer.media_type == JOKER_ASSETS)
+            .map(|layer| store.read_blob(&layer.digest))
+            .transpose()?;
+
+        Ok(JokerArtifact {
+            name: binary.annotations.get(TITLE_ANNOTATION).cloned().ok_or_else(error)?,
+            binary: store.read_blob(&binary.digest)?,
+            config: store.read_blob(&config.digest)?,
+            sbom,
+            assets,
+        })
+    }
+}
+
+/// Returns the OCI name of the platform the client runs on.
+pub fn current_platform() -> Platform {
+    let architecture = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        other => other,
+    };
+    Platform { architecture: architecture.to_owned(), os: "linux".to_owned(), variant: None }
+}
+
+/// Picks the manifest for the given platform from an index.
+pub fn select_manifest<'a>(index: &'a Index, platform: &Platform) -> Option<&'a Descriptor> {
+    index.manifests.iter().find(|descriptor| {
+        descriptor.platform.as_ref().is_some_and(|p| {
+            p.os == platform.os && p.architecture == platform.architecture
+        })
+    })
+}
+
+/// Checks whether a manifest is an index rather than a single image.
+pub fn is_index(media_type: &str) -> bool {
+    media_type == OCI_INDEX || media_type == DOCKER_MANIFEST_LIST
+}
+
+/// Builds an OCI runtime spec running the process of an image config.
+pub fn runtime_spec(config: &ImageConfig) -> Value {
+    let process = &config.config;
+
+    let args = process.entrypoint.iter().flatten()
+        .chain(process.cmd.iter().flatten())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let env = process.env.clone().unwrap_or_else(|| {
+        vec!["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_owned()]
+    });
+
+    // Only numeric users can be resolved without the passwd file of the image
+    let user = process.user.as_deref().unwrap_or_default();
+    let (uid, gid) = user.split_once(':').unwrap_or((user, "0"));
+    let uid = uid.parse::<u32>().unwrap_or(0);
+    let gid = gid.parse::<u32>().unwrap_or(0);
+
+    json!({
+        "ociVersion": "1.0.2",
+        "process": {
+            "user": { "uid": uid, "gid": gid },
+            "args": args,
+            "env": env,
+            "cwd": process.working_dir.clone().filter(|dir| !dir.is_empty()).unwrap_or_else(|| "/".to_owned()),
+            "capabilities": {
+                "bounding": DEFAULT_CAPABILITIES,
+                "effective": DEFAULT_CAPABILITIES,
+                "permitted": DEFAULT_CAPABILITIES,
+            },
+            "noNewPrivileges": true,
+        },
+        "root": { "path": "rootfs" },
+        "mounts": [
+            { "destination": "/proc", "type": "proc", "source": "proc" },
+            { "destination": "/dev", "type": "tmpfs", "source": "tmpfs", "options": ["nosuid", "strictatime", "mode=755", "size=65536k"] },
+            { "destination": "/dev/pts", "type": "devpts", "source": "devpts", "options": ["nosuid", "noexec", "newinstance", "ptmxmode=0666", "mode=0620"] },
+            { "destination": "/sys", "type": "sysfs", "source": "sysfs", "options": ["nosuid", "noexec", "nodev", "ro"] },
+        ],
+        "linux": {
+            "namespaces": [
+                { "type": "pid" },
+                { "type": "ipc" },
+                { "type": "uts" },
+                { "type": "mount" },
+                { "type": "network" },
+            ],
+        },
+    })
+}
+
+/// Opens a layer of the store as a tar stream, decompressing it if needed.
+pub fn open_layer(store: &Store, layer: &Descriptor) -> io::Result<Box<dyn Read>> {
+    let file = store.open_blob(&layer.digest)?;
+    match layer.media_type.as_str() {
+        OCI_LAYER => Ok(Box::new(file)),
+        OCI_LAYER_GZIP | DOCKER_LAYER_GZIP => Ok(Box::new(GzDecoder::new(file))),
+        other => Err(io::Error::new(io::ErrorKind::Unsupported, format!("Unsupported layer type {}.", other))),
+    }
+}
+
+/// An entry of a flattened root filesystem.
+struct Entry {
+    header: tar::Header,
+    link: Option<PathBuf>,
+    data: Vec<u8>,
+}
+
+/// Applies the layers of an image one over another, honoring whiteouts,
+/// and writes the result into a tar archive under `prefix`.
+pub fn flatten_layers(
+    store: &Store,
+    layers: &[Descriptor],
+    prefix: &Path,
+    builder: &mut tar::Builder<Vec<u8>>,
+) -> io::Result<()> {
+    let mut entries: BTreeMap<PathBuf, Entry> = BTreeMap::new();
+
+    for layer in layers {
+        let mut archive = tar::Archive::new(open_layer(store, layer)?);
+        let mut layer_entries = Vec::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = normalize_path(&entry.path()?);
+            let Some(path) = path else { continue };
+
+            let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default().to_owned();
+            let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+            // An opaque whiteout hides everything the lower layers have in its directory
+            if file_name == ".wh..wh..opq" {
+                entries.retain(|p, _| !p.starts_with(&parent) || *p == parent);
+                continue;
+            }
+            // A whiteout removes a file or a directory of the lower layers
+            if let Some(removed) = file_name.strip_prefix(".wh.") {
+                let removed = parent.join(removed);
+                entries.retain(|p, _| !p.starts_with(&removed));
+                continue;
+            }
+
+            let link = entry.link_name()?.map(|link| link.into_owned());
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data)?;
+            layer_entries.push((path, Entry { header: entry.header().clone(), link, data }));
+        }
+
+        // Whiteouts apply to the lower layers only, so the entries are added afterwards
+        for (path, entry) in layer_entries {
+            if !entry.header.entry_type().is_dir() {
+                entries.retain(|p, _| !p.starts_with(&path) || *p == path);
+            }
+            entries.insert(path, entry);
+        }
+    }
+
+    for (path, mut entry) in entries {
+        let path = prefix.join(path);
+        match entry.link {
+            // Unlike symlinks, hard links point to other entries of the archive
+            Some(link) if entry.header.entry_type().is_hard_link() => {
+                let link = normalize_path(&link).map(|link| prefix.join(link)).unwrap_or(link);
+                builder.append_link(&mut entry.header, &path, &link)?;
+            }
+            Some(link) => builder.append_link(&mut entry.header, &path, &link)?,
+            None => builder.append_data(&mut entry.header, &path, entry.data.as_slice())?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Strips `./` and leading slashes from a path of a layer.
+/// Paths escaping the root filesystem are dropped.
+fn normalize_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir | Component::RootDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
@@ -0,0 +1,182 @@
+use crate::errors::InterpolationError;
+
+/// Expands the variables of a value of a config with the values of the local environment.
+/// `line` is the line of the config the value is written at. See `interpolate_with`.
+pub fn interpolate(value: &str, line: usize) -> Result<String, InterpolationError> {
+    interpolate_with(value, line, |name| std::env::var(name).ok())
+}
+
+/// Expands the variables of every string of a TOML document, but not of its keys or
+/// its comments, so that a value of the environment can't change the document itself.
+/// `line_of` finds the line a value is written at by its dotted path, like `env.PORT`.
+pub fn interpolate_toml(value: &mut toml::Value, line_of: &impl Fn(&str) -> usize) -> Result<(), InterpolationError> {
+    interpolate_toml_at(value, "", line_of)
+}
+
+fn interpolate_toml_at(value: &mut toml::Value, path: &str, line_of: &impl Fn(&str) -> usize) -> Result<(), InterpolationError> {
+    match value {
+        toml::Value::String(string) => *string = interpolate(string, line_of(path))?,
+        toml::Value::Array(items) => {
+            for item in items {
+                interpolate_toml_at(item, path, line_of)?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match path.is_empty() {
+                    true => interpolate_toml_at(value, key, line_of)?,
+                    false => interpolate_toml_at(value, &format!("{}.{}", path, key), line_of)?,
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expands `${VAR}` references. `${VAR:-default}` takes the default when the variable
+/// is unset or empty and `${VAR-default}` only when it's unset, `${VAR:?message}` and
+/// `${VAR?message}` fail instead. An unset variable without a default expands to an empty
+/// string with a warning. `$${` is written as a literal `${`, e.g. for a health check command
+/// which is expanded by the shell of a container; any other `$` is left as is.
+pub fn interpolate_with(
+    text: &str,
+    first_line: usize,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, InterpolationError> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        let line = first_line + text[..text.len() - rest.len() + start].matches('\n').count();
+        rest = &rest[start..];
+
+        if rest.starts_with("$${") {
+            result.push_str("${");
+            rest = &rest[3..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            result.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+
+        let end = rest.find('}')
+            .filter(|end| !rest[..*end].contains('\n'))
+            .ok_or_else(|| InterpolationError { line, message: "unclosed ${".to_owned() })?;
+        result.push_str(&expand(&rest[2..end], line, &lookup)?);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+fn expand(reference: &str, line: usize, lookup: &impl Fn(&str) -> Option<String>) -> Result<String, InterpolationError> {
+    let name_end = reference.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(reference.len());
+    let (name, modifier) = reference.split_at(name_end);
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(InterpolationError { line, message: format!("bad variable name in ${{{}}}", reference) });
+    }
+
+    let value = lookup(name);
+    let operation = modifier.strip_prefix(':').unwrap_or(modifier);
+    let (operator, argument) = (operation.get(..1), operation.get(1..).unwrap_or_default());
+    // With a colon an empty variable is treated as an unset one
+    let value = match modifier.starts_with(':') {
+        true => value.filter(|value| !value.is_empty()),
+        false => value,
+    };
+
+    match (operator, value) {
+        (Some("-") | Some("?") | None, Some(value)) => Ok(value),
+        (Some("-"), None) => Ok(argument.to_owned()),
+        (Some("?"), None) => {
+            let message = match argument.is_empty() {
+                true => format!("{} is not set", name),
+                false => format!("{}: {}", name, argument),
+            };
+            Err(InterpolationError { line, message })
+        }
+        (None, None) => {
+            eprintln!("Warning: variable {} at line {} is not set, expanded to an empty string.", name, line);
+            Ok(String::new())
+        }
+        _ => Err(InterpolationError { line, message: format!("bad variable reference ${{{}}}", reference) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "PORT" => Some("8080".to_owned()),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn expanded(text: &str) -> String {
+        interpolate_with(text, 1, lookup).unwrap()
+    }
+
+    fn error(text: &str, first_line: usize) -> InterpolationError {
+        interpolate_with(text, first_line, lookup).unwrap_err()
+    }
+
+    #[test]
+    fn expands_set_variables() {
+        assert_eq!(expanded("--port ${PORT}"), "--port 8080");
+        assert_eq!(expanded("${PORT}:${PORT}"), "8080:8080");
+        assert_eq!(expanded("${UNSET}"), "");
+    }
+
+    #[test]
+    fn a_colon_treats_an_empty_variable_as_an_unset_one() {
+        assert_eq!(expanded("${EMPTY:-80}"), "80");
+        assert_eq!(expanded("${EMPTY-80}"), "");
+        assert_eq!(expanded("${UNSET:-80}"), "80");
+        assert_eq!(expanded("${UNSET-80}"), "80");
+        assert_eq!(expanded("${PORT:-80}"), "8080");
+    }
+
+    #[test]
+    fn a_question_mark_requires_the_variable() {
+        assert_eq!(error("${UNSET:?set the token}", 1).message, "UNSET: set the token");
+        assert_eq!(error("${EMPTY:?}", 1).message, "EMPTY is not set");
+        assert_eq!(expanded("${EMPTY?}"), "");
+        assert_eq!(expanded("${PORT:?required}"), "8080");
+    }
+
+    #[test]
+    fn only_dollar_braces_are_references() {
+        assert_eq!(expanded("$${PORT} costs $5"), "${PORT} costs $5");
+        assert_eq!(expanded("$$"), "$$");
+    }
+
+    #[test]
+    fn errors_point_at_the_line_of_the_reference() {
+        let err = error("a\nb ${PORT\n}", 3);
+        assert_eq!((err.line, err.message.as_str()), (4, "unclosed ${"));
+        assert_eq!(error("${PORT", 7).line, 7);
+        assert_eq!(error("x\n\n${UNSET:?}", 1).line, 3);
+    }
+
+    #[test]
+    fn rejects_bad_references() {
+        assert_eq!(error("${1X}", 1).message, "bad variable name in ${1X}");
+        assert_eq!(error("${}", 1).message, "bad variable name in ${}");
+        assert_eq!(error("${PORT+x}", 1).message, "bad variable reference ${PORT+x}");
+    }
+
+    #[test]
+    fn expands_the_values_of_a_toml_document_but_not_its_keys() {
+        let mut value: toml::Value = toml::from_str("[env]\n\"${HOME}\" = \"$${HOME}\"\n").unwrap();
+        interpolate_toml(&mut value, &|_| 1).unwrap();
+        assert_eq!(value["env"]["${HOME}"].as_str(), Some("${HOME}"));
+    }
+}
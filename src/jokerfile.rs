@@ -0,0 +1,353 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use crate::container::EnvVar;
+use crate::errors::JokerfileParseError;
+use crate::image::{self, Descriptor, Manifest};
+use crate::registry::ImageReference;
+use crate::store::{self, Store};
+
+pub const DEFAULT_JOKERFILE: &str = "Jokerfile";
+
+/// A step of a Jokerfile.
+#[derive(Clone, Debug)]
+pub enum Instruction {
+    /// The base of an image: `scratch`, an image of the local store
+    /// or a directory (starting with `.` or `/`) with a root filesystem.
+    From(String),
+    /// Runs a command on the build host in the context directory,
+    /// e.g. to compile the binary before it's copied.
+    Build(String),
+    /// Copies a file or a directory of the context into the image.
+    Copy { source: PathBuf, target: PathBuf },
+    Env(EnvVar),
+    Workdir(PathBuf),
+    Entrypoint(Vec<String>),
+    Cmd(Vec<String>),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::From(base) => write!(f, "FROM {}", base),
+            Instruction::Build(command) => write!(f, "BUILD {}", command),
+            Instruction::Copy { source, target } => write!(f, "COPY {} {}", source.display(), target.display()),
+            Instruction::Env(var) => write!(f, "ENV {}", var),
+            Instruction::Workdir(dir) => write!(f, "WORKDIR {}", dir.display()),
+            Instruction::Entrypoint(args) => write!(f, "ENTRYPOINT {}", args.join(" ")),
+            Instruction::Cmd(args) => write!(f, "CMD {}", args.join(" ")),
+        }
+    }
+}
+
+/// A recipe of an image: an instruction per line, optionally continued
+/// with a trailing `\`. Empty lines and lines starting with `#` are skipped.
+pub struct Jokerfile {
+    pub instructions: Vec<Instruction>,
+}
+
+impl Jokerfile {
+    pub fn parse(text: &str) -> Result<Self, JokerfileParseError> {
+        let mut instructions = Vec::new();
+        let mut pending = String::new();
+        let mut pending_line = 0;
+
+        for (index, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if pending.is_empty() && (trimmed.is_empty() || trimmed.starts_with('#')) {
+                continue;
+            }
+            if pending.is_empty() {
+                pending_line = index + 1;
+            }
+
+            match trimmed.strip_suffix('\\') {
+                Some(continued) => {
+                    pending.push_str(continued);
+                    pending.push(' ');
+                }
+                None => {
+                    pending.push_str(trimmed);
+                    instructions.push(parse_instruction(&pending, pending_line, instructions.is_empty())?);
+                    pending.clear();
+                }
+            }
+        }
+
+        if !pending.is_empty() {
+            instructions.push(parse_instruction(&pending, pending_line, instructions.is_empty())?);
+        }
+        if instructions.is_empty() {
+            return Err(JokerfileParseError { line: 1, message: "no instructions".to_owned() });
+        }
+
+        Ok(Jokerfile { instructions })
+    }
+}
+
+/// A starter Jokerfile for `joker init`, copying a binary into an empty image.
+pub fn starter_jokerfile(binary: &str) -> String {
+    format!(r#"FROM scratch
+# BUILD cargo build --release
+COPY {binary} /{binary}
+# ENV RUST_LOG=info
+ENTRYPOINT /{binary}
+"#, binary = binary)
+}
+
+/// Parses a line of a Jokerfile. The first instruction must be the only FROM.
+fn parse_instruction(line: &str, line_number: usize, first: bool) -> Result<Instruction, JokerfileParseError> {
+    let error = |message: &str| JokerfileParseError { line: line_number, message: message.to_owned() };
+
+    let (keyword, arguments) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let arguments = arguments.trim();
+    if arguments.is_empty() {
+        return Err(error(&format!("{} needs arguments", keyword)));
+    }
+    if first != keyword.eq_ignore_ascii_case("FROM") {
+        return Err(error("a Jokerfile must start with a single FROM"));
+    }
+
+    match keyword.to_uppercase().as_str() {
+        "FROM" => Ok(Instruction::From(arguments.to_owned())),
+        "BUILD" => Ok(Instruction::Build(arguments.to_owned())),
+        "COPY" => {
+            let parts = arguments.split_whitespace().collect::<Vec<_>>();
+            match parts[..] {
+                [source, target] => Ok(Instruction::Copy { source: PathBuf::from(source), target: PathBuf::from(target) }),
+                _ => Err(error("COPY takes a source and a target")),
+            }
+        }
+        "ENV" => arguments.parse::<EnvVar>()
+            .ok()
+            .filter(|_| arguments.contains('='))
+            .map(Instruction::Env)
+            .ok_or_else(|| error("ENV takes a KEY=VALUE pair")),
+        "WORKDIR" => match Path::new(arguments).is_absolute() {
+            true => Ok(Instruction::Workdir(PathBuf::from(arguments))),
+            false => Err(error("WORKDIR must be an absolute path")),
+        },
+        "ENTRYPOINT" => parse_command(arguments).map(Instruction::Entrypoint).ok_or_else(|| error("bad ENTRYPOINT")),
+        "CMD" => parse_command(arguments).map(Instruction::Cmd).ok_or_else(|| error("bad CMD")),
+        _ => Err(error(&format!("unknown instruction {}", keyword))),
+    }
+}
+
+/// Parses a command either as a JSON array or as words separated by whitespace.
+fn parse_command(arguments: &str) -> Option<Vec<String>> {
+    match arguments.starts_with('[') {
+        true => serde_json::from_str(arguments).ok(),
+        false => Some(arguments.split_whitespace().map(str::to_owned).collect()),
+    }
+}
+
+/// Builds an image in the local store instruction by instruction.
+pub struct ImageBuilder<'a> {
+    store: &'a Store,
+    context: PathBuf,
+    config: Value,
+    layers: Vec<Descriptor>,
+    /// The layer of the files copied by the current build.
+    layer: tar::Builder<GzEncoder<Vec<u8>>>,
+    copied: bool,
+}
+
+impl<'a> ImageBuilder<'a> {
+    pub fn new(store: &'a Store, context: &Path) -> Self {
+        let platform = image::current_platform();
+
+        ImageBuilder {
+            store,
+            context: context.to_owned(),
+            config: json!({
+                "architecture": platform.architecture,
+                "os": platform.os,
+                "config": {},
+                "rootfs": { "type": "layers", "diff_ids": [] },
+            }),
+            layers: Vec::new(),
+            layer: tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default())),
+            copied: false,
+        }
+    }
+
+    /// Executes an instruction of a Jokerfile.
+    pub fn apply(&mut self, instruction: &Instruction) -> Result<(), Box<dyn std::error::Error>> {
+        match instruction {
+            Instruction::From(base) => self.from(base)?,
+            Instruction::Build(command) => {
+                let status = Command::new("sh").arg("-c").arg(command).current_dir(&self.context).status()?;
+                if !status.success() {
+                    return Err(format!("Error: `{}` failed with {}.", command, status).into());
+                }
+            }
+            Instruction::Copy { source, target } => self.copy(source, target)?,
+            Instruction::Env(var) => {
+                let mut env = self.config["config"]["Env"].as_array().cloned().unwrap_or_default();
+                let prefix = format!("{}=", var.key);
+                env.retain(|v| !v.as_str().is_some_and(|v| v.starts_with(&prefix)));
+                env.push(json!(var.to_string()));
+                self.config["config"]["Env"] = json!(env);
+            }
+            Instruction::Workdir(dir) => self.config["config"]["WorkingDir"] = json!(dir),
+            Instruction::Entrypoint(args) => {
+                self.config["config"]["Entrypoint"] = json!(args);
+                // As in other image formats, a new entrypoint resets the arguments of the base
+                if let Some(config) = self.config["config"].as_object_mut() {
+                    config.remove("Cmd");
+                }
+            }
+            Instruction::Cmd(args) => self.config["config"]["Cmd"] = json!(args),
+        }
+
+        Ok(())
+    }
+
+    fn from(&mut self, base: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if base == "scratch" {
+            return Ok(());
+        }
+
+        if base.starts_with('.') || base.starts_with('/') {
+            let rootfs = self.context.join(base);
+            let mut layer = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+            layer.follow_symlinks(false);
+            layer.append_dir_all(".", &rootfs)?;
+            self.add_layer(layer)?;
+            return Ok(());
+        }
+
+        let reference: ImageReference = base.parse()?;
+        let digest = self.store.resolve(&reference)?
+            .ok_or_else(|| format!("Error: no image {} in the local store, pull it first.", reference))?;
+        let manifest = self.store.read_manifest(&digest)?;
+        if manifest.artifact_type.is_some() {
+            return Err(format!("Error: {} is not an image with a root filesystem.", reference).into());
+        }
+
+        let base_config: Value = serde_json::from_slice(&self.store.read_blob(&manifest.config.digest)?)?;
+        for key in ["architecture", "os", "config", "rootfs"] {
+            if !base_config[key].is_null() {
+                self.config[key] = base_config[key].clone();
+            }
+        }
+        self.layers = manifest.layers;
+
+        Ok(())
+    }
+
+    fn copy(&mut self, source: &Path, target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let escapes = source.is_absolute() || source.components().any(|c| c == Component::ParentDir);
+        if escapes {
+            return Err(format!("Error: {} is outside of the build context.", source.display()).into());
+        }
+
+        // Copying a file into a directory keeps its name
+        let into_directory = target.as_os_str().to_string_lossy().ends_with('/');
+        let target = match target.is_absolute() {
+            true => target.to_owned(),
+            false => {
+                let workdir = self.config["config"]["WorkingDir"].as_str().unwrap_or("/");
+                Path::new(workdir).join(target)
+            }
+        };
+        let target = target.strip_prefix("/").unwrap_or(&target).to_owned();
+
+        let source = self.context.join(source);
+        if source.is_dir() {
+            self.layer.append_dir_all(&target, &source)?;
+        } else {
+            let target = match into_directory {
+                true => target.join(source.file_name().unwrap_or_default()),
+                false => target,
+            };
+            self.layer.append_path_with_name(&source, &target)?;
+        }
+        self.copied = true;
+
+        Ok(())
+    }
+
+    /// Stores a layer and records it in the image config.
+    fn add_layer(&mut self, layer: tar::Builder<GzEncoder<Vec<u8>>>) -> io::Result<()> {
+        let compressed = layer.into_inner()?.finish()?;
+
+        // The config refers to the uncompressed contents of a layer
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+        decoder.write_all(&compressed)?;
+        let diff_id = store::digest(&decoder.finish()?);
+
+        self.store.write_blob(&compressed)?;
+        self.record_layer(Descriptor::new(image::OCI_LAYER_GZIP, &compressed), diff_id);
+
+        Ok(())
+    }
+
+    /// Adds a tarball of a root filesystem as a layer of the image.
+    /// The tarball is stored as it is, uncompressed.
+    pub fn add_archive(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        // Reading all the entries checks it's a tarball before it's stored
+        let not_tarball = |err: io::Error| format!("Error: {} is not a tarball: {}.", path.display(), err);
+        for entry in tar::Archive::new(File::open(path)?).entries().map_err(not_tarball)? {
+            entry.map_err(not_tarball)?;
+        }
+
+        let (digest, size) = store::digest_reader(File::open(path)?)?;
+        self.store.write_blob_from(&digest, File::open(path)?)?;
+        self.record_layer(Descriptor {
+            media_type: image::OCI_LAYER.to_owned(),
+            digest: digest.clone(),
+            size,
+            platform: None,
+            annotations: Default::default(),
+        }, digest);
+
+        Ok(())
+    }
+
+    /// Appends a stored layer to the manifest and its uncompressed digest to the config.
+    fn record_layer(&mut self, layer: Descriptor, diff_id: String) {
+        self.layers.push(layer);
+
+        let diff_ids = &mut self.config["rootfs"]["diff_ids"];
+        if !diff_ids.is_array() {
+            *diff_ids = json!([]);
+        }
+        diff_ids.as_array_mut().expect("the diff ids were just made an array").push(json!(diff_id));
+    }
+
+    /// Finishes the image and tags it. Returns the digest of its manifest.
+    pub fn finish(mut self, reference: &ImageReference) -> Result<String, Box<dyn std::error::Error>> {
+        if self.copied {
+            let layer = std::mem::replace(
+                &mut self.layer,
+                tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default())),
+            );
+            self.add_layer(layer)?;
+        }
+
+        let config = serde_json::to_vec(&self.config)?;
+        self.store.write_blob(&config)?;
+
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: Some(image::OCI_MANIFEST.to_owned()),
+            artifact_type: None,
+            config: Descriptor::new(image::OCI_CONFIG, &config),
+            layers: self.layers,
+            annotations: Default::default(),
+        };
+
+        let digest = self.store.write_blob(&serde_json::to_vec(&manifest)?)?;
+        self.store.set_tag(&reference.tag_name(), &digest)?;
+
+        Ok(digest)
+    }
+}
@@ -1,15 +1,60 @@
 pub mod errors;
 pub mod container;
 pub mod daemon;
+pub mod oci;
+pub mod image;
+pub mod registry;
+pub mod store;
+pub mod jokerfile;
+pub mod signature;
+pub mod sbom;
+pub mod compose;
+pub mod schema;
+pub mod interpolation;
+pub mod client;
+pub mod transport;
+pub mod plugin;
+pub mod output;
+pub mod completions;
+pub mod scheduler;
+pub mod discovery;
+pub mod signals;
+pub mod cron;
+pub mod ui;
+pub mod systemd;
+pub mod assets;
+#[cfg(feature = "mock")]
+pub mod mock;
 
 
+use std::collections::BTreeMap;
+use std::ffi::OsString;
 use std::io;
-use std::io::{Read, Write};
-use clap::{arg, Command};
-use std::net::{IpAddr, SocketAddr, TcpStream};
-use std::str::FromStr;
-use crate::daemon::{Daemon, get_config, write_config, Requests};
-use crate::errors::AbsentHashMapKeyError;
+use std::io::{IsTerminal, Read, Write};
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command, ValueHint};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Serialize;
+use crate::client::JokerClient;
+use crate::compose::Stack;
+use crate::cron::{CronExpression, ScheduleInfo};
+use crate::container::{
+    Asset, ContainerConfig, ContainerFilter, DependencyCondition, Device, EnvVar, HealthcheckOptions, HookFailure,
+    IdMapping, Label, LocalHooks, Mount, NetworkMode, PortMapping, RestartPolicy, RunOptions, Runtime, SecretMount,
+};
+use crate::discovery::DiscoveredDaemon;
+use crate::daemon::{Attachment, Daemon, DaemonAddress, DaemonStatus, Requests, Selection};
+use crate::errors::{InvalidOptionError, JokerError};
+use crate::image::{Descriptor, Index, JokerArtifact, Manifest};
+use crate::jokerfile::{ImageBuilder, Instruction, Jokerfile};
+use crate::oci::Bundle;
+use crate::registry::{ImageReference, RegistryClient};
+use crate::sbom::Sbom;
+use crate::scheduler::Constraint;
+use crate::signature::Signature;
+use crate::store::Store;
+use crate::transport::{TcpTransport, Transport};
 
 /// The function to get the help message.
 pub fn cli() -> Command {
@@ -19,12 +64,27 @@ pub fn cli() -> Command {
         .subcommand_required(true)
         .arg_required_else_help(true)
         .allow_external_subcommands(true)
+        .arg(
+            arg!(--format <FORMAT> "How to print the results.")
+                .global(true)
+                .value_parser(PossibleValuesParser::new(["table", "json", "plain"]).try_map(|format| format.parse::<output::Format>()))
+                .default_value("table")
+        )
+        .arg(
+            arg!(--config <FILE> "The CLI config to use instead of config.cfg or ~/.config/joker/config.cfg.")
+                .global(true)
+        )
+        .arg(
+            arg!(--daemon <DAEMON_NAME> "The daemon to send the command to, a name or a host:port address, \
+            instead of the current one.")
+                .global(true)
+        )
         .subcommand(
             Command::new("add")
-                .about("Add a new daemon with custom ip and port.")
+                .about("Add a new daemon with custom host and port.")
                 .arg(arg!(<DAEMON_NAME> "The name of the daemon."))
                 .arg_required_else_help(true)
-                .arg(arg!(-i --ip <IP_ADDRESS> "The ip-address of the host."))
+                .arg(arg!(-i --ip <HOST> "The ip-address or the hostname of the host.").visible_alias("host"))
                 .arg_required_else_help(true)
                 .arg(arg!(-p --port <PORT> "The port of the host."))
                 .arg_required_else_help(true),
@@ -33,15 +93,266 @@ pub fn cli() -> Command {
             Command::new("checkout")
                 .about("Switch to a daemon.")
                 .arg(arg!(<DAEMON_NAME> "The name of the daemon to checkout."))
+                .arg(arg!(--"no-verify" "Switch without checking that the daemon answers."))
                 .arg_required_else_help(true),
         )
+        .subcommand(
+            Command::new("ping")
+                .about("Check that a daemon answers and show its state.")
+                .arg(arg!([DAEMON_NAME] "The daemon to ping, the current one by default.")),
+        )
+        .subcommand(
+            Command::new("ui")
+                .about("Show the daemons and their containers live, and stop, restart, inspect or tail them.")
+                .arg(arg!(--interval <DURATION> "How often to refresh the daemons.")
+                    .default_value("2s")
+                    .value_parser(|value: &str| container::parse_duration("--interval", value)))
+                .arg(arg!(--group <GROUP> "Show the daemons of a group instead of every daemon of the config.")),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the CLI config, the local permissions and every daemon, and suggest fixes."),
+        )
         .subcommand(
             Command::new("run")
                 .about("Run specified containers on a current daemon.")
                 .arg_required_else_help(true)
-                .arg(arg!(<CONTAINER_NAME> ... "Containers to run"))
+                .arg(arg!(<CONTAINER_NAME> ... "Containers to run: binaries with a .joker config, OCI bundle directories or pulled images")
+                    .value_hint(ValueHint::AnyPath))
+                .arg_required_else_help(true)
+                .arg(arg!(--sbom <FILE> "An SPDX or CycloneDX JSON SBOM of the container for the daemon to keep. \
+                Defaults to the .sbom.json file of a container, if any."))
+                .arg(arg!(--"cap-add" <CAPABILITY> "Add a Linux capability to the containers.")
+                    .action(ArgAction::Append))
+                .arg(arg!(--"cap-drop" <CAPABILITY> "Drop a Linux capability from the containers.")
+                    .action(ArgAction::Append))
+                .arg(arg!(--"uid-map" <MAPPING> "Map container uids to host uids, as CONTAINER_ID:HOST_ID:COUNT.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(IdMapping)))
+                .arg(arg!(--"gid-map" <MAPPING> "Map container gids to host gids, as CONTAINER_ID:HOST_ID:COUNT.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(IdMapping)))
+                .arg(arg!(-v --volume <MOUNT> "Mount a host directory, as HOST_PATH:CONTAINER_PATH[:ro|rw].")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(Mount)))
+                .arg(arg!(--asset <ASSET> "Ship a local file or directory with the container, as SOURCE:CONTAINER_PATH.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(Asset)))
+                .arg(arg!(--secret <SECRET> "Give a secret of the daemon, as NAME[:FILE_PATH|ENV_VAR].")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(SecretMount)))
+                .arg(arg!(--"read-only" "Mount the root filesystem of the containers as read-only."))
+                .arg(arg!(-p --publish <PORTS> "Publish a container port on the daemon host, as [HOST_IP:]HOST_PORT:CONTAINER_PORT[/tcp|udp].")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(PortMapping)))
+                .arg(arg!(--device <DEVICE> "Give access to a host device, as HOST_PATH[:CONTAINER_PATH][:rwm].")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(Device)))
+                .arg(arg!(--runtime <RUNTIME> "Execute binaries as native processes or as WebAssembly modules: native or wasm.")
+                    .value_parser(clap::value_parser!(Runtime)))
+                .arg(arg!(--network <NETWORK> "Connect the containers to a network: bridge, host or none.")
+                    .value_parser(clap::value_parser!(NetworkMode)))
+                .arg(arg!(--"network-alias" <ALIAS> "Add a name the containers can be reached by on the bridge network.")
+                    .action(ArgAction::Append))
+                .arg(arg!(-e --env <VARIABLE> "Set an environment variable, as KEY=VALUE or KEY to take the local value.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(EnvVar)))
+                .arg(arg!(--"env-file" <PATH> "Read environment variables from a file of KEY=VALUE lines.")
+                    .action(ArgAction::Append))
+                .arg(arg!(-l --label <LABEL> "Label the containers, as KEY=VALUE, to find them with ps --filter.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(Label)))
+                .arg(arg!(--restart <POLICY> "Restart the containers on exit: no, always or on-failure[:MAX_RETRIES].")
+                    .value_parser(clap::value_parser!(RestartPolicy)))
+                .arg(arg!(--"restart-backoff" <DURATION> "The delay before the first restart, doubled after every restart in a row.")
+                    .value_parser(|value: &str| container::parse_duration("--restart-backoff", value)))
+                .arg(arg!(--timeout <DURATION> "Kill the containers once they have run for this long, \
+                e.g. 30m, and mark them as timed out.")
+                    .value_parser(|value: &str| container::parse_duration("--timeout", value)))
+                .arg(arg!(--"health-cmd" <COMMAND> "The command which checks the health of the containers."))
+                .arg(arg!(--"health-interval" <DURATION> "The time between the health checks.")
+                    .value_parser(|value: &str| container::parse_duration("--health-interval", value)))
+                .arg(arg!(--"health-timeout" <DURATION> "The time after which a health check fails.")
+                    .value_parser(|value: &str| container::parse_duration("--health-timeout", value)))
+                .arg(arg!(--"health-retries" <COUNT> "The number of failed health checks in a row before a container is unhealthy.")
+                    .value_parser(clap::value_parser!(u32)))
+                .arg(arg!(--"no-healthcheck" "Disable the health check of the config.")
+                    .conflicts_with("health-cmd"))
+                .arg(arg!(-d --detach "Return once the containers are sent instead of following their output until they exit. \
+                Runs with --all, --group or --replicas are always detached."))
+                .arg(arg!(--watch "Keep watching the binaries and their configs, and run them again when they change.")
+                    .conflicts_with_all(["sbom", "all", "group"]))
+                .args(broadcast_args("Run the containers"))
+                .arg(arg!(--schedule "Run the containers on the daemon with the most free memory and CPUs, \
+                of the config or of the group.")
+                    .conflicts_with_all(["all", "daemon", "watch"]))
+                .arg(arg!(--constraint <LABEL> "Schedule only on daemons with a label, e.g. env=prod. Can be repeated.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(Constraint))
+                    .requires("schedule"))
+                .arg(arg!(--replicas <COUNT> "Run replicas of each container named <name>-<index>, \
+                spread across the daemons of --all or --group.")
+                    .value_parser(clap::value_parser!(u32).range(1..))
+                    .conflicts_with_all(["watch", "schedule"]))
+                .arg(arg!(--"dry-run" "Check the containers and print what would be sent to which daemon, \
+                without connecting to it.")
+                    .conflicts_with_all(["watch", "schedule", "replicas"])),
+        )
+        .subcommand(
+            Command::new("pull")
+                .about("Pull an image from an OCI registry into the local store.")
+                .arg(arg!(<IMAGE> "The image to pull, e.g. alpine:3.19 or ghcr.io/owner/app:1.0."))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("push")
+                .about("Push an image to an OCI registry.")
+                .arg(arg!(<IMAGE> "The name and the tag to push as, e.g. ghcr.io/owner/app:1.0."))
+                .arg(arg!([CONTAINER_PATH] "A binary with a .joker config to publish. \
+                Pushes the image of the local store by default."))
+                .arg_required_else_help(true)
+                .arg(arg!(--sbom <FILE> "An SPDX or CycloneDX JSON SBOM to publish with the binary. \
+                Defaults to its .sbom.json file, if any.")),
+        )
+        .subcommand(
+            Command::new("build")
+                .about("Build an image in the local store from a Jokerfile.")
+                .arg(arg!(-t --tag <IMAGE> "The name and the tag of the image, e.g. app:1.0.").required(true))
+                .arg(arg!(-f --file <JOKERFILE> "The recipe of the image. Defaults to the Jokerfile of the context."))
+                .arg(arg!([CONTEXT] "The directory the files are copied from.").default_value("."))
                 .arg_required_else_help(true),
         )
+        .subcommand(
+            Command::new("images")
+                .about("List the images of the local store.")
+                .arg(
+                    arg!(--remote [DAEMON_NAME] "List the images of a daemon instead, the current one without a name.")
+                        .require_equals(true)
+                        .default_missing_value("")
+                ),
+        )
+        .subcommand(
+            Command::new("rmi")
+                .about("Remove images from the local store.")
+                .arg(arg!(<IMAGE> ... "The images to remove."))
+                .arg_required_else_help(true)
+                .arg(
+                    arg!(--remote [DAEMON_NAME] "Remove the images from a daemon instead, the current one without a name.")
+                        .require_equals(true)
+                        .default_missing_value("")
+                ),
+        )
+        .subcommand(
+            Command::new("commit")
+                .about("Save the changes of a container as a new image of the daemon's store.")
+                .arg(arg!(<CONTAINER_NAME> "The container to commit."))
+                .arg(arg!(<IMAGE> "The name and the tag of the new image, e.g. app:debug."))
+                .arg_required_else_help(true)
+                .arg(arg!(--pull "Download the image into the local store too.")),
+        )
+        .subcommand(
+            Command::new("checkpoint")
+                .about("Freeze a container to the disk of the daemon with CRIU.")
+                .arg(arg!(<CONTAINER_NAME> "The container to checkpoint."))
+                .arg_required_else_help(true)
+                .arg(arg!(--name <CHECKPOINT> "The name of the checkpoint. Defaults to the name of the container."))
+                .arg(arg!(--"leave-running" "Keep the container running after the checkpoint."))
+                .arg(arg!(-o --output <FILE> "Download the checkpoint archive, e.g. to restore it on another daemon.")),
+        )
+        .subcommand(
+            Command::new("restore")
+                .about("Restore a container from a checkpoint.")
+                .arg(arg!(<CHECKPOINT> "A checkpoint of the daemon, or a checkpoint archive to upload."))
+                .arg_required_else_help(true)
+                .arg(arg!(--name <CONTAINER_NAME> "The name of the restored container. \
+                Defaults to the name of the checkpointed one.")),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the root filesystem of a container as a tarball. Uses stdout by default.")
+                .arg(arg!(<CONTAINER_NAME> "The container to export."))
+                .arg_required_else_help(true)
+                .arg(arg!(-o --output <FILE> "The file to write the tarball to.")),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Create an image of the local store from a tarball of a root filesystem.")
+                .arg(arg!(<TARBALL> "The tarball, e.g. made by joker export."))
+                .arg(arg!(<IMAGE> "The name and the tag of the new image, e.g. app:imported."))
+                .arg_required_else_help(true)
+                .arg(arg!(--entrypoint <COMMAND> "The command the image runs, as words separated by spaces.")),
+        )
+        .subcommand(
+            Command::new("save")
+                .about("Save images of the local store to an archive. Uses stdout by default.")
+                .arg(arg!(<IMAGE> ... "The images to save."))
+                .arg_required_else_help(true)
+                .arg(arg!(-o --output <FILE> "The file to write the archive to.")),
+        )
+        .subcommand(
+            Command::new("load")
+                .about("Load the images of an archive into the local store. Uses stdin by default.")
+                .arg(arg!(-i --input <FILE> "The archive to read, e.g. made by joker save.")),
+        )
+        .subcommand(
+            Command::new("keygen")
+                .about("Generate an ed25519 key to sign containers with.")
+                .arg(arg!(--key <FILE> "The file of the key. Its public key is written next to it, with .pub.")
+                    .default_value(signature::KEY_PATH)),
+        )
+        .subcommand(
+            Command::new("sign")
+                .about("Sign a binary or an image of the local store.")
+                .arg(arg!(<TARGET> "A binary, or the reference of an image."))
+                .arg_required_else_help(true)
+                .arg(arg!(--key <FILE> "The key to sign with.").default_value(signature::KEY_PATH)),
+        )
+        .subcommand(
+            Command::new("trust")
+                .about("Manage the keys a current daemon trusts the signatures of.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Trust a public key.")
+                        .arg(arg!(<PUBLIC_KEY> "A public key file, e.g. joker.pub, or the key itself."))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the trusted keys.")
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Stop trusting a key.")
+                        .arg(arg!(<KEY_ID> "The identifier of the key, as listed."))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("up")
+                .about("Deploy the containers of a compose file as a stack.")
+                .arg(arg!(-f --file <FILE> "The compose file.").default_value(compose::DEFAULT_COMPOSE_FILE))
+                .arg(arg!(--"dry-run" "Check the services and print what would be sent to the daemon, \
+                without connecting to it.")),
+        )
+        .subcommand(
+            Command::new("down")
+                .about("Stop and remove the containers of a stack.")
+                .arg(arg!(-f --file <FILE> "The compose file the stack is named by.")
+                    .default_value(compose::DEFAULT_COMPOSE_FILE))
+                .arg(arg!(--name <STACK> "The name of the stack, instead of a compose file.")),
+        )
+        .subcommand(
+            Command::new("stack")
+                .about("Manage the stacks of a current daemon.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("ls")
+                        .about("List the stacks with their containers.")
+                ),
+        )
         .subcommand(
             Command::new("trace")
                 .about("Trace the events on the daemon. Uses stdout by default.")
@@ -51,6 +362,71 @@ pub fn cli() -> Command {
                 .about("Get the output of the specified container.")
                 .arg(arg!(<CONTAINER_NAME> "The name of the container to get logs from. \
                 Uses stdout by default"))
+                .args(broadcast_args("Get the logs"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("stop")
+                .about("Stop containers of a current daemon.")
+                .arg(arg!(<CONTAINER_NAME> ... "The containers to stop."))
+                .args(broadcast_args("Stop the containers"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("restart")
+                .about("Restart containers of a current daemon.")
+                .arg(arg!(<CONTAINER_NAME> ... "The containers to restart."))
+                .args(broadcast_args("Restart the containers"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("scale")
+                .about("Run or remove replicas of a container until there are as many as given.")
+                .arg(arg!(<CONTAINER_NAME> "The container the replicas are run from: a binary with a .joker config, \
+                an OCI bundle directory or a pulled image.")
+                    .value_hint(ValueHint::AnyPath))
+                .arg(arg!(<REPLICAS> "The number of replicas to keep, 0 removes all of them.")
+                    .value_parser(clap::value_parser!(u32)))
+                .args(broadcast_args("Spread the replicas"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("ps")
+                .about("List the containers of a current daemon.")
+                .arg(arg!(-f --filter <FILTER> "Only list the containers with a label or a status: \
+                label=KEY=VALUE, label=KEY or status=STATUS. Can be repeated.")
+                    .action(ArgAction::Append)
+                    .value_parser(clap::value_parser!(ContainerFilter))),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Write a starter .joker config next to a binary.")
+                .arg(arg!([NAME] "The binary to write the config for. Defaults to the name of the current directory."))
+                .arg(arg!(--jokerfile "Write a Jokerfile which copies the binary into an image too."))
+                .arg(arg!(--compose "Write a compose file with the binary as its only service too."))
+                .arg(arg!(--force "Overwrite the files which already exist.")),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check a .joker config without sending anything to a daemon.")
+                .arg(arg!(<PATH> "A .joker config or the container it belongs to."))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Show the state of the specified container.")
+                .arg(arg!(<CONTAINER_NAME> "The name of the container to inspect."))
+                .arg_required_else_help(true)
+                .arg(arg!(--sbom "Print the SBOM the container was run with instead.")),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Show the CPU, memory and IO usage of a container.")
+                .arg(arg!(<CONTAINER_NAME> "The name of the container."))
+                .arg(arg!(--history <DURATION> "Show the samples the daemon kept over this time, e.g. 24h, \
+                instead of the latest one.")
+                    .value_parser(|value: &str| container::parse_duration("--history", value)))
+                .arg(arg!(--export <FILE> "Write the samples to a CSV file instead of printing them."))
                 .arg_required_else_help(true),
         )
         .subcommand(
@@ -59,33 +435,216 @@ pub fn cli() -> Command {
                 .arg(arg!(<CONFIG_PATH> "The config file to send."))
                 .arg_required_else_help(true),
         )
+        .subcommand(
+            Command::new("daemon")
+                .about("Manage the daemons of the CLI config.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("List the daemons with their labels and groups.")
+                )
+                .subcommand(
+                    Command::new("label")
+                        .about("Set or remove the labels of a daemon.")
+                        .arg(arg!(<DAEMON_NAME> "The name of the daemon."))
+                        .arg(arg!(<LABEL> ... "KEY=VALUE to set a label, e.g. env=prod, or KEY- to remove it."))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("group")
+                        .about("Add daemons to a group, creating it if needed.")
+                        .arg(arg!(<GROUP> "The name of the group."))
+                        .arg(arg!(<DAEMON_NAME> ... "The daemons to add."))
+                        .arg(arg!(--remove "Remove the daemons from the group instead, \
+                        and the group itself once it's empty."))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("discover")
+                        .about("Find the daemons advertising themselves on the local network and offer to add them.")
+                        .arg(arg!(--timeout <DURATION> "How long to wait for the daemons to answer.")
+                            .value_parser(|value: &str| container::parse_duration("--timeout", value))
+                            .default_value("2s"))
+                        .arg(arg!(-y --yes "Add every new daemon without asking.")),
+                )
+                .subcommand(
+                    Command::new("install-service")
+                        .about("Write a systemd unit which runs a daemon on this host.")
+                        .arg(arg!(--executable <PATH> "The daemon executable the unit runs.")
+                            .required(true)
+                            .value_hint(ValueHint::ExecutablePath))
+                        .arg(arg!([DAEMON_ARGS] ... "The arguments of the daemon, after --.")
+                            .last(true))
+                        .arg(arg!(--user "Write a unit for the systemd manager of the user, \
+                        for a rootless daemon."))
+                        .arg(arg!(-o --output <FILE> "The file to write the unit to, - for the standard output. \
+                        Defaults to the directory systemd loads the units of the administrator from."))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("volume")
+                .about("Manage the volumes of a current daemon.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a named volume.")
+                        .arg(arg!(<VOLUME_NAME> "The name of the volume."))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the volumes of the daemon.")
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a named volume with all its data.")
+                        .arg(arg!(<VOLUME_NAME> "The name of the volume."))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("plugins")
+                .about("List the external subcommands: joker-<name> executables on the PATH.")
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a completion script for a shell.")
+                .arg(arg!(<SHELL> "The shell to complete the commands in.")
+                    .value_parser(value_parser!(clap_complete::Shell)))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("__complete")
+                .about("Print the names of daemons or containers for the completion scripts.")
+                .hide(true)
+                .arg(arg!([WORDS] ... "The words after joker, the last one being completed.")
+                    .allow_hyphen_values(true)
+                    .trailing_var_arg(true)),
+        )
+        .subcommand(
+            Command::new("schedule")
+                .about("Run containers of a current daemon on a schedule.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("add")
+                        .about("Run a container the daemon has on a cron expression.")
+                        .arg(arg!(<CRON> "When to run it: minute hour day month weekday, e.g. \"0 3 * * *\", or @daily.")
+                            .value_parser(clap::value_parser!(CronExpression)))
+                        .arg(arg!(<CONTAINER_NAME> "The container to run."))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the schedules with their last and next runs.")
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a schedule.")
+                        .arg(arg!(<SCHEDULE_ID> "The id of the schedule."))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("secret")
+                .about("Manage the secrets of a current daemon.")
+                .subcommand_required(true)
+                .arg_required_else_help(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Create a secret from a file or from the standard input.")
+                        .arg(arg!(<SECRET_NAME> "The name of the secret."))
+                        .arg(arg!([FILE] "The file holding the secret, - for the standard input.").default_value("-"))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the secrets of the daemon, without their values.")
+                )
+                .subcommand(
+                    Command::new("rm")
+                        .about("Remove a secret.")
+                        .arg(arg!(<SECRET_NAME> "The name of the secret."))
+                        .arg_required_else_help(true),
+                ),
+        )
+}
+
+/// The `--all` and `--group` options of the commands which can run on several daemons at once.
+fn broadcast_args(action: &str) -> [Arg; 2] {
+    [
+        Arg::new("all")
+            .long("all")
+            .action(ArgAction::SetTrue)
+            .help(format!("{} on every daemon of the config.", action))
+            .conflicts_with_all(["group", "daemon"]),
+        Arg::new("group")
+            .long("group")
+            .value_name("GROUP")
+            .help(format!("{} on every daemon of a group.", action))
+            .conflicts_with("daemon"),
+    ]
 }
 
 /// Entry function which executes cli commands.
 /// It parses the command and its arguments and then calls a
 /// corresponding Rust function.
-pub fn execute(command: &mut Command) -> Result<(), Box<dyn std::error::Error>> {
+pub fn execute(command: &mut Command) -> Result<(), JokerError> {
     let matches = command.clone().get_matches();
+    if let Some(&format) = matches.get_one::<output::Format>("format") {
+        output::set_format(format);
+    }
+    let selection = Selection {
+        config: matches.get_one::<String>("config").map(PathBuf::from),
+        daemon: matches.get_one::<String>("daemon").cloned(),
+    };
+    execute_subcommand(command, &matches, &selection).map_err(JokerError::from)
+}
+
+/// Calls the Rust function of a subcommand.
+fn execute_subcommand(command: &mut Command, matches: &ArgMatches, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
     match matches.subcommand() {
         Some(("add", sub_matches)) => {
             let daemon_name = sub_matches.get_one::<String>("DAEMON_NAME").expect("Daemon name is required, but not provided.");
             let ip_addr = sub_matches.get_one::<String>("ip").expect("IP address is required, but not provided.");
             let port = sub_matches.get_one::<String>("port").expect("Port number is required, but not provided.");
 
-            match add_daemon(daemon_name, ip_addr, port) {
+            match add_daemon(daemon_name, ip_addr, port, selection) {
                 Ok(_) => {
                     Ok(())
                 }
                 Err(err) => {
-                    println!("Error while adding daemon: {}", err);
-                    Err(err)
+                    status!("Error while adding daemon: {}", err);
+                    Err(err.into())
                 }
             }
         }
         Some(("checkout", sub_matches)) => {
             let daemon_name = sub_matches.get_one::<String>("DAEMON_NAME").expect("required");
 
-            checkout_daemon(daemon_name)
+            Ok(checkout_daemon(daemon_name, !sub_matches.get_flag("no-verify"), selection)?)
+        }
+        Some(("ping", sub_matches)) => {
+            Ok(ping_daemon(sub_matches.get_one::<String>("DAEMON_NAME"), selection)?)
+        }
+        Some(("ui", sub_matches)) => {
+            let config = selection.get_config()?;
+            let daemons = match sub_matches.get_one::<String>("group") {
+                Some(group) => config.group(group)?,
+                None => config.all_daemons(),
+            };
+            let clients = daemons.into_iter()
+                .map(|daemon| JokerClient::new(&daemon.name, daemon.socket_address)
+                    .with_transport(TcpTransport { timeout: Some(VERIFY_TIMEOUT) }))
+                .collect();
+
+            Ok(ui::run(clients, *sub_matches.get_one::<Duration>("interval").expect("default"))?)
+        }
+        Some(("doctor", _)) => {
+            Ok(doctor(selection)?)
         }
         Some(("run", sub_matches)) => {
             let containers = sub_matches
@@ -94,201 +653,2846 @@ pub fn execute(command: &mut Command) -> Result<(), Box<dyn std::error::Error>>
                 .flatten()
                 .map(|x| x.as_str())
                 .collect::<Vec<_>>();
+            let options = get_run_options(sub_matches)?;
+            let sbom = sub_matches.get_one::<String>("sbom");
+
+            if sub_matches.get_flag("dry-run") {
+                let daemons = match broadcast_clients(sub_matches, selection)? {
+                    Some(clients) => clients.iter().map(|client| client.daemon().clone()).collect(),
+                    None => vec![selection.current_daemon()?],
+                };
+                let prepared = prepare_containers(&containers, &options, sbom.map(Path::new))?;
+                return print_dry_run(&daemons, &prepared);
+            }
+            if let Some(&replicas) = sub_matches.get_one::<u32>("replicas") {
+                let clients = match broadcast_clients(sub_matches, selection)? {
+                    Some(clients) => clients,
+                    None => vec![JokerClient::from_selection(selection)?],
+                };
+                for container in containers {
+                    scale_replicas(&clients, container, replicas, &options, sbom.map(Path::new))?;
+                }
+                return Ok(());
+            }
+            if sub_matches.get_flag("schedule") {
+                let constraints = get_all::<Constraint>(sub_matches, "constraint");
+                let client = schedule_daemon(sub_matches.get_one::<String>("group"), &constraints, selection)?;
+                let names = run_containers(&client, &containers, &options, sbom.map(Path::new))?;
+                return match sub_matches.get_flag("detach") {
+                    true => Ok(()),
+                    false => Ok(attach_containers(&client, &names)?),
+                };
+            }
+            if let Some(clients) = broadcast_clients(sub_matches, selection)? {
+                let results = client::broadcast(&clients, |client| client.run(&containers, &options, sbom.map(Path::new)));
+                return Ok(report_broadcast("run", &clients, results)?);
+            }
 
-            run_containers(&containers)
+            let client = JokerClient::from_selection(selection)?;
+            let names = run_containers(&client, &containers, &options, sbom.map(Path::new))?;
+            match (sub_matches.get_flag("watch"), sub_matches.get_flag("detach")) {
+                (true, _) => watch_containers(&containers, &options, selection),
+                (false, true) => Ok(()),
+                (false, false) => Ok(attach_containers(&client, &names)?),
+            }
+        }
+        Some(("pull", sub_matches)) => {
+            let image = sub_matches.get_one::<String>("IMAGE")
+                .ok_or("Image should be provided")?;
+            pull_image(image)
+        }
+        Some(("push", sub_matches)) => {
+            let image = sub_matches.get_one::<String>("IMAGE")
+                .ok_or("Image should be provided")?;
+            let container = sub_matches.get_one::<String>("CONTAINER_PATH");
+            let sbom = sub_matches.get_one::<String>("sbom");
+            push_image(image, container.map(String::as_str), sbom.map(Path::new))
+        }
+        Some(("build", sub_matches)) => {
+            let image = sub_matches.get_one::<String>("tag")
+                .ok_or("Image should be provided")?;
+            let context = sub_matches.get_one::<String>("CONTEXT")
+                .ok_or("Context should be provided")?;
+            let file = sub_matches.get_one::<String>("file");
+            build_image(image, Path::new(context), file.map(Path::new))
+        }
+        Some(("images", sub_matches)) => {
+            let remote = remote_selection(sub_matches, selection);
+            list_images(remote.is_some(), remote.as_ref().unwrap_or(selection))
+        }
+        Some(("rmi", sub_matches)) => {
+            let images = get_all::<String>(sub_matches, "IMAGE");
+            let remote = remote_selection(sub_matches, selection);
+            remove_images(&images, remote.is_some(), remote.as_ref().unwrap_or(selection))
+        }
+        Some(("commit", sub_matches)) => {
+            let container_name = sub_matches.get_one::<String>("CONTAINER_NAME")
+                .ok_or("Container name should be provided")?;
+            let image = sub_matches.get_one::<String>("IMAGE")
+                .ok_or("Image should be provided")?;
+            commit_container(container_name, image, sub_matches.get_flag("pull"), selection)
+        }
+        Some(("checkpoint", sub_matches)) => {
+            let container_name = sub_matches.get_one::<String>("CONTAINER_NAME")
+                .ok_or("Container name should be provided")?;
+            let checkpoint_name = sub_matches.get_one::<String>("name").unwrap_or(container_name);
+            let output = sub_matches.get_one::<String>("output");
+            checkpoint_container(
+                container_name,
+                checkpoint_name,
+                sub_matches.get_flag("leave-running"),
+                output.map(Path::new),
+                selection,
+            )
+        }
+        Some(("restore", sub_matches)) => {
+            let checkpoint = sub_matches.get_one::<String>("CHECKPOINT")
+                .ok_or("Checkpoint should be provided")?;
+            let container_name = sub_matches.get_one::<String>("name");
+            restore_container(checkpoint, container_name.map(String::as_str), selection)
+        }
+        Some(("export", sub_matches)) => {
+            let container_name = sub_matches.get_one::<String>("CONTAINER_NAME")
+                .ok_or("Container name should be provided")?;
+            let output = sub_matches.get_one::<String>("output");
+            export_container(container_name, output.map(Path::new), selection)
+        }
+        Some(("import", sub_matches)) => {
+            let tarball = sub_matches.get_one::<String>("TARBALL")
+                .ok_or("Tarball should be provided")?;
+            let image = sub_matches.get_one::<String>("IMAGE")
+                .ok_or("Image should be provided")?;
+            let entrypoint = sub_matches.get_one::<String>("entrypoint");
+            import_image(Path::new(tarball), image, entrypoint.map(String::as_str))
+        }
+        Some(("save", sub_matches)) => {
+            let images = get_all::<String>(sub_matches, "IMAGE");
+            let output = sub_matches.get_one::<String>("output");
+            save_images(&images, output.map(Path::new))
+        }
+        Some(("load", sub_matches)) => {
+            let input = sub_matches.get_one::<String>("input");
+            load_images(input.map(Path::new))
+        }
+        Some(("keygen", sub_matches)) => {
+            let key = sub_matches.get_one::<String>("key")
+                .ok_or("Key should be provided")?;
+            generate_key(Path::new(key))
+        }
+        Some(("sign", sub_matches)) => {
+            let target = sub_matches.get_one::<String>("TARGET")
+                .ok_or("Target should be provided")?;
+            let key = sub_matches.get_one::<String>("key")
+                .ok_or("Key should be provided")?;
+            sign_container(target, Path::new(key))
+        }
+        Some(("trust", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("add", trust_matches)) => {
+                    let public_key = trust_matches.get_one::<String>("PUBLIC_KEY")
+                        .ok_or("Public key should be provided")?;
+                    manage_trust(Requests::TrustAdd, Some(public_key), selection)
+                }
+                Some(("list", _)) => {
+                    manage_trust(Requests::TrustList, None, selection)
+                }
+                Some(("rm", trust_matches)) => {
+                    let key_id = trust_matches.get_one::<String>("KEY_ID")
+                        .ok_or("Key id should be provided")?;
+                    manage_trust(Requests::TrustRemove, Some(key_id), selection)
+                }
+                _ => show_help_message(command),
+            }
+        }
+        Some(("init", sub_matches)) => {
+            let name = sub_matches.get_one::<String>("NAME");
+            init_container(
+                name.map(String::as_str),
+                sub_matches.get_flag("jokerfile"),
+                sub_matches.get_flag("compose"),
+                sub_matches.get_flag("force"),
+            )
+        }
+        Some(("validate", sub_matches)) => {
+            let path = sub_matches.get_one::<String>("PATH").ok_or("Config path should be provided")?;
+            validate_config(path)
+        }
+        Some(("up", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("file")
+                .ok_or("Compose file should be provided")?;
+            stack_up(Path::new(file), sub_matches.get_flag("dry-run"), selection)
+        }
+        Some(("down", sub_matches)) => {
+            let name = match sub_matches.get_one::<String>("name") {
+                Some(name) => name.clone(),
+                None => {
+                    let file = sub_matches.get_one::<String>("file")
+                        .ok_or("Compose file should be provided")?;
+                    Stack::open(Path::new(file))?.name
+                }
+            };
+            stack_down(&name, selection)
+        }
+        Some(("stack", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("ls", _)) => {
+                    list_stacks(selection)
+                }
+                _ => show_help_message(command),
+            }
         }
         Some(("trace", _)) => {
-            daemon_trace()
+            daemon_trace(selection)
         }
         Some(("logs", sub_matches)) => {
             let container = sub_matches.get_one::<String>("CONTAINER_NAME")
                 .ok_or("Container name should be provided")?;
-            get_logs(container)
+            match broadcast_clients(sub_matches, selection)? {
+                Some(clients) => Ok(print_broadcast_logs(&clients, container)?),
+                None => get_logs(container, selection),
+            }
+        }
+        Some(("stop", sub_matches)) => {
+            let containers = get_all::<String>(sub_matches, "CONTAINER_NAME");
+            if let Some(clients) = broadcast_clients(sub_matches, selection)? {
+                let results = client::broadcast(&clients, |client| {
+                    containers.iter().try_for_each(|container_name| client.stop(container_name))
+                });
+                return Ok(report_broadcast("stop", &clients, results)?);
+            }
+
+            stop_containers(&containers, selection)
+        }
+        Some(("restart", sub_matches)) => {
+            let containers = get_all::<String>(sub_matches, "CONTAINER_NAME");
+            if let Some(clients) = broadcast_clients(sub_matches, selection)? {
+                let results = client::broadcast(&clients, |client| {
+                    containers.iter().try_for_each(|container_name| client.restart(container_name))
+                });
+                return Ok(report_broadcast("restart", &clients, results)?);
+            }
+
+            restart_containers(&containers, selection)
+        }
+        Some(("scale", sub_matches)) => {
+            let container = sub_matches.get_one::<String>("CONTAINER_NAME").expect("required");
+            let replicas = *sub_matches.get_one::<u32>("REPLICAS").expect("required");
+            let clients = match broadcast_clients(sub_matches, selection)? {
+                Some(clients) => clients,
+                None => vec![JokerClient::from_selection(selection)?],
+            };
+
+            Ok(scale_replicas(&clients, container, replicas, &RunOptions::default(), None)?)
+        }
+        Some(("ps", sub_matches)) => {
+            list_containers(&get_all::<ContainerFilter>(sub_matches, "filter"), selection)
+        }
+        Some(("inspect", sub_matches)) => {
+            let container = sub_matches.get_one::<String>("CONTAINER_NAME")
+                .ok_or("Container name should be provided")?;
+            match sub_matches.get_flag("sbom") {
+                true => print_sbom(container, selection),
+                false => inspect_container(container, selection),
+            }
+        }
+        Some(("stats", sub_matches)) => {
+            let container = sub_matches.get_one::<String>("CONTAINER_NAME").expect("required");
+            let history = sub_matches.get_one::<Duration>("history").copied();
+            let export = sub_matches.get_one::<String>("export").map(Path::new);
+            Ok(show_stats(container, history, export, selection)?)
         }
         Some(("send", sub_matches)) => {
             let config = sub_matches.get_one::<String>("CONFIG_PATH")
                 .ok_or("Path should be provided")?;
-            send_config(config)
+            send_config(config, selection)
+        }
+        Some(("daemon", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("list", _)) => {
+                    list_daemons(selection)
+                }
+                Some(("label", daemon_matches)) => {
+                    let daemon_name = daemon_matches.get_one::<String>("DAEMON_NAME")
+                        .ok_or("Daemon name should be provided")?;
+                    Ok(label_daemon(daemon_name, &get_all::<String>(daemon_matches, "LABEL"), selection)?)
+                }
+                Some(("group", daemon_matches)) => {
+                    let group = daemon_matches.get_one::<String>("GROUP")
+                        .ok_or("Group name should be provided")?;
+                    let daemons = get_all::<String>(daemon_matches, "DAEMON_NAME");
+                    Ok(group_daemons(group, &daemons, daemon_matches.get_flag("remove"), selection)?)
+                }
+                Some(("discover", daemon_matches)) => {
+                    let timeout = *daemon_matches.get_one::<Duration>("timeout").expect("default");
+                    Ok(discover_daemons(timeout, daemon_matches.get_flag("yes"), selection)?)
+                }
+                Some(("install-service", daemon_matches)) => {
+                    let output = daemon_matches.get_one::<String>("output");
+                    let executable = daemon_matches.get_one::<String>("executable")
+                        .ok_or("Daemon executable should be provided")?;
+                    let args = get_all::<String>(daemon_matches, "DAEMON_ARGS");
+                    install_service(Path::new(executable), &args, daemon_matches.get_flag("user"), output.map(Path::new))
+                }
+                _ => show_help_message(command),
+            }
+        }
+        Some(("volume", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("create", volume_matches)) => {
+                    let volume = volume_matches.get_one::<String>("VOLUME_NAME")
+                        .ok_or("Volume name should be provided")?;
+                    manage_volume(Requests::VolumeCreate, Some(volume), selection)
+                }
+                Some(("list", _)) => {
+                    manage_volume(Requests::VolumeList, None, selection)
+                }
+                Some(("rm", volume_matches)) => {
+                    let volume = volume_matches.get_one::<String>("VOLUME_NAME")
+                        .ok_or("Volume name should be provided")?;
+                    manage_volume(Requests::VolumeRemove, Some(volume), selection)
+                }
+                _ => show_help_message(command),
+            }
+        }
+        Some(("schedule", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("add", schedule_matches)) => {
+                    let expression = schedule_matches.get_one::<CronExpression>("CRON").expect("required");
+                    let container = schedule_matches.get_one::<String>("CONTAINER_NAME").expect("required");
+                    Ok(add_schedule(expression, container, selection)?)
+                }
+                Some(("list", _)) => {
+                    Ok(list_schedules(selection)?)
+                }
+                Some(("rm", schedule_matches)) => {
+                    let id = schedule_matches.get_one::<String>("SCHEDULE_ID").expect("required");
+                    JokerClient::from_selection(selection)?.unschedule(id)?;
+                    status!("Removed schedule {}.", id);
+                    Ok(())
+                }
+                _ => show_help_message(command),
+            }
+        }
+        Some(("secret", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("create", secret_matches)) => {
+                    let secret = secret_matches.get_one::<String>("SECRET_NAME")
+                        .ok_or("Secret name should be provided")?;
+                    let file = secret_matches.get_one::<String>("FILE")
+                        .ok_or("Secret file should be provided")?;
+                    let value = match file.as_str() {
+                        "-" => {
+                            let mut value = Vec::new();
+                            io::stdin().read_to_end(&mut value)?;
+                            value
+                        }
+                        path => std::fs::read(path)?,
+                    };
+                    manage_secret(Requests::SecretCreate, Some(secret), Some(&value), selection)
+                }
+                Some(("list", _)) => {
+                    manage_secret(Requests::SecretList, None, None, selection)
+                }
+                Some(("rm", secret_matches)) => {
+                    let secret = secret_matches.get_one::<String>("SECRET_NAME")
+                        .ok_or("Secret name should be provided")?;
+                    manage_secret(Requests::SecretRemove, Some(secret), None, selection)
+                }
+                _ => show_help_message(command),
+            }
+        }
+        Some(("plugins", _)) => {
+            list_plugins()
+        }
+        Some(("completions", sub_matches)) => {
+            let &shell = sub_matches.get_one::<clap_complete::Shell>("SHELL").expect("Shell is required, but not provided.");
+            completions::generate(shell, &mut cli(), &mut io::stdout())?;
+            Ok(())
+        }
+        Some(("__complete", sub_matches)) => {
+            complete_names(command, &get_all::<String>(sub_matches, "WORDS"), selection)
+        }
+        Some((name, sub_matches)) if plugin::find(name).is_some() => {
+            let args = get_all::<OsString>(sub_matches, "");
+            Ok(run_plugin(name, &args, selection)?)
         }
         _ => {
-            println!("Error: no such subcommand.");
+            status!("Error: no such subcommand.");
             show_help_message(command)
         },
     }
 }
 
 
-/// Adds a daemon with specified ip address and port.
+/// Adds a daemon with specified ip address or hostname and port.
+/// A hostname is resolved when connecting, not here.
 /// Propagates the error down the stack trace.
-fn add_daemon(daemon_name: &str, ip_addr: &str, port: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn add_daemon(daemon_name: &str, host: &str, port: &str, selection: &Selection) -> Result<(), JokerError> {
     // TODO: add checking whether we modify current daemon
-    let mut config = daemon::get_config()?;
+    let port_number = port.parse()
+        .map_err(|_| InvalidOptionError { option: "port".to_owned(), value: port.to_owned() })?;
+    let address = DaemonAddress::new(host, port_number)
+        .map_err(|_| InvalidOptionError { option: "ip".to_owned(), value: host.to_owned() })?;
 
-    let socket_addr = SocketAddr::new(IpAddr::from_str(ip_addr)?, port.parse()?);
+    selection.add_daemon(daemon_name, address.clone())?;
 
-    config.daemons.insert(daemon_name.to_owned(), socket_addr);
+    status!("Added daemon {} at {}.", daemon_name, address);
 
-    println!(
-        "Added daemon {} at ip {} and port {}.",
-        daemon_name,
-        ip_addr,
-        port,
-    );
+    Ok(())
+}
+
+/// How long `checkout` waits for a daemon to answer.
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(5);
 
-    write_config(&config)?;
+/// Changes current daemon to a specified one. Unless `verify` is off,
+/// the daemon has to answer a ping first.
+/// Propagates the error down the stack trace.
+fn checkout_daemon(name: &str, verify: bool, selection: &Selection) -> Result<(), JokerError> {
+    if verify {
+        let client = JokerClient::from(selection.find_daemon(name)?)
+            .with_transport(TcpTransport { timeout: Some(VERIFY_TIMEOUT) });
+        client.ping().map_err(|err| unreachable_daemon(client.daemon(), err))?;
+    }
+
+    selection.checkout(name)?;
+
+    status!(
+        "Switching to daemon {}.",
+        name,
+    );
 
     Ok(())
 }
 
-/// Changes current daemon to a specified one.
+/// The result of `joker ping`.
+#[derive(Serialize)]
+struct PingResult {
+    daemon: String,
+    socket_address: DaemonAddress,
+    latency_ms: f64,
+    #[serde(flatten)]
+    status: DaemonStatus,
+}
+
+/// Checks that a daemon answers and prints the round-trip time and its state.
 /// Propagates the error down the stack trace.
-fn checkout_daemon(name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let mut config = daemon::get_config()?;
+fn ping_daemon(name: Option<&String>, selection: &Selection) -> Result<(), JokerError> {
+    let client = match name {
+        Some(name) => JokerClient::from(selection.find_daemon(name)?),
+        None => JokerClient::from_selection(selection)?,
+    };
+    let daemon = client.daemon().clone();
 
-    match config.daemons.get(name) {
-        None => {
-            println!(
-                "Error while switching to daemon {}: no such daemon.",
-                name,
-            );
+    let (status, latency) = client.ping().map_err(|err| unreachable_daemon(&daemon, err))?;
 
-            Err(Box::new(AbsentHashMapKeyError))
-        }
-        Some(&socket_address) => {
-            let name = name.to_owned();
+    let result = PingResult {
+        daemon: daemon.name,
+        socket_address: daemon.socket_address,
+        latency_ms: latency.as_secs_f64() * 1000.0,
+        status,
+    };
+    output::print_record(&result, &[
+        ("Daemon", Some(format!("{} at {}", result.daemon, result.socket_address))),
+        ("Latency", Some(format!("{:.1} ms", result.latency_ms))),
+        ("Version", Some(result.status.version.clone()).filter(|version| !version.is_empty())),
+        ("Running containers", Some(result.status.containers_running.to_string())),
+        ("Uptime", Some(format_uptime(result.status.uptime))),
+        ("Memory", (result.status.memory_total > 0).then(|| format!(
+            "{} free of {}", format_size(result.status.memory_available), format_size(result.status.memory_total),
+        ))),
+        ("CPUs", (result.status.cpus > 0).then(|| format!(
+            "{} (load {:.2})", result.status.cpus, result.status.load_average,
+        ))),
+    ]).map_err(|err| JokerError::Other(err.into()))
+}
 
-            println!(
-                "Switching to daemon {}.",
-                name,
-            );
+/// The outcome of a check of `joker doctor`.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
 
-            let previous = config.current_daemon.clone();
-            config.current_daemon = Daemon {name, socket_address};
-            config.daemons.insert(previous.name, previous.socket_address);
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CheckStatus::Ok => write!(f, "ok"),
+            CheckStatus::Warning => write!(f, "warning"),
+            CheckStatus::Error => write!(f, "error"),
+        }
+    }
+}
 
-            write_config(&config)?;
+/// A check of `joker doctor`, with what to do about it when it didn't pass.
+#[derive(Serialize)]
+struct Check {
+    check: String,
+    status: CheckStatus,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix: Option<String>,
+}
 
-            Ok(())
-        }
+impl Check {
+    fn new(check: impl Into<String>, status: CheckStatus, detail: impl Into<String>, fix: Option<String>) -> Self {
+        Check { check: check.into(), status, detail: detail.into(), fix }
     }
 }
 
-/// Sends containers to current daemon.
+/// Checks the CLI config, the local permissions and every daemon of the config,
+/// prints a line per check and the fixes of the ones which failed.
+/// Fails if any check found an error, warnings don't.
 /// Propagates the error down the stack trace.
-fn run_containers(containers: &[&str]) -> Result<(), Box<dyn std::error::Error>> {
-    let config = get_config()?;
+fn doctor(selection: &Selection) -> Result<(), JokerError> {
+    let mut checks = Vec::new();
+
+    let path = selection.config_path();
+    let config = match std::fs::read(&path) {
+        Ok(data) => match serde_json::from_slice::<daemon::Config>(&data) {
+            Ok(config) => {
+                checks.push(Check::new("config", CheckStatus::Ok, format!("{} is valid", path.display()), None));
+                Some(config)
+            }
+            Err(err) => {
+                checks.push(Check::new("config", CheckStatus::Error, format!("{} is malformed: {}", path.display(), err), Some(format!(
+                    "Fix the JSON of {}, or move it away and add the daemons again with `joker add`.", path.display(),
+                ))));
+                None
+            }
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            checks.push(Check::new("config", CheckStatus::Error, format!("{} doesn't exist", path.display()), Some(
+                "Add a daemon with `joker add <DAEMON_NAME> --host <HOST> --port <PORT>`, or set JOKER_CONFIG.".to_owned(),
+            )));
+            None
+        }
+        Err(err) => {
+            checks.push(Check::new("config", CheckStatus::Error, format!("{} can't be read: {}", path.display(), err), Some(format!(
+                "Make {} readable by this user.", path.display(),
+            ))));
+            None
+        }
+    };
 
-    let mut tcp_stream = TcpStream::connect(config.current_daemon.socket_address)?;
-    tcp_stream.set_nodelay(true)?;
+    checks.push(check_writable("config permissions", &path));
+    if let Some(config) = &config {
+        checks.extend(check_config(config));
+    }
+    let selected = match &selection.daemon {
+        Some(name) => Some(("--daemon", name.clone(), "Give --daemon the name of a daemon of the config or a host:port address.".to_owned())),
+        None => std::env::var(daemon::DAEMON_VAR).ok().map(|name| (daemon::DAEMON_VAR, name, format!(
+            "Set {} to the name of a daemon of the config or to a host:port address, or unset it.", daemon::DAEMON_VAR,
+        ))),
+    };
+    if let Some((source, name, fix)) = selected {
+        checks.push(match selection.current_daemon() {
+            Ok(daemon) => Check::new(source, CheckStatus::Ok, format!("{} is {} at {}", source, daemon.name, daemon.socket_address), None),
+            Err(err) => Check::new(source, CheckStatus::Error, format!("{}={} can't be used: {}", source, name, err), Some(fix)),
+        });
+    }
 
-    println!("Connection established. Sending files to a daemon");
+    let store = store::store_path();
+    let mut check = check_writable("image store", &store);
+    if !store.exists() && check.status == CheckStatus::Ok {
+        check.detail = format!("{} will be created on the first pull", store.display());
+    }
+    checks.push(check);
 
-    for &container_path in containers {
+    if let Some(config) = &config {
+        let clients = config.all_daemons().into_iter()
+            .map(|daemon| JokerClient::new(&daemon.name, daemon.socket_address)
+                .with_transport(TcpTransport { timeout: Some(VERIFY_TIMEOUT) }))
+            .collect::<Vec<_>>();
+        // the errors are sorted out in the threads, before they're turned into messages
+        let results = client::broadcast(&clients, |client| Ok(check_daemon(client.daemon(), client.ping())));
+        for (client, result) in clients.iter().zip(results) {
+            checks.push(result.unwrap_or_else(|err| {
+                Check::new(format!("daemon {}", client.daemon().name), CheckStatus::Error, err, None)
+            }));
+        }
+    }
 
-        let binary_name = container_path.split('/').last()
-            .ok_or("Error: bad file path.")?.as_bytes().to_owned();
-        let binary = std::fs::read(container_path)?;
-        let binary_config = std::fs::read(format!("{}.joker", container_path))?;
+    output::print_list(&["CHECK", "STATUS", "DETAIL"], &checks, |check| vec![
+        check.check.clone(),
+        check.status.to_string(),
+        check.detail.clone(),
+    ]).map_err(|err| JokerError::Other(err.into()))?;
 
-        // Send the type of request
-        let request = Requests::Run;
-        tcp_stream.write_all(&[request as u8])?;
+    if output::format() != output::Format::Json {
+        let fixes = checks.iter()
+            .filter_map(|check| Some((&check.check, check.fix.as_ref()?)))
+            .collect::<Vec<_>>();
+        if !fixes.is_empty() {
+            println!();
+            for (check, fix) in fixes {
+                println!("{}: {}", check, fix);
+            }
+        }
+    }
 
-        // Send the size of binary name and binary name itself
-        tcp_stream.write_all(&(binary_name.len() as u64).to_le_bytes())?;
-        tcp_stream.write_all(&binary_name)?;
+    match checks.iter().filter(|check| check.status == CheckStatus::Error).count() {
+        0 => Ok(()),
+        1 => Err(JokerError::Other("Error: doctor found a problem.".into())),
+        errors => Err(JokerError::Other(format!("Error: doctor found {} problems.", errors).into())),
+    }
+}
 
-        // Send the size of the binary and the binary itself
-        tcp_stream.write_all(&(binary.len() as u64).to_le_bytes())?;
-        tcp_stream.write_all(&binary)?;
+/// Checks that a file, or the directory it would be created in, can be written by this user.
+fn check_writable(check: &str, path: &Path) -> Check {
+    let target = match path.exists() {
+        true => path,
+        // the first existing ancestor, where `create_dir_all` would start
+        false => match path.ancestors().skip(1).find(|ancestor| ancestor.as_os_str().is_empty() || ancestor.exists()) {
+            Some(ancestor) if ancestor.as_os_str().is_empty() => Path::new("."),
+            Some(ancestor) => ancestor,
+            None => Path::new("."),
+        },
+    };
 
-        // Send the size of binary config and binary config itself
-        tcp_stream.write_all(&(binary_config.len() as u64).to_le_bytes())?;
-        tcp_stream.write_all(&binary_config)?;
+    match is_writable(target) {
+        true => Check::new(check, CheckStatus::Ok, format!("{} is writable", target.display()), None),
+        false => Check::new(check, CheckStatus::Error, format!("{} isn't writable by this user", target.display()), Some(format!(
+            "Give this user write access to {}, e.g. with `chown` or `chmod u+w`.", target.display(),
+        ))),
     }
+}
 
-    println!(
-        "Running containers {} at daemon {}.",
-        containers.join(", "),
-        config.current_daemon.name,
-    );
+#[cfg(unix)]
+fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
 
-    Ok(())
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: the path is a valid NUL-terminated string
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
 }
 
-/// Prints daemon messages to a standard output.
-/// Propagates the error down the stack trace.
-fn daemon_trace() -> Result<(), Box<dyn std::error::Error>> {
-    let config = get_config()?;
+#[cfg(not(unix))]
+fn is_writable(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| !metadata.permissions().readonly())
+}
 
-    let mut tcp_stream = TcpStream::connect(config.current_daemon.socket_address)?;
-    tcp_stream.set_nodelay(true)?;
+/// Checks that the groups and the labels of the config name daemons which exist.
+fn check_config(config: &daemon::Config) -> Vec<Check> {
+    let mut checks = Vec::new();
 
-    // writing a request to a daemon
-    let request = Requests::Trace;
-    tcp_stream.write_all(&[request as u8])?;
+    for (group, names) in &config.groups {
+        let unknown = names.iter()
+            .filter(|name| config.find_daemon(name).is_none())
+            .cloned()
+            .collect::<Vec<_>>();
+        if !unknown.is_empty() {
+            checks.push(Check::new(format!("group {}", group), CheckStatus::Warning, format!("unknown daemons {}", unknown.join(", ")), Some(format!(
+                "Remove them with `joker daemon group {} {} --remove`.", group, unknown.join(" "),
+            ))));
+        }
+    }
 
-    // getting result from a daemon
-    let received_data = String::from_utf8(read_all_from_stream(&mut tcp_stream)?)?;
+    let mut labelled = config.labels.keys()
+        .filter(|name| config.find_daemon(name).is_none())
+        .cloned()
+        .collect::<Vec<_>>();
+    labelled.sort();
+    if !labelled.is_empty() {
+        checks.push(Check::new("labels", CheckStatus::Warning, format!("labels of unknown daemons {}", labelled.join(", ")), Some(
+            "Add the daemons again, or remove their labels with `joker daemon label <DAEMON_NAME> <KEY>-`.".to_owned(),
+        )));
+    }
 
-    println!("{}", received_data);
+    checks
+}
 
-    Ok(())
+/// Turns the ping of a daemon into a check, with a fix which depends on how it failed.
+fn check_daemon(daemon: &Daemon, result: Result<(DaemonStatus, Duration), JokerError>) -> Check {
+    let check = format!("daemon {}", daemon.name);
+    let version = env!("CARGO_PKG_VERSION");
+
+    match result {
+        Ok((status, _)) if status.version.is_empty() => Check::new(check, CheckStatus::Warning, format!(
+            "{} answers but doesn't report its version", daemon.socket_address,
+        ), Some(format!("Upgrade the daemon of {} to {} so that its protocol can be checked.", daemon.name, version))),
+        Ok((status, _)) if !compatible_versions(&status.version, version) => Check::new(check, CheckStatus::Warning, format!(
+            "{} runs version {}, this CLI is {}", daemon.socket_address, status.version, version,
+        ), Some(format!("Upgrade the CLI or the daemon of {} so that they speak the same protocol.", daemon.name))),
+        Ok((status, latency)) => Check::new(check, CheckStatus::Ok, format!(
+            "{} runs version {}, answered in {:.1} ms", daemon.socket_address, status.version, latency.as_secs_f64() * 1000.0,
+        ), None),
+        Err(JokerError::Connection(err)) => {
+            let fix = match err.kind() {
+                io::ErrorKind::ConnectionRefused => format!(
+                    "Start the daemon on {}, or fix its port with `joker add {} --host <HOST> --port <PORT>`.",
+                    daemon.socket_address, daemon.name,
+                ),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => format!(
+                    "Check that {} is up and that no firewall drops the port.", daemon.socket_address,
+                ),
+                _ => format!("Check the address {} with `joker add {} --host <HOST> --port <PORT>`.", daemon.socket_address, daemon.name),
+            };
+            Check::new(check, CheckStatus::Error, format!("{} is unreachable: {}", daemon.socket_address, err), Some(fix))
+        }
+        Err(JokerError::Protocol(err)) => Check::new(check, CheckStatus::Error, format!(
+            "{} answered something else than a daemon: {}", daemon.socket_address, err,
+        ), Some(format!(
+            "Check that {} is a joker daemon, and upgrade it to {} if it is.", daemon.socket_address, version,
+        ))),
+        Err(err) => Check::new(check, CheckStatus::Error, err.to_string(), None),
+    }
 }
 
-/// Receives a log of a specified container.
+/// Whether a daemon and the CLI speak the same protocol: the same major version,
+/// or the same minor one before 1.0. Versions which aren't semantic are compared as a whole.
+fn compatible_versions(daemon: &str, cli: &str) -> bool {
+    let significant = |version: &str| {
+        let mut parts = version.trim_start_matches('v').split('.');
+        match (parts.next(), parts.next()) {
+            (Some("0"), Some(minor)) => format!("0.{}", minor),
+            (Some(major), _) => major.to_owned(),
+            _ => version.to_owned(),
+        }
+    };
+
+    significant(daemon) == significant(cli)
+}
+
+/// Picks the daemon with the most free memory, and then the most idle CPUs, among
+/// the daemons of a group or of the config which have the labels of the constraints.
 /// Propagates the error down the stack trace.
-fn get_logs(container_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let config = get_config()?;
+fn schedule_daemon(group: Option<&String>, constraints: &[Constraint], selection: &Selection) -> Result<JokerClient, JokerError> {
+    let config = selection.get_config()?;
+    let daemons = match group {
+        Some(group) => config.group(group)?,
+        None => config.all_daemons(),
+    };
 
-    let mut tcp_stream = TcpStream::connect(config.current_daemon.socket_address)?;
-    tcp_stream.set_nodelay(true)?;
+    let clients = daemons.into_iter()
+        .filter(|daemon| {
+            let labels = config.labels(&daemon.name);
+            constraints.iter().all(|constraint| constraint.matches(&labels))
+        })
+        .map(|daemon| JokerClient::new(&daemon.name, daemon.socket_address)
+            .with_transport(TcpTransport { timeout: Some(VERIFY_TIMEOUT) }))
+        .collect::<Vec<_>>();
+    if clients.is_empty() {
+        return Err(JokerError::Other("Error: no daemon matches the constraints.".into()));
+    }
 
-    // writing request to a daemon
-    let request = Requests::Logs;
-    tcp_stream.write_all(&[request as u8])?;
-    // writing container name to a daemon
-    tcp_stream.write_all(&container_name.len().to_le_bytes())?;
-    tcp_stream.write_all(container_name.as_bytes())?;
+    let statuses = client::broadcast(&clients, |client| client.ping());
+    let candidates = clients.iter().zip(statuses)
+        .filter_map(|(client, status)| match status {
+            Ok((status, _)) => Some((client.daemon().clone(), status)),
+            Err(err) => {
+                eprintln!("Warning: daemon {} is skipped: {}", client.daemon().name, err);
+                None
+            }
+        })
+        .collect();
+    let (daemon, status) = scheduler::pick(candidates)
+        .ok_or_else(|| JokerError::Other("Error: none of the daemons answered.".into()))?;
 
-    // getting result from a daemon
-    let received_data = String::from_utf8(read_all_from_stream(&mut tcp_stream)?)?;
+    status!(
+        "Scheduled on daemon {} with {} of free memory and {:.1} idle CPUs.",
+        daemon.name,
+        format_size(status.memory_available),
+        scheduler::idle_cpus(&status),
+    );
 
-    println!("{}", received_data);
+    Ok(JokerClient::new(&daemon.name, daemon.socket_address))
+}
 
-    Ok(())
+/// Names the daemon in a connection error.
+fn unreachable_daemon(daemon: &Daemon, err: JokerError) -> JokerError {
+    match err {
+        JokerError::Connection(err) => JokerError::Connection(io::Error::new(
+            err.kind(),
+            format!("{} at {} is unreachable: {}", daemon.name, daemon.socket_address, err),
+        )),
+        err => err,
+    }
 }
 
-/// Sends a config file to a daemon.
+/// Collects the options of the `run` command.
 /// Propagates the error down the stack trace.
-fn send_config(config_path: &String) -> Result<(), Box<dyn std::error::Error>> {
-    let config = get_config()?;
+fn get_run_options(sub_matches: &ArgMatches) -> Result<RunOptions, Box<dyn std::error::Error>> {
+    // Variables from the command line win over the ones from env files
+    let mut env = Vec::new();
+    for path in get_all::<String>(sub_matches, "env-file") {
+        env.extend(container::parse_env_file(&std::fs::read_to_string(path)?)?);
+    }
+    env.extend(get_all::<EnvVar>(sub_matches, "env"));
+
+    Ok(RunOptions {
+        cap_add: get_all(sub_matches, "cap-add"),
+        cap_drop: get_all(sub_matches, "cap-drop"),
+        uid_maps: get_all(sub_matches, "uid-map"),
+        gid_maps: get_all(sub_matches, "gid-map"),
+        mounts: get_all(sub_matches, "volume"),
+        assets: get_all(sub_matches, "asset"),
+        secrets: get_all(sub_matches, "secret"),
+        read_only: sub_matches.get_flag("read-only"),
+        publish: get_all(sub_matches, "publish"),
+        devices: get_all(sub_matches, "device"),
+        network: sub_matches.get_one::<NetworkMode>("network").copied(),
+        network_aliases: get_all(sub_matches, "network-alias"),
+        env,
+        labels: get_all(sub_matches, "label"),
+        restart: sub_matches.get_one::<RestartPolicy>("restart").copied(),
+        restart_backoff: sub_matches.get_one::<Duration>("restart-backoff").copied(),
+        timeout: sub_matches.get_one::<Duration>("timeout").copied(),
+        healthcheck: HealthcheckOptions {
+            command: sub_matches.get_one::<String>("health-cmd").cloned(),
+            interval: sub_matches.get_one::<Duration>("health-interval").copied(),
+            timeout: sub_matches.get_one::<Duration>("health-timeout").copied(),
+            retries: sub_matches.get_one::<u32>("health-retries").copied(),
+            disable: sub_matches.get_flag("no-healthcheck"),
+        },
+        runtime: sub_matches.get_one::<Runtime>("runtime").copied(),
+        name: None,
+        stack: None,
+        depends_on: Vec::new(),
+    })
+}
+
+/// Sends containers to the daemon of a client. Returns the names the daemon knows them by.
+/// Propagates the error down the stack trace.
+fn run_containers(
+    client: &JokerClient,
+    containers: &[&str],
+    options: &RunOptions,
+    sbom: Option<&Path>,
+) -> Result<Vec<String>, JokerError> {
+    let names = client.run(containers, options, sbom)?;
+
+    status!(
+        "Running containers {} at daemon {}.",
+        containers.join(", "),
+        client.daemon().name,
+    );
+
+    Ok(names)
+}
+
+/// How often an attached `run` polls the output and the state of its containers.
+const ATTACH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Prints the output of containers as it comes until all of them exit, by polling their
+/// state and the logs they wrote since the last poll. With several containers every line starts with the name of its
+/// container. Ctrl-C, SIGTERM and terminal resizes are forwarded to the containers still
+/// running. Fails with the exit code of the first container which failed.
+/// Propagates the error down the stack trace.
+fn attach_containers(client: &JokerClient, names: &[String]) -> Result<(), JokerError> {
+    let _forwarding = signals::forward();
+    let mut offsets = vec![0; names.len()];
+    let mut pending = vec![Vec::new(); names.len()];
+    let mut exit_codes = vec![None; names.len()];
+
+    while exit_codes.iter().any(Option::is_none) {
+        // The state is read before the logs, so the output written before an exit isn't missed
+        let containers = client.list()?;
+        for (index, name) in names.iter().enumerate() {
+            if exit_codes[index].is_some() {
+                continue;
+            }
+            let info = containers.iter().find(|info| &info.name == name)
+                .ok_or_else(|| JokerError::Other(format!("Error: container {} is gone.", name).into()))?;
+
+            // Only the output after what was read already is sent
+            let (output, length) = client.logs_from(name, offsets[index])?;
+            pending[index].extend_from_slice(&output);
+            offsets[index] = length;
+
+            let timed_out = info.status == container::TIMED_OUT_STATUS;
+            let exited = timed_out || (info.exit_code.is_some() && !matches!(info.status.as_str(), "running" | "restarting"));
+            let complete = match exited {
+                true => pending[index].len(),
+                false => pending[index].iter().rposition(|&byte| byte == b'\n').map_or(0, |end| end + 1),
+            };
+            let complete = pending[index].drain(..complete).collect::<Vec<_>>();
+            for line in String::from_utf8_lossy(&complete).lines() {
+                print_output_line(name, line, names.len() > 1)?;
+            }
+
+            if timed_out {
+                eprintln!("Container {} timed out.", name);
+                exit_codes[index] = Some(container::TIMED_OUT_EXIT_CODE);
+            } else if exited {
+                exit_codes[index] = info.exit_code;
+            }
+        }
+
+        if exit_codes.iter().any(Option::is_none) {
+            for signal in signals::wait(ATTACH_INTERVAL) {
+                forward_signal(client, names, &exit_codes, signal);
+            }
+        }
+    }
+
+    match names.iter().zip(exit_codes.into_iter().flatten()).find(|(_, code)| *code != 0) {
+        Some((name, code)) => Err(JokerError::ContainerExited { name: name.clone(), code }),
+        None => Ok(()),
+    }
+}
+
+/// Sends a signal to the containers of an attached session which haven't exited yet.
+/// A container which can't get it, e.g. because it has just exited, is skipped with a warning.
+fn forward_signal(client: &JokerClient, names: &[String], exit_codes: &[Option<i32>], signal: signals::Signal) {
+    for (name, _) in names.iter().zip(exit_codes).filter(|(_, code)| code.is_none()) {
+        match client.signal(name, signal) {
+            Ok(()) if !matches!(signal, signals::Signal::WindowChange(_)) => eprintln!("Sent {} to {}.", signal, name),
+            Ok(()) => {}
+            Err(err) => eprintln!("Warning: couldn't send {} to {}: {}", signal, name, err),
+        }
+    }
+}
+
+/// Prints a line of the output of a container: as is, after the name of the container,
+/// or as a JSON object of its own.
+fn print_output_line(name: &str, line: &str, with_name: bool) -> Result<(), JokerError> {
+    match output::format() {
+        output::Format::Json => println!(
+            "{}",
+            serde_json::json!({ "container": name, "output": line }),
+        ),
+        output::Format::Table | output::Format::Plain if with_name => println!("{} | {}", name, line),
+        output::Format::Table | output::Format::Plain => println!("{}", line),
+    }
+    io::stdout().flush().map_err(|err| JokerError::Other(err.into()))
+}
+
+/// Polls the containers run from local files and runs a container again when its binary,
+/// bundle or `.joker` config changes. A container which fails to prepare, e.g. because
+/// of a half-written config, keeps running until the next change. Never returns on success.
+/// Propagates the error down the stack trace.
+fn watch_containers(containers: &[&str], options: &RunOptions, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut watched = Vec::new();
+    for &container_path in containers {
+        let container_path = container_path.trim_end_matches('/');
+        match Path::new(container_path).exists() {
+            true => watched.push((container_path, last_modified(container_path)?, false)),
+            false => eprintln!("Warning: {} is an image, it's not watched.", container_path),
+        }
+    }
+    if watched.is_empty() {
+        return Err("Error: none of the containers is a local file to watch.".into());
+    }
+
+    status!("Watching {} for changes. Press Ctrl-C to stop.", watched.iter().map(|(path, ..)| *path).collect::<Vec<_>>().join(", "));
+
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+
+        for (container_path, modified, unreadable) in &mut watched {
+            // A binary briefly disappears while it's rebuilt
+            let current = match last_modified(container_path) {
+                Ok(current) => current,
+                Err(err) => {
+                    if !*unreadable {
+                        status!("Error while watching {}: {}", container_path, err);
+                    }
+                    *unreadable = true;
+                    continue;
+                }
+            };
+            *unreadable = false;
+            if current == *modified {
+                continue;
+            }
+            *modified = current;
+
+            if let Err(err) = redeploy_container(container_path, options, selection) {
+                status!("Error while redeploying {}: {}", container_path, err);
+            }
+        }
+    }
+}
+
+/// How often `run --watch` looks for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Replaces a container of a current daemon with a new build of it.
+/// Propagates the error down the stack trace.
+fn redeploy_container(container_path: &str, options: &RunOptions, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    // Everything is checked before the running container is removed
+    let mut container = prepare_container(container_path, options)?;
+    attach_sbom(&mut container, container_path, None)?;
+    let client = JokerClient::from_selection(selection)?;
+
+    let name = container_name(&container)?;
+    client.remove(&name)
+        .map_err(|err| format!("Error: couldn't remove {}: {}", name, err))?;
+    client.send(vec![container])?;
+
+    status!("Redeployed {} at daemon {}.", name, client.daemon().name);
+
+    Ok(())
+}
+
+/// The name a daemon knows a prepared container by: the one of its config,
+/// or the one of its binary, bundle or image.
+/// Propagates the error down the stack trace.
+fn container_name(container: &PreparedContainer) -> Result<String, Box<dyn std::error::Error>> {
+    let container_config = ContainerConfig::parse(&String::from_utf8_lossy(&container.config))?;
+    match container_config.get(container::CONTAINER_NAME_KEY) {
+        Some(name) => Ok(name.to_owned()),
+        None => Ok(String::from_utf8(container.name.clone())?),
+    }
+}
+
+/// Runs or removes replicas of a container until the daemons of the clients have as many as given
+/// between them. A replica is named `<name>-<index>`. The missing replicas go to the daemons with
+/// the fewest replicas, and the replicas with the highest indices are removed first.
+/// Propagates the error down the stack trace.
+fn scale_replicas(
+    clients: &[JokerClient],
+    container_path: &str,
+    replicas: u32,
+    options: &RunOptions,
+    sbom: Option<&Path>,
+) -> Result<(), JokerError> {
+    let container_path = container_path.trim_end_matches('/');
+    let name = container_name(&prepare_container(container_path, options)?)?;
+
+    // A daemon which doesn't answer could have replicas, so nothing is changed without all of them
+    let mut running = Vec::new();
+    let mut counts = vec![0; clients.len()];
+    for (daemon, (client, containers)) in clients.iter().zip(client::broadcast(clients, |client| client.list())).enumerate() {
+        let containers = containers.map_err(|err| JokerError::Other(
+            format!("Error: couldn't list the containers of daemon {}: {}", client.daemon().name, err).into(),
+        ))?;
+        for info in containers {
+            if let Some(index) = scheduler::replica_index(&name, &info.name) {
+                running.push((index, daemon));
+                counts[daemon] += 1;
+            }
+        }
+    }
+    running.sort_unstable();
+
+    for &(index, daemon) in running.iter().rev().filter(|(index, _)| *index > replicas) {
+        let replica = scheduler::replica_name(&name, index);
+        clients[daemon].remove(&replica)?;
+        counts[daemon] -= 1;
+        status!("Removed {} from daemon {}.", replica, clients[daemon].daemon().name);
+    }
+
+    for index in (1..=replicas).filter(|index| !running.iter().any(|(running, _)| running == index)) {
+        let daemon = scheduler::spread(&counts)
+            .ok_or_else(|| JokerError::Other("Error: there is no daemon to run the replicas on.".into()))?;
+        let replica = scheduler::replica_name(&name, index);
+        let options = RunOptions { name: Some(replica.clone()), ..options.clone() };
+        clients[daemon].run(&[container_path], &options, sbom)?;
+        counts[daemon] += 1;
+        status!("Running {} at daemon {}.", replica, clients[daemon].daemon().name);
+    }
+
+    status!("Replicas of {}: {}.", name, replicas);
+
+    Ok(())
+}
+
+/// The latest modification time of a container: of its binary or of any file
+/// of its bundle directory, and of its `.joker` config if there's one.
+fn last_modified(container_path: &str) -> io::Result<std::time::SystemTime> {
+    let mut latest = std::time::SystemTime::UNIX_EPOCH;
+    let mut pending = vec![Path::new(container_path).to_owned()];
+    let config_path = PathBuf::from(format!("{}.joker", container_path));
+    if config_path.exists() {
+        pending.push(config_path);
+    }
+
+    while let Some(path) = pending.pop() {
+        let metadata = std::fs::symlink_metadata(&path)?;
+        latest = latest.max(metadata.modified()?);
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                pending.push(entry?.path());
+            }
+        }
+    }
+
+    Ok(latest)
+}
+
+/// Prepares containers for `joker run`: binaries with a `.joker` config, OCI bundle
+/// directories or images of the local store. All of them are checked before anything
+/// is sent. An SBOM can only be given for a single container.
+/// Propagates the error down the stack trace.
+fn prepare_containers(containers: &[&str], options: &RunOptions, sbom: Option<&Path>) -> Result<Vec<PreparedContainer>, JokerError> {
+    if sbom.is_some() && containers.len() > 1 {
+        return Err(JokerError::Other("Error: an SBOM describes a single container.".into()));
+    }
+    let sbom = sbom.map(Sbom::read).transpose()?;
+
+    let mut prepared = Vec::with_capacity(containers.len());
+    for &container_path in containers {
+        let container_path = container_path.trim_end_matches('/');
+        let mut container = prepare_container(container_path, options)?;
+        attach_sbom(&mut container, container_path, sbom.as_ref())?;
+        prepared.push(container);
+    }
+
+    Ok(prepared)
+}
+
+/// What `--dry-run` would send to a daemon.
+#[derive(Serialize)]
+struct DryRun<'a> {
+    daemons: &'a [Daemon],
+    containers: Vec<PlannedContainer>,
+}
+
+/// A prepared container as printed by `--dry-run`.
+#[derive(Serialize)]
+struct PlannedContainer {
+    name: String,
+    /// `binary`, `bundle` or `image`.
+    kind: &'static str,
+    size: u64,
+    digest: String,
+    layers: Vec<Descriptor>,
+    /// The media types of the documents sent along, such as signatures.
+    attachments: Vec<String>,
+    pre_run: Option<String>,
+    post_run: Option<String>,
+    config: String,
+}
+
+impl PlannedContainer {
+    fn new(container: &PreparedContainer) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(PlannedContainer {
+            name: container_name(container)?,
+            kind: match container.request {
+                Requests::RunBundle => "bundle",
+                Requests::RunImage => "image",
+                _ => "binary",
+            },
+            size: container.payload.len() as u64,
+            digest: store::digest(&container.payload),
+            layers: container.layers.clone(),
+            attachments: container.attachments.iter().map(|attachment| attachment.media_type.clone()).collect(),
+            pre_run: container.hooks.pre_run.clone(),
+            post_run: container.hooks.post_run.clone(),
+            config: String::from_utf8_lossy(&container.config).into_owned(),
+        })
+    }
+}
+
+/// Prints the daemons prepared containers would be sent to and everything that would be
+/// sent: the payloads, the layers, the attachments, the configs and the local hooks.
+/// Propagates the error down the stack trace.
+fn print_dry_run(daemons: &[Daemon], prepared: &[PreparedContainer]) -> Result<(), Box<dyn std::error::Error>> {
+    let plan = DryRun {
+        daemons,
+        containers: prepared.iter().map(PlannedContainer::new).collect::<Result<_, _>>()?,
+    };
+
+    status!("Dry run, nothing is sent.");
+    if output::format() == output::Format::Json {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+        return Ok(());
+    }
+
+    for daemon in plan.daemons {
+        println!("Daemon: {} at {}", daemon.name, daemon.socket_address);
+    }
+    for container in &plan.containers {
+        let layers_size = container.layers.iter().map(|layer| layer.size).sum();
+        println!();
+        output::print_record(container, &[
+            ("Container", Some(container.name.clone())),
+            ("Kind", Some(container.kind.to_owned())),
+            ("Size", Some(format_size(container.size))),
+            ("Digest", Some(container.digest.clone())),
+            ("Layers", (!container.layers.is_empty()).then(|| format!(
+                "{} ({})", container.layers.len(), format_size(layers_size),
+            ))),
+            ("Attachments", (!container.attachments.is_empty()).then(|| container.attachments.join(", "))),
+            ("Pre-run hook", container.pre_run.clone()),
+            ("Post-run hook", container.post_run.clone()),
+        ])?;
+        println!("Config:");
+        for line in container.config.lines().filter(|line| !line.trim().is_empty()) {
+            println!("    {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Attaches an SBOM given explicitly or kept next to a container,
+/// replacing the one an artifact was published with.
+fn attach_sbom(container: &mut PreparedContainer, container_path: &str, sbom: Option<&Sbom>) -> io::Result<()> {
+    let sbom = match sbom {
+        Some(sbom) => Some(sbom.clone()),
+        None => Sbom::find(container_path)?,
+    };
+    if let Some(sbom) = sbom {
+        container.attachments.retain(|attachment| !sbom::SBOM_TYPES.contains(&attachment.media_type.as_str()));
+        container.attachments.push(Attachment { media_type: sbom.media_type, data: sbom.data });
+    }
+
+    Ok(())
+}
+
+/// Sends prepared containers to a daemon over a single connection.
+/// Propagates the error down the stack trace.
+fn send_containers(transport: &dyn Transport, daemon: &Daemon, prepared: Vec<PreparedContainer>) -> Result<(), JokerError> {
+    let hooks = prepared.iter()
+        .map(|container| (String::from_utf8_lossy(&container.name).into_owned(), container.hooks.clone()))
+        .collect::<Vec<_>>();
+    for (name, hooks) in &hooks {
+        if let Some(command) = &hooks.pre_run {
+            run_hook("pre-run", command, name, daemon, hooks.failure)?;
+        }
+    }
+
+    let mut connection = transport.connect(daemon).map_err(JokerError::Connection)?;
+
+    for container in prepared {
+        write_container(&mut connection, &container).map_err(JokerError::Connection)?;
+
+        if matches!(container.request, Requests::RunImage) {
+            upload_layers(&mut connection, &container.layers)?;
+        }
+    }
+
+    for (name, hooks) in &hooks {
+        if let Some(command) = &hooks.post_run {
+            run_hook("post-run", command, name, daemon, hooks.failure)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the request to run a prepared container.
+fn write_container(tcp_stream: &mut impl Write, container: &PreparedContainer) -> io::Result<()> {
+    // Send the type of request
+    tcp_stream.write_all(&[container.request as u8])?;
+
+    // Send the size of binary name and binary name itself
+    tcp_stream.write_all(&(container.name.len() as u64).to_le_bytes())?;
+    tcp_stream.write_all(&container.name)?;
+
+    // Send the size of the binary (the bundle archive, or the spec of an image) and the binary itself
+    tcp_stream.write_all(&(container.payload.len() as u64).to_le_bytes())?;
+    tcp_stream.write_all(&container.payload)?;
+
+    if matches!(container.request, Requests::RunImage) {
+        write_all_to_stream(tcp_stream, &serde_json::to_vec(&container.layers)?)?;
+    }
+
+    // Send the size of binary config and binary config itself
+    tcp_stream.write_all(&(container.config.len() as u64).to_le_bytes())?;
+    tcp_stream.write_all(&container.config)?;
+
+    // Send the documents the daemon checks the container with, such as signatures
+    write_all_to_stream(tcp_stream, &serde_json::to_vec(&container.attachments)?)
+}
+
+/// Runs a local hook of a container with `sh` in the current directory. The hook
+/// learns about the container from the `JOKER_HOOK`, `JOKER_CONTAINER` and
+/// `JOKER_DAEMON` environment variables.
+fn run_hook(event: &str, command: &str, container: &str, daemon: &Daemon, failure: HookFailure) -> Result<(), Box<dyn std::error::Error>> {
+    eprintln!("Running the {} hook of {}.", event, container);
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("JOKER_HOOK", event)
+        .env("JOKER_CONTAINER", container)
+        .env("JOKER_DAEMON", &daemon.name)
+        .status()?;
+    if status.success() {
+        return Ok(());
+    }
+
+    let message = format!("the {} hook of {} failed with {}", event, container, status);
+    match failure {
+        HookFailure::Fatal => Err(format!("Error: {}.", message).into()),
+        HookFailure::Warn => {
+            eprintln!("Warning: {}.", message);
+            Ok(())
+        }
+    }
+}
+
+/// Reads a container and applies the options of `run` to it: a pulled image
+/// or artifact, an OCI bundle directory or a binary with its `.joker` config.
+/// Propagates the error down the stack trace.
+fn prepare_container(container_path: &str, options: &RunOptions) -> Result<PreparedContainer, Box<dyn std::error::Error>> {
+    let config_path = format!("{}.joker", container_path);
+
+    let image = match Path::new(container_path).exists() {
+        true => None,
+        false => find_image(container_path)?,
+    };
+
+    let (bundle, mut attachments) = match image {
+        Some(StoredImage::Bundle { bundle, attachments }) => (Some(bundle), attachments),
+        Some(StoredImage::Artifact { artifact, mut attachments }) => {
+            // The config was published as entries with their variables expanded by `push`
+            let mut binary_config = ContainerConfig::parse(&String::from_utf8(artifact.config)?)?;
+            binary_config.apply(options)?;
+            check_runtime(container_path, &artifact.binary, &binary_config)?;
+            // A pulled container must not run commands on this machine, nor read its files
+            let hooks = binary_config.take_local_hooks()?;
+            if hooks.pre_run.is_some() || hooks.post_run.is_some() {
+                eprintln!("Warning: the local hooks of {} are skipped, it's an image.", container_path);
+            }
+            if binary_config.get(container::ASSET_KEY).is_some() {
+                eprintln!("Warning: the assets of the config of {} are skipped, it's an image.", container_path);
+                binary_config.remove(container::ASSET_KEY);
+            }
+            // The assets of the artifact are attached already, the ones of the options go over them
+            attach_assets(&mut attachments, &options.assets)?;
+
+            return Ok(PreparedContainer {
+                request: Requests::Run,
+                name: artifact.name.into_bytes(),
+                payload: artifact.binary,
+                config: binary_config.to_string().into_bytes(),
+                layers: Vec::new(),
+                attachments,
+                hooks: LocalHooks::default(),
+            });
+        }
+        None if Bundle::is_bundle(Path::new(container_path)) => {
+            (Some(Bundle::open(Path::new(container_path))?), Vec::new())
+        }
+        None => (None, Vec::new()),
+    };
+
+    if let Some(mut bundle) = bundle {
+        if options.runtime == Some(Runtime::Wasm) {
+            return Err(format!("Error: {} is an OCI container, not a WebAssembly module.", container_path).into());
+        }
+        bundle.apply(options)?;
+
+        // The spec of a bundle describes the process, so its `.joker`
+        // config is optional and only holds the options of the daemon host
+        let mut bundle_config = match Path::new(&config_path).exists() {
+            true => schema::parse_config(&std::fs::read_to_string(&config_path)?)?,
+            false => ContainerConfig::default(),
+        };
+        bundle_config.apply_host_options(options)?;
+        let hooks = bundle_config.take_local_hooks()?;
+        let assets = bundle_config.take_assets(config_directory(&config_path), &options.assets)?;
+        attach_assets(&mut attachments, &assets)?;
+
+        // An image is sent as its layers, which the daemon stacks with overlayfs
+        // and shares between containers, rather than as a flattened root filesystem
+        let container = match bundle.image_layers() {
+            Some(layers) => PreparedContainer {
+                request: Requests::RunImage,
+                name: bundle.name.as_bytes().to_owned(),
+                payload: serde_json::to_vec_pretty(&bundle.spec)?,
+                config: bundle_config.to_string().into_bytes(),
+                layers: layers.to_vec(),
+                attachments,
+                hooks,
+            },
+            None => PreparedContainer {
+                request: Requests::RunBundle,
+                name: bundle.name.as_bytes().to_owned(),
+                payload: bundle.pack()?,
+                config: bundle_config.to_string().into_bytes(),
+                layers: Vec::new(),
+                attachments,
+                hooks,
+            },
+        };
+        return Ok(container);
+    }
+
+    let binary_name = container_path.split('/').next_back()
+        .ok_or("Error: bad file path.")?.as_bytes().to_owned();
+    let binary = std::fs::read(container_path)?;
+    let mut binary_config = schema::parse_config(&std::fs::read_to_string(&config_path)?)?;
+    binary_config.apply(options)?;
+    check_runtime(container_path, &binary, &binary_config)?;
+    let hooks = binary_config.take_local_hooks()?;
+    let mut attachments = binary_signatures(container_path, &binary)?;
+    let assets = binary_config.take_assets(config_directory(&config_path), &options.assets)?;
+    attach_assets(&mut attachments, &assets)?;
+
+    Ok(PreparedContainer {
+        request: Requests::Run,
+        name: binary_name,
+        payload: binary,
+        config: binary_config.to_string().into_bytes(),
+        layers: Vec::new(),
+        attachments,
+        hooks,
+    })
+}
+
+/// Writes a starter `.joker` config for a binary, and optionally a Jokerfile and
+/// a compose file next to it. Existing files are kept unless `force` is set.
+/// Propagates the error down the stack trace.
+fn init_container(name: Option<&str>, jokerfile: bool, compose: bool, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let container_path = match name {
+        Some(name) => name.trim_end_matches('/').to_owned(),
+        None => std::env::current_dir()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or("Error: the current directory has no name, give the name of the binary.")?,
+    };
+    let path = Path::new(&container_path);
+    let binary = path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or("Error: bad file path.")?;
+    if !container::is_valid_name(&binary) {
+        return Err(InvalidOptionError { option: "container name".to_owned(), value: binary }.into());
+    }
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut files = vec![(PathBuf::from(format!("{}.joker", container_path)), schema::starter_config(&binary))];
+    if jokerfile {
+        files.push((directory.join(jokerfile::DEFAULT_JOKERFILE), jokerfile::starter_jokerfile(&binary)));
+    }
+    if compose {
+        files.push((directory.join(compose::DEFAULT_COMPOSE_FILE), compose::starter_compose_file(&binary, &format!("./{}", binary))));
+    }
+
+    // Nothing is written if any of the files would be overwritten
+    if let Some((existing, _)) = files.iter().find(|(path, _)| !force && path.exists()) {
+        return Err(format!("Error: {} already exists, pass --force to overwrite it.", existing.display()).into());
+    }
+    for (path, contents) in &files {
+        std::fs::write(path, contents)?;
+        status!("Wrote {}.", path.display());
+    }
+    if !path.exists() {
+        eprintln!("Warning: {} doesn't exist yet, build it before running it.", container_path);
+    }
+
+    Ok(())
+}
+
+/// Checks a `.joker` config the same way `run` does and reports the first problem.
+/// Propagates the error down the stack trace.
+fn validate_config(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config_path = match path.ends_with(".joker") {
+        true => path.to_owned(),
+        false => format!("{}.joker", path),
+    };
+    let text = std::fs::read_to_string(&config_path)?;
+
+    let mut config = schema::parse_config(&text)
+        .map_err(|err| format!("Error: {}: {}", config_path, err))?;
+    config.apply(&RunOptions::default())
+        .map_err(|err| format!("Error: {}: {}", config_path, err))?;
+
+    let format = match schema::is_toml(&text) {
+        true => format!("schema version {}", schema::SCHEMA_VERSION),
+        false => "Key: value lines".to_owned(),
+    };
+    status!("{} is a valid container config ({}).", config_path, format);
+
+    Ok(())
+}
+
+/// Checks that a binary suits the runtime of its config, so that
+/// a WebAssembly module isn't executed as a native binary or vice versa.
+fn check_runtime(container_path: &str, binary: &[u8], config: &ContainerConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let wasm = binary.starts_with(container::WASM_MAGIC);
+    match config.runtime()? {
+        Runtime::Wasm if !wasm => Err(format!("Error: {} is not a WebAssembly module.", container_path).into()),
+        Runtime::Native if wasm => Err(format!(
+            "Error: {} is a WebAssembly module, set `{}: wasm` in its config or pass --runtime wasm.",
+            container_path,
+            container::RUNTIME_KEY,
+        ).into()),
+        _ => Ok(()),
+    }
+}
+
+/// The directory the relative paths of a `.joker` config are relative to.
+fn config_directory(config_path: &str) -> &Path {
+    Path::new(config_path).parent().unwrap_or(Path::new(""))
+}
+
+/// Packs assets and attaches them to a container, if there are any.
+fn attach_assets(attachments: &mut Vec<Attachment>, assets: &[Asset]) -> io::Result<()> {
+    if !assets.is_empty() {
+        attachments.push(assets::attachment(&assets::pack(assets)?));
+    }
+
+    Ok(())
+}
+
+/// A container ready to be sent to a daemon.
+struct PreparedContainer {
+    request: Requests,
+    name: Vec<u8>,
+    /// A binary, a bundle archive or the runtime spec of an image.
+    payload: Vec<u8>,
+    config: Vec<u8>,
+    /// The layers of an image, bottom first.
+    layers: Vec<Descriptor>,
+    attachments: Vec<Attachment>,
+    /// The hooks run by the CLI, taken out of the config.
+    hooks: LocalHooks,
+}
+
+/// Attaches the signatures of a binary made by `joker sign`.
+/// Signatures of an older build of the binary, or which don't match
+/// their key, are skipped with a warning.
+fn binary_signatures(container_path: &str, binary: &[u8]) -> Result<Vec<Attachment>, Box<dyn std::error::Error>> {
+    let signatures = signature::read_signatures(Path::new(&format!("{}.sig", container_path)))?;
+    let digest = store::digest(binary);
+
+    let (current, stale): (Vec<_>, Vec<_>) = signatures.into_iter().partition(|signature| signature.digest == digest);
+    if !stale.is_empty() {
+        eprintln!("Warning: {} has signatures of another build, sign it again.", container_path);
+    }
+    let (valid, invalid): (Vec<_>, Vec<_>) = current.into_iter().partition(Signature::verify);
+    for signature in invalid {
+        eprintln!("Warning: the signature of {} by key {} is invalid.", container_path, signature.key_id());
+    }
+
+    valid.iter()
+        .map(|signature| Ok(Attachment {
+            media_type: signature::SIGNATURE_TYPE.to_owned(),
+            data: serde_json::to_string(signature)?,
+        }))
+        .collect()
+}
+
+/// Sends the layers the daemon asks for: it answers the list of the layers
+/// of an image with the digests of those it doesn't have yet.
+/// Propagates the error down the stack trace.
+fn upload_layers(stream: &mut (impl Read + Write), layers: &[Descriptor]) -> Result<(), Box<dyn std::error::Error>> {
+    let missing: Vec<String> = serde_json::from_slice(&read_all_from_stream(stream)?)?;
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let store = Store::open()?;
+    for digest in &missing {
+        let layer = layers.iter().find(|layer| &layer.digest == digest)
+            .ok_or_else(|| format!("Error: daemon asked for {}, which is not a layer of the image.", digest))?;
+        eprintln!("Uploading layer {} ({}).", short_digest(digest), format_size(layer.size));
+
+        stream.write_all(&layer.size.to_le_bytes())?;
+        io::copy(&mut store.open_blob(digest)?, stream)?;
+    }
+
+    Ok(())
+}
+
+/// A container found in the local store, with its manifest
+/// and signatures attached for the daemon to verify it.
+enum StoredImage {
+    /// An image with a root filesystem, run as an OCI bundle.
+    Bundle { bundle: Bundle, attachments: Vec<Attachment> },
+    /// A binary with its `.joker` config, run as a plain container.
+    Artifact { artifact: JokerArtifact, attachments: Vec<Attachment> },
+}
+
+/// Finds an image of the local store by its reference.
+/// An image is run as a container named after the last component of its repository.
+fn find_image(reference: &str) -> Result<Option<StoredImage>, Box<dyn std::error::Error>> {
+    let Ok(reference) = reference.parse::<ImageReference>() else {
+        return Ok(None);
+    };
+
+    // a path which doesn't exist isn't a reason to create the store
+    let Some(store) = Store::open_existing() else {
+        return Ok(None);
+    };
+    let Some(digest) = store.resolve(&reference)? else {
+        return Ok(None);
+    };
+
+    let manifest_data = store.read_blob(&digest)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest_data)?;
+
+    // The signatures are of the manifest, which lists the digests of the binary or the layers
+    let mut attachments = vec![Attachment {
+        media_type: manifest.media_type.clone().unwrap_or_else(|| image::OCI_MANIFEST.to_owned()),
+        data: String::from_utf8(manifest_data)?,
+    }];
+    for signature in store.signatures(&digest)? {
+        if !signature.verify() {
+            eprintln!("Warning: the signature of {} by key {} is invalid.", reference, signature.key_id());
+            continue;
+        }
+        attachments.push(Attachment {
+            media_type: signature::SIGNATURE_TYPE.to_owned(),
+            data: serde_json::to_string(&signature)?,
+        });
+    }
+
+    if manifest.artifact_type.as_deref() == Some(image::JOKER_ARTIFACT) {
+        let artifact = JokerArtifact::from_store(&store, &manifest)?;
+        if let Some(sbom) = &artifact.sbom {
+            attachments.push(Attachment { media_type: sbom.media_type.clone(), data: sbom.data.clone() });
+        }
+        if let Some(assets) = &artifact.assets {
+            attachments.push(assets::attachment(assets));
+        }
+        return Ok(Some(StoredImage::Artifact { artifact, attachments }));
+    }
+
+    let name = reference.repository.split('/').next_back().unwrap_or(&reference.repository);
+    let bundle = Bundle::from_image(name, &store, &digest)?;
+    Ok(Some(StoredImage::Bundle { bundle, attachments }))
+}
+
+/// Publishes a container to a registry. A binary with its `.joker` config
+/// is packaged as an OCI artifact; without a path, the image is taken
+/// from the local store. Blobs the registry already has are not uploaded.
+/// Propagates the error down the stack trace.
+fn push_image(
+    reference: &str,
+    container_path: Option<&str>,
+    sbom: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let reference: ImageReference = reference.parse()?;
+    if reference.digest.is_some() {
+        return Err("Error: images are pushed by a tag, not a digest.".into());
+    }
+    let store = Store::open()?;
+
+    let manifest_digest = match container_path {
+        Some(container_path) => {
+            let name = container_path.split('/').next_back()
+                .ok_or("Error: bad file path.")?.to_owned();
+            let binary = std::fs::read(container_path)?;
+            // The config is published as the entries the daemon reads, whichever format it's written in,
+            // and its assets as a layer of their own
+            let config_path = format!("{}.joker", container_path);
+            let mut config = schema::parse_config(&std::fs::read_to_string(&config_path)?)?;
+            let assets = config.take_assets(config_directory(&config_path), &[])?;
+            let assets = match assets.is_empty() {
+                true => None,
+                false => Some(assets::pack(&assets)?),
+            };
+            let config = config.to_string().into_bytes();
+
+            let sbom = match sbom {
+                Some(sbom) => Some(Sbom::read(sbom)?),
+                None => Sbom::find(container_path)?,
+            };
+
+            let artifact = JokerArtifact { name, binary, config, sbom, assets };
+            store.write_blob(&artifact.binary)?;
+            store.write_blob(&artifact.config)?;
+            store.write_blob(b"{}")?;
+            if let Some(sbom) = &artifact.sbom {
+                store.write_blob(sbom.data.as_bytes())?;
+            }
+            if let Some(assets) = &artifact.assets {
+                store.write_blob(assets)?;
+            }
+
+            let digest = store.write_blob(&serde_json::to_vec(&artifact.manifest())?)?;
+            store.set_tag(&reference.tag_name(), &digest)?;
+            digest
+        }
+        None if sbom.is_some() => return Err("Error: an SBOM is only published with a binary.".into()),
+        None => store.resolve(&reference)?
+            .ok_or_else(|| format!("Error: no image {} in the local store.", reference))?,
+    };
+
+    let manifest_data = store.read_blob(&manifest_digest)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest_data)?;
+    let mut client = RegistryClient::new(&reference, "pull,push");
+
+    status!("Pushing {}.", reference);
+
+    for blob in std::iter::once(&manifest.config).chain(&manifest.layers) {
+        if client.has_blob(&blob.digest)? {
+            status!("Blob {} already exists.", short_digest(&blob.digest));
+            continue;
+        }
+        status!("Uploading blob {} ({}).", short_digest(&blob.digest), format_size(blob.size));
+        client.put_blob(&blob.digest, &store.read_blob(&blob.digest)?)?;
+    }
+
+    let media_type = manifest.media_type.as_deref().unwrap_or(image::OCI_MANIFEST);
+    client.put_manifest(&reference.tag, media_type, &manifest_data)?;
+
+    status!("Pushed {} as {}.", reference, manifest_digest);
+
+    Ok(())
+}
+
+/// Builds an image into the local store following the instructions of a Jokerfile.
+/// Propagates the error down the stack trace.
+fn build_image(reference: &str, context: &Path, file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let reference: ImageReference = reference.parse()?;
+    if reference.digest.is_some() {
+        return Err("Error: images are built with a tag, not a digest.".into());
+    }
+    if !context.is_dir() {
+        return Err(format!("Error: build context {} is not a directory.", context.display()).into());
+    }
+
+    let file = file.map(Path::to_path_buf)
+        .unwrap_or_else(|| context.join(jokerfile::DEFAULT_JOKERFILE));
+    let jokerfile = Jokerfile::parse(&std::fs::read_to_string(&file)?)?;
+    let store = Store::open()?;
+    let mut builder = ImageBuilder::new(&store, context);
+
+    let total = jokerfile.instructions.len();
+    for (step, instruction) in jokerfile.instructions.iter().enumerate() {
+        status!("Step {}/{}: {}", step + 1, total, instruction);
+        builder.apply(instruction)?;
+    }
+
+    let digest = builder.finish(&reference)?;
+    status!("Built {} as {}.", reference, digest);
+
+    Ok(())
+}
+
+/// Downloads an image from a registry into the local store.
+/// Blobs already present in the store are not downloaded again.
+/// Propagates the error down the stack trace.
+fn pull_image(reference: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let reference: ImageReference = reference.parse()?;
+    let store = Store::open()?;
+    let mut client = RegistryClient::new(&reference, "pull");
+
+    status!("Pulling {}.", reference);
+
+    let mut manifest = client.get_manifest(reference.manifest_reference())?;
+    let mut expected_digest = reference.digest.clone();
+
+    if image::is_index(&manifest.media_type) {
+        if let Some(digest) = &expected_digest {
+            check_digest(&manifest.data, digest)?;
+        }
+        let index: Index = serde_json::from_slice(&manifest.data)?;
+        let platform = image::current_platform();
+        let descriptor = image::select_manifest(&index, &platform)
+            .ok_or_else(|| format!("Error: no image for {}/{}.", platform.os, platform.architecture))?;
+
+        expected_digest = Some(descriptor.digest.clone());
+        let index_data = std::mem::take(&mut manifest.data);
+        manifest = client.get_manifest(&descriptor.digest)?;
+        // the index an image was pulled by stays the root of its tag
+        if reference.digest.is_some() {
+            store.write_blob(&index_data)?;
+        }
+    }
+
+    if let Some(digest) = &expected_digest {
+        check_digest(&manifest.data, digest)?;
+    }
+    let parsed: Manifest = serde_json::from_slice(&manifest.data)?;
+
+    for blob in std::iter::once(&parsed.config).chain(&parsed.layers) {
+        if store.has_blob(&blob.digest) {
+            status!("Blob {} already exists.", short_digest(&blob.digest));
+            continue;
+        }
+        status!("Downloading blob {} ({}).", short_digest(&blob.digest), format_size(blob.size));
+        store.write_blob_from(&blob.digest, client.get_blob(&blob.digest)?)?;
+    }
+
+    // An image pulled by its digest is recorded by it,
+    // so that it is listed and not collected as garbage
+    let manifest_digest = store.write_blob(&manifest.data)?;
+    store.set_tag(&reference.store_name(), &manifest_digest)?;
+
+    status!("Pulled {} as {}.", reference, manifest_digest);
+
+    Ok(())
+}
+
+/// Returns the selection of the daemon whose images `images` and `rmi` work on, if they
+/// work on a daemon's: with `--remote`, the daemon it names or the selected one, and with
+/// `--daemon`, that one. Without either, they work on the local store.
+fn remote_selection(matches: &ArgMatches, selection: &Selection) -> Option<Selection> {
+    match matches.get_one::<String>("remote").filter(|name| !name.is_empty()) {
+        Some(name) => Some(Selection { daemon: Some(name.clone()), ..selection.clone() }),
+        None if matches.contains_id("remote") || selection.daemon.is_some() => Some(selection.clone()),
+        None => None,
+    }
+}
+
+/// Prints the images of the local store or of a current daemon.
+/// Propagates the error down the stack trace.
+fn list_images(daemon: bool, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let images = match daemon {
+        true => JokerClient::from_selection(selection)?.images()?,
+        false => match Store::open_existing() {
+            Some(store) => store.images()?,
+            None => Vec::new(),
+        },
+    };
+
+    output::print_list(&["REFERENCE", "DIGEST", "KIND", "SIZE"], &images, |image| vec![
+        image.reference.clone(),
+        short_digest(&image.digest).to_owned(),
+        image.kind.clone(),
+        format_size(image.size),
+    ])?;
+
+    Ok(())
+}
+
+/// Removes images from the local store or from a current daemon.
+/// Blobs no other image refers to are removed from the local store too.
+/// Propagates the error down the stack trace.
+fn remove_images(images: &[String], daemon: bool, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let references = images.iter()
+        .map(|image| image.parse::<ImageReference>().map(|reference| reference.store_name()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if daemon {
+        let client = JokerClient::from_selection(selection)?;
+        for reference in &references {
+            status!("{}", client.remove_image(reference)?);
+        }
+
+        return Ok(());
+    }
+
+    let store = Store::open()?;
+    for reference in &references {
+        match store.remove_tag(reference)? {
+            Some(digest) => status!("Untagged {} ({}).", reference, short_digest(&digest)),
+            None => return Err(format!("Error: no image {} in the local store.", reference).into()),
+        }
+    }
+
+    let (count, size) = store.collect_garbage()?;
+    status!("Removed {} blobs ({}).", count, format_size(size));
+
+    Ok(())
+}
+
+/// Makes an image of the daemon's store from a container: the writable layer
+/// of the container becomes a new layer over the layers of its image.
+/// With `pull`, the blobs of the image missing from the local store are downloaded.
+/// Propagates the error down the stack trace.
+fn commit_container(container_name: &str, reference: &str, pull: bool, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let reference: ImageReference = reference.parse()?;
+    if reference.digest.is_some() {
+        return Err("Error: images are committed with a tag, not a digest.".into());
+    }
+    let mut tcp_stream = JokerClient::from_selection(selection)?.connect(Requests::Commit)?;
+
+    // writing request to a daemon
+    write_all_to_stream(&mut tcp_stream, container_name.as_bytes())?;
+    write_all_to_stream(&mut tcp_stream, reference.tag_name().as_bytes())?;
+    tcp_stream.write_all(&[pull as u8])?;
+
+    // getting the manifest of the new image from a daemon
+    let manifest_data = read_result_from_stream(&mut tcp_stream)
+        .map_err(|err| format!("Error: couldn't commit {}: {}", container_name, err))?;
+    let manifest: Manifest = serde_json::from_slice(&manifest_data)?;
+    let manifest_digest = store::digest(&manifest_data);
+
+    status!("Committed {} as {} ({}).", container_name, reference, short_digest(&manifest_digest));
+
+    if !pull {
+        return Ok(());
+    }
+
+    let store = Store::open()?;
+    let missing = std::iter::once(&manifest.config)
+        .chain(&manifest.layers)
+        .filter(|blob| !store.has_blob(&blob.digest))
+        .collect::<Vec<_>>();
+    let digests = missing.iter().map(|blob| blob.digest.as_str()).collect::<Vec<_>>();
+    write_all_to_stream(&mut tcp_stream, &serde_json::to_vec(&digests)?)?;
+
+    // The daemon sends the requested blobs in order
+    for blob in missing {
+        status!("Downloading blob {} ({}).", short_digest(&blob.digest), format_size(blob.size));
+        let mut size = [0u8; 8];
+        tcp_stream.read_exact(&mut size)?;
+        let size = u64::from_le_bytes(size);
+        store.write_blob_from(&blob.digest, (&mut tcp_stream).take(size))?;
+    }
+
+    store.write_blob_from(&manifest_digest, manifest_data.as_slice())?;
+    store.set_tag(&reference.tag_name(), &manifest_digest)?;
+    status!("Pulled {} into the local store.", reference);
+
+    Ok(())
+}
+
+/// Checkpoints a container with CRIU on a current daemon, stopping it
+/// unless `leave_running` is set. With an output path, the checkpoint
+/// archive is downloaded so that it can be restored on another daemon.
+/// Propagates the error down the stack trace.
+fn checkpoint_container(
+    container_name: &str,
+    checkpoint_name: &str,
+    leave_running: bool,
+    output: Option<&Path>,
+    selection: &Selection,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !container::is_valid_name(checkpoint_name) {
+        return Err(InvalidOptionError { option: "checkpoint name".to_owned(), value: checkpoint_name.to_owned() }.into());
+    }
+    let mut tcp_stream = JokerClient::from_selection(selection)?.connect(Requests::Checkpoint)?;
+
+    // writing request to a daemon
+    write_all_to_stream(&mut tcp_stream, container_name.as_bytes())?;
+    write_all_to_stream(&mut tcp_stream, checkpoint_name.as_bytes())?;
+    tcp_stream.write_all(&[leave_running as u8, output.is_some() as u8])?;
+
+    // getting the name of the checkpoint from a daemon
+    let checkpoint = read_result_from_stream(&mut tcp_stream)
+        .map_err(|err| format!("Error: couldn't checkpoint {}: {}", container_name, err))?;
+    status!("Checkpointed {} as {}.", container_name, String::from_utf8(checkpoint)?);
+
+    if let Some(output) = output {
+        // The archive holds the memory of the container, so it's streamed to the disk
+        let size = download_to_file(&mut tcp_stream, output)?;
+        status!("Saved the checkpoint to {} ({}).", output.display(), format_size(size));
+    }
+
+    Ok(())
+}
+
+/// Restores a container from a checkpoint of a current daemon. A path
+/// to a checkpoint archive is uploaded first, which migrates a container
+/// checkpointed on another daemon.
+/// Propagates the error down the stack trace.
+fn restore_container(checkpoint: &str, container_name: Option<&str>, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(name) = container_name.filter(|name| !container::is_valid_name(name)) {
+        return Err(InvalidOptionError { option: "container name".to_owned(), value: name.to_owned() }.into());
+    }
+    let archive = Path::new(checkpoint);
+    let upload = archive.is_file();
+    let client = JokerClient::from_selection(selection)?;
+
+    let mut tcp_stream = client.connect(Requests::Restore)?;
+
+    // writing request to a daemon: the name of a checkpoint or an empty one followed by an archive
+    match upload {
+        true => {
+            write_all_to_stream(&mut tcp_stream, &[])?;
+            let mut file = std::fs::File::open(archive)?;
+            tcp_stream.write_all(&file.metadata()?.len().to_le_bytes())?;
+            status!("Uploading the checkpoint {}.", archive.display());
+            io::copy(&mut file, &mut tcp_stream)?;
+        }
+        false => write_all_to_stream(&mut tcp_stream, checkpoint.as_bytes())?,
+    }
+    write_all_to_stream(&mut tcp_stream, container_name.unwrap_or_default().as_bytes())?;
+
+    // getting the name of the restored container from a daemon
+    let restored = read_result_from_stream(&mut tcp_stream)
+        .map_err(|err| format!("Error: couldn't restore {}: {}", checkpoint, err))?;
+    status!(
+        "Restored container {} at daemon {}.",
+        String::from_utf8(restored)?,
+        client.daemon().name,
+    );
+
+    Ok(())
+}
+
+/// Downloads the root filesystem of a container of a current daemon as a tarball.
+/// Propagates the error down the stack trace.
+fn export_container(container_name: &str, output: Option<&Path>, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tcp_stream = JokerClient::from_selection(selection)?.connect(Requests::Export)?;
+
+    // writing request to a daemon
+    write_all_to_stream(&mut tcp_stream, container_name.as_bytes())?;
+
+    // getting the tarball from a daemon after the result of the export
+    read_result_from_stream(&mut tcp_stream)
+        .map_err(|err| format!("Error: couldn't export {}: {}", container_name, err))?;
+    match output {
+        Some(output) => {
+            let size = download_to_file(&mut tcp_stream, output)?;
+            status!("Exported {} to {} ({}).", container_name, output.display(), format_size(size));
+        }
+        None => {
+            copy_from_stream(&mut tcp_stream, &mut io::stdout().lock())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Makes an image of the local store with a tarball of a root filesystem as its only layer.
+/// Propagates the error down the stack trace.
+fn import_image(tarball: &Path, reference: &str, entrypoint: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let reference: ImageReference = reference.parse()?;
+    if reference.digest.is_some() {
+        return Err("Error: images are imported with a tag, not a digest.".into());
+    }
+    let store = Store::open()?;
+
+    let mut builder = ImageBuilder::new(&store, Path::new("."));
+    builder.add_archive(tarball)?;
+    if let Some(entrypoint) = entrypoint {
+        let args = entrypoint.split_whitespace().map(str::to_owned).collect::<Vec<_>>();
+        builder.apply(&Instruction::Entrypoint(args))?;
+    }
+
+    let digest = builder.finish(&reference)?;
+    status!("Imported {} as {} ({}).", tarball.display(), reference, short_digest(&digest));
+
+    Ok(())
+}
+
+/// Writes images of the local store to an archive in the OCI image layout,
+/// which other OCI tools can read too.
+/// Propagates the error down the stack trace.
+fn save_images(images: &[String], output: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let store = Store::open()?;
+
+    let mut saved = BTreeMap::new();
+    for image in images {
+        let reference: ImageReference = image.parse()?;
+        let digest = store.resolve(&reference)?
+            .ok_or_else(|| format!("Error: no image {} in the local store.", reference))?;
+        saved.insert(reference.store_name(), digest);
+    }
+
+    match output {
+        Some(output) => {
+            let file = std::fs::File::create(output)?;
+            if let Err(err) = store.save(&saved, io::BufWriter::new(file)) {
+                let _ = std::fs::remove_file(output);
+                return Err(err.into());
+            }
+            status!("Saved {} to {}.", saved.keys().cloned().collect::<Vec<_>>().join(", "), output.display());
+        }
+        None => store.save(&saved, io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Loads the images of an archive in the OCI image layout into the local store.
+/// Propagates the error down the stack trace.
+fn load_images(input: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let store = Store::open()?;
+
+    let loaded = match input {
+        Some(input) => store.load(io::BufReader::new(std::fs::File::open(input)?))?,
+        None => store.load(io::stdin().lock())?,
+    };
+
+    for (reference, digest) in loaded {
+        status!("Loaded {} ({}).", reference, short_digest(&digest));
+    }
+
+    Ok(())
+}
+
+/// Checks that the data has the expected digest.
+fn check_digest(data: &[u8], expected: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let actual = store::digest(data);
+    if actual != expected {
+        return Err(format!("Error: digest mismatch: expected {}, got {}.", expected, actual).into());
+    }
+    Ok(())
+}
+
+/// Shortens a digest for printing.
+fn short_digest(digest: &str) -> &str {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    &hex[..hex.len().min(12)]
+}
+
+/// Formats a number of seconds for printing, e.g. `3d 4h` or `5m 12s`.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+/// Formats a size in bytes for printing.
+fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit + 1 < UNITS.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", size),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// Prints daemon messages to a standard output.
+/// Propagates the error down the stack trace.
+fn daemon_trace(selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    output::print_text("trace", &JokerClient::from_selection(selection)?.trace()?)?;
 
-    let mut tcp_stream = TcpStream::connect(config.current_daemon.socket_address)?;
-    tcp_stream.set_nodelay(true)?;
+    Ok(())
+}
 
-    let config_name = config_path.split('/').last()
-        .ok_or("Error: bad file path.")?.as_bytes().to_owned();
-    let config_file = std::fs::read(config_path)?;
+/// Receives a log of a specified container.
+/// Propagates the error down the stack trace.
+fn get_logs(container_name: &str, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    output::print_text("logs", &JokerClient::from_selection(selection)?.logs(container_name)?)?;
 
-    // Send the type of request
-    let request = Requests::Send;
-    tcp_stream.write_all(&[request as u8])?;
+    Ok(())
+}
+
+/// The result of a command on one of several daemons.
+#[derive(Serialize)]
+struct BroadcastResult {
+    daemon: String,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+}
+
+/// Returns clients of the daemons of `--all` or `--group`, if one of them is given.
+/// Propagates the error down the stack trace.
+fn broadcast_clients(sub_matches: &ArgMatches, selection: &Selection) -> Result<Option<Vec<JokerClient>>, JokerError> {
+    let daemons = match (sub_matches.get_flag("all"), sub_matches.get_one::<String>("group")) {
+        (true, _) => selection.get_config()?.all_daemons(),
+        (false, Some(group)) => selection.get_config()?.group(group)?,
+        (false, None) => return Ok(None),
+    };
+
+    Ok(Some(daemons.into_iter().map(|daemon| JokerClient::new(&daemon.name, daemon.socket_address)).collect()))
+}
+
+/// Prints how a command went on each daemon. Fails if it failed on any of them.
+fn report_broadcast<T>(action: &str, clients: &[JokerClient], results: Vec<Result<T, String>>) -> Result<(), JokerError> {
+    let results = clients.iter().zip(results)
+        .map(|(client, result)| BroadcastResult {
+            daemon: client.daemon().name.clone(),
+            success: result.is_ok(),
+            error: result.err(),
+            output: None,
+        })
+        .collect::<Vec<_>>();
+
+    output::print_list(&["DAEMON", "RESULT"], &results, |result| vec![
+        result.daemon.clone(),
+        result.error.clone().unwrap_or_else(|| "ok".to_owned()),
+    ]).map_err(|err| JokerError::Other(err.into()))?;
+
+    broadcast_outcome(action, &results)
+}
+
+/// Fails if a command failed on any of the daemons.
+fn broadcast_outcome(action: &str, results: &[BroadcastResult]) -> Result<(), JokerError> {
+    match results.iter().filter(|result| !result.success).count() {
+        0 => Ok(()),
+        failed => Err(JokerError::Other(
+            format!("Error: {} failed on {} of {} daemons.", action, failed, results.len()).into(),
+        )),
+    }
+}
+
+/// Prints the output of a container on each of several daemons, under the name of the daemon.
+/// Fails if the logs couldn't be got from any of them.
+fn print_broadcast_logs(clients: &[JokerClient], container_name: &str) -> Result<(), JokerError> {
+    let results = client::broadcast(clients, |client| client.logs(container_name));
+    let results = clients.iter().zip(results)
+        .map(|(client, result)| BroadcastResult {
+            daemon: client.daemon().name.clone(),
+            success: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+            output: result.ok(),
+        })
+        .collect::<Vec<_>>();
+
+    match output::format() {
+        output::Format::Json => println!("{}", serde_json::to_string_pretty(&results).map_err(|err| JokerError::Other(err.into()))?),
+        output::Format::Table | output::Format::Plain => {
+            for result in &results {
+                println!("==> {} <==", result.daemon);
+                println!("{}", result.output.as_ref().or(result.error.as_ref()).map_or("", String::as_str));
+            }
+        }
+    }
+
+    broadcast_outcome("logs", &results)
+}
+
+/// Stops containers of a current daemon.
+/// Propagates the error down the stack trace.
+fn stop_containers(container_names: &[String], selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    for container_name in container_names {
+        client.stop(container_name)
+            .map_err(|err| format!("Error: couldn't stop {}: {}", container_name, err))?;
+        status!("Stopped {}.", container_name);
+    }
+
+    Ok(())
+}
+
+/// Restarts containers of a current daemon, starting the stopped ones.
+/// Propagates the error down the stack trace.
+fn restart_containers(container_names: &[String], selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    for container_name in container_names {
+        client.restart(container_name)
+            .map_err(|err| format!("Error: couldn't restart {}: {}", container_name, err))?;
+        status!("Restarted {}.", container_name);
+    }
+
+    Ok(())
+}
+
+/// Prints the state of a specified container.
+/// Propagates the error down the stack trace.
+fn inspect_container(container_name: &str, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let info = JokerClient::from_selection(selection)?.inspect(container_name)?;
+
+    output::print_record(&info, &[
+        ("Name", Some(info.name.clone())),
+        ("Status", Some(info.status.clone())),
+        ("PID", info.pid.map(|pid| pid.to_string())),
+        ("Exit code", info.exit_code.map(|exit_code| exit_code.to_string())),
+        ("Runtime", Some(info.runtime.clone()).filter(|runtime| !runtime.is_empty())),
+        ("Restart policy", Some(info.restart_policy.clone())),
+        ("Restart count", Some(info.restart_count.to_string())),
+        ("Health", info.health.clone()),
+        ("Failing streak", info.health.as_ref().map(|_| info.failing_streak.to_string())),
+    ])?;
+
+    Ok(())
+}
+
+/// Prints the resource usage of a container of a current daemon, or writes it to a CSV file.
+/// With a history every sample is a row, otherwise the latest sample is printed as a record.
+/// Propagates the error down the stack trace.
+fn show_stats(container_name: &str, history: Option<Duration>, export: Option<&Path>, selection: &Selection) -> Result<(), JokerError> {
+    let samples = JokerClient::from_selection(selection)?.stats(container_name, history)?;
+
+    if let Some(path) = export {
+        let mut csv = String::from("timestamp,cpu_percent,memory,memory_limit,read_bytes,write_bytes,rx_bytes,tx_bytes\n");
+        for sample in &samples {
+            csv.push_str(&format!(
+                "{},{:.2},{},{},{},{},{},{}\n",
+                sample.timestamp, sample.cpu_percent, sample.memory, sample.memory_limit,
+                sample.read_bytes, sample.write_bytes, sample.rx_bytes, sample.tx_bytes,
+            ));
+        }
+        std::fs::write(path, csv)?;
+        status!("Wrote {} samples of {} to {}.", samples.len(), container_name, path.display());
+        return Ok(());
+    }
+
+    let to_json_error = |err: serde_json::Error| JokerError::Other(err.into());
+    let memory = |sample: &container::StatsSample| match sample.memory_limit {
+        0 => format_size(sample.memory),
+        limit => format!("{} / {}", format_size(sample.memory), format_size(limit)),
+    };
+
+    if history.is_none() {
+        let sample = samples.last()
+            .ok_or_else(|| JokerError::Protocol("no sample in the answer".to_owned()))?;
+        return output::print_record(sample, &[
+            ("Time", Some(format_timestamp(sample.timestamp))),
+            ("CPU", Some(format!("{:.1}%", sample.cpu_percent))),
+            ("Memory", Some(memory(sample))),
+            ("Disk IO", Some(format!("{} read, {} written", format_size(sample.read_bytes), format_size(sample.write_bytes)))),
+            ("Network IO", Some(format!("{} received, {} sent", format_size(sample.rx_bytes), format_size(sample.tx_bytes)))),
+        ]).map_err(to_json_error);
+    }
+
+    output::print_list(&["TIME", "CPU", "MEMORY", "READ", "WRITTEN", "RECEIVED", "SENT"], &samples, |sample| vec![
+        format_timestamp(sample.timestamp),
+        format!("{:.1}%", sample.cpu_percent),
+        memory(sample),
+        format_size(sample.read_bytes),
+        format_size(sample.write_bytes),
+        format_size(sample.rx_bytes),
+        format_size(sample.tx_bytes),
+    ]).map_err(to_json_error)?;
+
+    // The peaks answer most capacity questions at a glance
+    if output::format() == output::Format::Table && !samples.is_empty() {
+        let average_cpu = samples.iter().map(|sample| sample.cpu_percent).sum::<f64>() / samples.len() as f64;
+        let peak_cpu = samples.iter().map(|sample| sample.cpu_percent).fold(0.0, f64::max);
+        let peak_memory = samples.iter().map(|sample| sample.memory).max().unwrap_or_default();
+        println!(
+            "{} samples: CPU {:.1}% on average, {:.1}% at peak; memory {} at peak.",
+            samples.len(), average_cpu, peak_cpu, format_size(peak_memory),
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the SBOM a container of a current daemon was run with, as it was attached.
+/// Propagates the error down the stack trace.
+fn print_sbom(container_name: &str, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let sbom = JokerClient::from_selection(selection)?.sbom(container_name)
+        .map_err(|err| format!("Error: no SBOM of {}: {}", container_name, err))?;
+    println!("{}", String::from_utf8(sbom)?);
+
+    Ok(())
+}
+
+/// Deploys the services of a compose file on a current daemon. Every container
+/// is checked before anything is sent, so a mistake doesn't leave a half-deployed stack.
+/// Propagates the error down the stack trace.
+fn stack_up(path: &Path, dry_run: bool, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let stack = Stack::open(path)?;
+    let client = JokerClient::from_selection(selection)?;
+
+    let order = stack.start_order()?;
+    let mut prepared = Vec::with_capacity(order.len());
+    let mut healthchecks = BTreeMap::<&str, bool>::new();
+    for name in &order {
+        let service = &stack.file.services[*name];
+        let options = stack.run_options(name, service)
+            .map_err(|err| format!("Error: service {}: {}", name, err))?;
+        let container_path = stack.container_path(service);
+
+        let mut container = prepare_container(&container_path, &options)
+            .map_err(|err| format!("Error: service {}: {}", name, err))?;
+        attach_sbom(&mut container, &container_path, None)?;
+
+        // waiting for a health check which never runs would block a dependent container forever
+        for (dependency, condition) in service.depends_on.conditions()? {
+            if condition == DependencyCondition::Healthy && !healthchecks[dependency] {
+                return Err(format!(
+                    "Error: service {} waits for service {} to be healthy, but it has no health check.",
+                    name, dependency,
+                ).into());
+            }
+        }
+        let config = ContainerConfig::parse(&String::from_utf8_lossy(&container.config))?;
+        healthchecks.insert(*name, config.has_healthcheck());
+
+        prepared.push(container);
+    }
+
+    if dry_run {
+        return print_dry_run(&[client.daemon().clone()], &prepared);
+    }
+    client.send(prepared)?;
+
+    status!(
+        "Deployed stack {} ({}) at daemon {}.",
+        stack.name,
+        order.join(", "),
+        client.daemon().name,
+    );
+
+    Ok(())
+}
+
+/// Stops and removes all the containers of a stack of a current daemon.
+/// Propagates the error down the stack trace.
+fn stack_down(name: &str, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    status!("{}", JokerClient::from_selection(selection)?.stack_down(name)?);
+
+    Ok(())
+}
+
+/// Prints the stacks of a current daemon.
+/// Propagates the error down the stack trace.
+fn list_stacks(selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let stacks = JokerClient::from_selection(selection)?.stacks()?;
+
+    output::print_list(&["NAME", "CONTAINERS"], &stacks, |stack| vec![
+        stack.name.clone(),
+        stack.containers.join(", "),
+    ])?;
+
+    Ok(())
+}
+
+/// Prints the containers of a current daemon.
+/// Propagates the error down the stack trace.
+fn list_containers(filters: &[ContainerFilter], selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let containers = JokerClient::from_selection(selection)?.list()?.into_iter()
+        .filter(|info| filters.iter().all(|filter| filter.matches(info)))
+        .collect::<Vec<_>>();
+
+    output::print_list(&["NAME", "STATUS", "HEALTH", "RESTARTS", "LABELS"], &containers, |info| vec![
+        info.name.clone(),
+        info.status.clone(),
+        info.health.clone().unwrap_or_else(|| "-".to_owned()),
+        info.restart_count.to_string(),
+        match info.labels.is_empty() {
+            true => "-".to_owned(),
+            false => info.labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(","),
+        },
+    ])?;
+
+    Ok(())
+}
 
-    // Send the size of config name and config name itself
-    tcp_stream.write_all(&(config_name.len() as u64).to_le_bytes())?;
-    tcp_stream.write_all(&config_name)?;
+/// Sends a config file to a daemon.
+/// Propagates the error down the stack trace.
+fn send_config(config_path: &String, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    let config_name = config_path.split('/').next_back()
+        .ok_or("Error: bad file path.")?;
+    let config_file = std::fs::read(config_path)?;
 
-    // Send the size of the config and the config itself
-    tcp_stream.write_all(&(config_file.len() as u64).to_le_bytes())?;
-    tcp_stream.write_all(&config_file)?;
+    client.send_config(config_name, &config_file)?;
 
-    println!(
+    status!(
         "Sending config file {} at daemon {}.",
-        String::from_utf8(config_name)?,
-        config.current_daemon.name,
+        config_name,
+        client.daemon().name,
     );
 
+    Ok(())
+}
+
+/// Collects all the values of a repeatable argument.
+fn get_all<T: Clone + Send + Sync + 'static>(sub_matches: &ArgMatches, id: &str) -> Vec<T> {
+    sub_matches.get_many::<T>(id)
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// Generates a signing key and writes it with its public key.
+/// Propagates the error down the stack trace.
+fn generate_key(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let public_path = path.with_extension("pub");
+    let key = signature::generate_key()?;
+    signature::write_key(&key, path, &public_path).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => format!("Error: {} already exists.", path.display()).into(),
+        _ => Box::<dyn std::error::Error>::from(err),
+    })?;
+
+    let public_key = std::fs::read_to_string(&public_path)?;
+    status!("Wrote the key to {} and its public key to {}.", path.display(), public_path.display());
+    status!("Key id: {}", signature::key_id(&public_key));
+
+    Ok(())
+}
+
+/// Signs the digest of a binary, written to a `.sig` file next to it,
+/// or of the manifest of an image, kept in the local store.
+/// Propagates the error down the stack trace.
+fn sign_container(target: &str, key_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let key = signature::read_key(key_path)?;
+
+    let path = Path::new(target);
+    if path.is_file() {
+        let (digest, _) = store::digest_reader(io::BufReader::new(std::fs::File::open(path)?))?;
+        let signature_path = format!("{}.sig", target);
+
+        let mut signatures = signature::read_signatures(Path::new(&signature_path))?;
+        let signature = Signature::sign(&key, &digest);
+        status!("Signed {} ({}) with key {}.", target, short_digest(&digest), signature.key_id());
+        signature::add_signature(&mut signatures, signature);
+        std::fs::write(&signature_path, serde_json::to_vec_pretty(&signatures)?)?;
+
+        return Ok(());
+    }
+
+    let reference: ImageReference = target.parse()?;
+    let store = Store::open()?;
+    let digest = store.resolve(&reference)?
+        .ok_or_else(|| format!("Error: {} is neither a file nor an image of the local store.", target))?;
+
+    let mut signatures = store.signatures(&digest)?;
+    let signature = Signature::sign(&key, &digest);
+    status!("Signed {} ({}) with key {}.", reference, short_digest(&digest), signature.key_id());
+    signature::add_signature(&mut signatures, signature);
+    store.write_signatures(&digest, &signatures)?;
+
+    Ok(())
+}
+
+/// Sends a request about the trusted keys to a daemon and prints its response.
+/// A daemon with a signature policy only runs containers signed by its trusted keys.
+/// Propagates the error down the stack trace.
+fn manage_trust(request: Requests, argument: Option<&str>, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    let keys = match (request, argument) {
+        // A public key is given either as its file or as the key itself
+        (Requests::TrustAdd, Some(public_key)) => {
+            let public_key = match Path::new(public_key).is_file() {
+                true => std::fs::read_to_string(public_key)?.trim().to_owned(),
+                false => public_key.to_owned(),
+            };
+            status!("{}", client.trust(&public_key)?);
+            return Ok(());
+        }
+        (Requests::TrustRemove, Some(key_id)) => {
+            status!("{}", client.untrust(key_id)?);
+            return Ok(());
+        }
+        _ => client.trusted_keys()?,
+    };
+    let keys = keys.into_iter()
+        .map(|public_key| TrustedKey { key_id: signature::key_id(&public_key), public_key })
+        .collect::<Vec<_>>();
+    output::print_list(&["KEY ID", "PUBLIC KEY"], &keys, |key| vec![key.key_id.clone(), key.public_key.clone()])?;
+
+    Ok(())
+}
+
+/// A daemon of the CLI config, as listed by `joker daemon list`.
+#[derive(Serialize)]
+struct DaemonInfo {
+    name: String,
+    socket_address: DaemonAddress,
+    current: bool,
+    labels: BTreeMap<String, String>,
+    groups: Vec<String>,
+}
+
+/// Prints the daemons of the CLI config with their labels and groups.
+/// Propagates the error down the stack trace.
+fn list_daemons(selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let config = selection.get_config()?;
+    let daemons = config.all_daemons().into_iter()
+        .map(|daemon| DaemonInfo {
+            current: daemon.name == config.current_daemon.name,
+            labels: config.labels(&daemon.name),
+            groups: config.groups_of(&daemon.name),
+            name: daemon.name,
+            socket_address: daemon.socket_address,
+        })
+        .collect::<Vec<_>>();
+
+    output::print_list(&["NAME", "ADDRESS", "LABELS", "GROUPS"], &daemons, |daemon| vec![
+        match daemon.current {
+            true => format!("{} *", daemon.name),
+            false => daemon.name.clone(),
+        },
+        daemon.socket_address.to_string(),
+        match daemon.labels.is_empty() {
+            true => "-".to_owned(),
+            false => daemon.labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(","),
+        },
+        match daemon.groups.is_empty() {
+            true => "-".to_owned(),
+            false => daemon.groups.join(","),
+        },
+    ])?;
+
+    Ok(())
+}
+
+/// Sets and removes labels of a daemon of the CLI config.
+/// Propagates the error down the stack trace.
+fn label_daemon(daemon_name: &str, changes: &[String], selection: &Selection) -> Result<(), JokerError> {
+    let mut config = selection.get_config()?;
+    if config.find_daemon(daemon_name).is_none() {
+        return Err(JokerError::UnknownDaemon(daemon_name.to_owned()));
+    }
+    let changes = changes.iter()
+        .map(|change| daemon::parse_label_change(change))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let labels = config.labels.entry(daemon_name.to_owned()).or_default();
+    for (key, value) in changes {
+        match value {
+            Some(value) => labels.insert(key, value),
+            None => labels.remove(&key),
+        };
+    }
+    if labels.is_empty() {
+        config.labels.remove(daemon_name);
+    }
+    selection.write_config(&config)?;
+
+    status!("Updated the labels of daemon {}.", daemon_name);
+
+    Ok(())
+}
+
+/// Adds daemons of the CLI config to a group, or removes them from it.
+/// Propagates the error down the stack trace.
+fn group_daemons(group: &str, daemon_names: &[String], remove: bool, selection: &Selection) -> Result<(), JokerError> {
+    let mut config = selection.get_config()?;
+    if let Some(unknown) = daemon_names.iter().find(|name| config.find_daemon(name).is_none()) {
+        return Err(JokerError::UnknownDaemon(unknown.clone()));
+    }
+
+    let members = config.groups.entry(group.to_owned()).or_default();
+    for name in daemon_names {
+        match remove {
+            true => members.remove(name),
+            false => members.insert(name.clone()),
+        };
+    }
+    if members.is_empty() {
+        config.groups.remove(group);
+    }
+    selection.write_config(&config)?;
+
+    match remove {
+        true => status!("Removed {} from group {}.", daemon_names.join(", "), group),
+        false => status!("Added {} to group {}.", daemon_names.join(", "), group),
+    }
+
+    Ok(())
+}
+
+/// A daemon found by `joker daemon discover`, with the name it has in the CLI config if it's there.
+#[derive(Serialize)]
+struct DiscoveryResult {
+    #[serde(flatten)]
+    daemon: DiscoveredDaemon,
+    config_name: Option<String>,
+}
+
+/// Lists the daemons advertising themselves on the local network and adds the new ones
+/// to the CLI config: all of them with `add_all`, otherwise the ones confirmed at a prompt
+/// when the input is a terminal. A daemon is new if no daemon of the config has its address.
+/// Propagates the error down the stack trace.
+fn discover_daemons(timeout: Duration, add_all: bool, selection: &Selection) -> Result<(), JokerError> {
+    // Discovery works without a config, adding the daemons doesn't
+    let known = selection.get_config().map(|config| config.all_daemons()).unwrap_or_default();
+
+    let results = discovery::discover(timeout).map_err(JokerError::Connection)?.into_iter()
+        .map(|daemon| DiscoveryResult {
+            config_name: known.iter()
+                .find(|known| known.socket_address == daemon.address)
+                .map(|known| known.name.clone()),
+            daemon,
+        })
+        .collect::<Vec<_>>();
+    if results.is_empty() {
+        status!("No daemon answered within {:?}.", timeout);
+        return Ok(());
+    }
+
+    output::print_list(&["NAME", "ADDRESS", "VERSION", "IN CONFIG"], &results, |result| vec![
+        result.daemon.name.clone(),
+        result.daemon.address.to_string(),
+        result.daemon.version.clone().unwrap_or_else(|| "-".to_owned()),
+        result.config_name.clone().unwrap_or_else(|| "-".to_owned()),
+    ]).map_err(|err| JokerError::Other(err.into()))?;
+
+    let ask = !add_all && io::stdin().is_terminal();
+    for result in results.iter().filter(|result| result.config_name.is_none()) {
+        let name = result.daemon.name.split_whitespace().collect::<Vec<_>>().join("-");
+        if known.iter().any(|known| known.name == name) {
+            eprintln!("Warning: daemon {} at {} isn't added, the config has another daemon named so.", name, result.daemon.address);
+            continue;
+        }
+        let add = add_all || (ask && confirm(&format!("Add daemon {} at {}?", name, result.daemon.address))?);
+        if !add {
+            continue;
+        }
+
+        selection.add_daemon(&name, result.daemon.address.clone())?;
+        status!("Added daemon {} at {}.", name, result.daemon.address);
+    }
+
+    Ok(())
+}
+
+/// Writes the systemd unit of a daemon executable, to the directory systemd loads units from
+/// unless an output is given, and prints how to start it. The daemon isn't part of joker,
+/// so the executable has to be given, and it has to exist.
+/// Propagates the error down the stack trace.
+fn install_service(executable: &Path, args: &[String], user: bool, output: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let executable = std::path::absolute(executable)?;
+    if !executable.is_file() {
+        return Err(format!("Error: no daemon executable at {}.", executable.display()).into());
+    }
+    let unit = systemd::unit(&executable, args, user);
+
+    let path = match output {
+        Some(output) if output == Path::new("-") => {
+            io::stdout().write_all(unit.as_bytes())?;
+            return Ok(());
+        }
+        Some(output) => output.to_path_buf(),
+        None => systemd::unit_path(user)
+            .ok_or("Error: no config directory for the unit of the user, set XDG_CONFIG_HOME or HOME.")?,
+    };
+    let parent = path.parent().filter(|parent| !parent.as_os_str().is_empty());
+    parent.map_or(Ok(()), std::fs::create_dir_all).and_then(|_| std::fs::write(&path, unit)).map_err(|err| match err.kind() {
+        io::ErrorKind::PermissionDenied if !user => {
+            format!("Error: can't write {}: {}. Run as root, or use --user for a rootless daemon.", path.display(), err)
+        }
+        _ => format!("Error: can't write {}: {}.", path.display(), err),
+    })?;
+
+    status!("Wrote {}.", path.display());
+    if output.is_none() {
+        let scope = if user { " --user" } else { "" };
+        status!("Start the daemon with: systemctl{0} daemon-reload && systemctl{0} enable --now {1}", scope, systemd::UNIT_NAME);
+    }
+
+    Ok(())
+}
+
+/// Asks a yes or no question on the terminal. Anything but `y` or `yes` is a no.
+fn confirm(question: &str) -> Result<bool, JokerError> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush().map_err(|err| JokerError::Other(err.into()))?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|err| JokerError::Other(err.into()))?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// A key a daemon trusts, as listed by `joker trust list`.
+#[derive(Serialize)]
+struct TrustedKey {
+    key_id: String,
+    public_key: String,
+}
+
+/// Sends a volume request to a daemon and prints its response.
+/// Propagates the error down the stack trace.
+fn manage_volume(request: Requests, volume_name: Option<&str>, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    match (request, volume_name) {
+        (Requests::VolumeCreate, Some(name)) => status!("{}", client.create_volume(name)?),
+        (Requests::VolumeRemove, Some(name)) => status!("{}", client.remove_volume(name)?),
+        _ => output::print_text("volumes", &client.volumes()?)?,
+    }
+
+    Ok(())
+}
+
+/// Makes a current daemon run one of its containers on a cron expression.
+/// Propagates the error down the stack trace.
+fn add_schedule(expression: &CronExpression, container_name: &str, selection: &Selection) -> Result<(), JokerError> {
+    let client = JokerClient::from_selection(selection)?;
+    let id = client.schedule(expression, container_name)?;
+
+    status!("Scheduled {} at \"{}\" on daemon {} as {}.", container_name, expression, client.daemon().name, id);
+
+    Ok(())
+}
+
+/// Prints the schedules of a current daemon with the outcome of their last runs.
+/// Propagates the error down the stack trace.
+fn list_schedules(selection: &Selection) -> Result<(), JokerError> {
+    let schedules = JokerClient::from_selection(selection)?.schedules()?;
+
+    output::print_list(&["ID", "CRON", "CONTAINER", "LAST RUN", "RESULT", "NEXT RUN"], &schedules, |schedule: &ScheduleInfo| vec![
+        schedule.id.clone(),
+        schedule.expression.clone(),
+        schedule.container.clone(),
+        schedule.last_run.map_or_else(|| "-".to_owned(), format_timestamp),
+        match (&schedule.last_status, schedule.last_exit_code) {
+            (Some(status), Some(code)) => format!("{} ({})", status, code),
+            (Some(status), None) => status.clone(),
+            (None, _) => "-".to_owned(),
+        },
+        schedule.next_run.map_or_else(|| "-".to_owned(), format_timestamp),
+    ]).map_err(|err| JokerError::Other(err.into()))
+}
+
+/// Formats a Unix timestamp as a UTC date and time, e.g. `2024-05-01 03:00 UTC`.
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let minutes = timestamp % 86400 / 60;
+
+    // The civil date of a number of days since 1970-01-01, in eras of 400 years from 0000-03-01
+    let shifted = days + 719468;
+    let era = shifted.div_euclid(146097);
+    let day_of_era = shifted.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, minutes / 60, minutes % 60)
+}
+
+/// Sends requests for creating, listing and deleting secrets.
+/// The value of a secret is sent only once, on creation, and the daemon never sends it back.
+/// Propagates the error down the stack trace.
+fn manage_secret(request: Requests, secret_name: Option<&str>, value: Option<&[u8]>, selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let client = JokerClient::from_selection(selection)?;
+
+    match (request, secret_name) {
+        (Requests::SecretCreate, Some(name)) => status!("{}", client.create_secret(name, value.unwrap_or_default())?),
+        (Requests::SecretRemove, Some(name)) => status!("{}", client.remove_secret(name)?),
+        _ => output::print_text("secrets", &client.secrets()?)?,
+    }
+
+    Ok(())
+}
+
+/// Executes the `joker-<name>` executable of an external subcommand with the rest
+/// of the arguments. A plugin learns the current daemon from `JOKER_DAEMON` and
+/// `JOKER_DAEMON_ADDRESS`, and the CLI config from `JOKER_CONFIG`. A failed plugin
+/// makes joker exit with the same code.
+fn run_plugin(name: &str, args: &[OsString], selection: &Selection) -> Result<(), JokerError> {
+    let path = plugin::find(name).ok_or_else(|| JokerError::Other(format!("Error: no plugin {}.", name).into()))?;
+
+    let mut plugin = std::process::Command::new(&path);
+    plugin.args(args);
+    let config_path = selection.config_path();
+    plugin.env(daemon::CONFIG_VAR, config_path.canonicalize().unwrap_or(config_path));
+    // A plugin may not need a daemon at all, so a missing CLI config isn't an error here
+    if let Ok(daemon) = selection.current_daemon() {
+        plugin.env("JOKER_DAEMON", &daemon.name);
+        plugin.env("JOKER_DAEMON_ADDRESS", daemon.socket_address.to_string());
+    }
+
+    let status = plugin.status()?;
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => Err(JokerError::Plugin { name: name.to_owned(), code }),
+        // Killed by a signal
+        None => Err(JokerError::Plugin { name: name.to_owned(), code: 1 }),
+    }
+}
+
+/// An external subcommand, as listed by `joker plugins`.
+#[derive(Serialize)]
+struct PluginInfo {
+    name: String,
+    path: PathBuf,
+}
+
+/// Prints the external subcommands found on the `PATH`.
+fn list_plugins() -> Result<(), Box<dyn std::error::Error>> {
+    let plugins = plugin::list().into_iter()
+        .map(|(name, path)| PluginInfo { name, path })
+        .collect::<Vec<_>>();
+    output::print_list(&["NAME", "PATH"], &plugins, |plugin| vec![plugin.name.clone(), plugin.path.display().to_string()])?;
+
+    Ok(())
+}
+
+/// How long completing waits for the containers of the current daemon.
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Prints the names of the daemons of the config or of the containers of the current
+/// daemon, one per line, when the last of the words is one. Nothing is printed if they
+/// can't be read, not to break the completion: the scripts complete the word themselves.
+fn complete_names(command: &Command, words: &[String], selection: &Selection) -> Result<(), Box<dyn std::error::Error>> {
+    let names: Vec<String> = match completions::names_at(command, words) {
+        Some(completions::Names::Daemons) => selection.get_config()
+            .map(|config| std::iter::once(config.current_daemon.name).chain(config.daemons.into_keys()).collect())
+            .unwrap_or_default(),
+        Some(completions::Names::Containers) => JokerClient::from_selection(selection)
+            .and_then(|client| client.with_transport(TcpTransport { timeout: Some(COMPLETION_TIMEOUT) }).list())
+            .map(|containers| containers.into_iter().map(|container| container.name).collect())
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+
+    for name in names {
+        println!("{}", name);
+    }
 
     Ok(())
 }
@@ -299,7 +3503,44 @@ fn show_help_message(command: &mut Command) -> Result<(), Box<dyn std::error::Er
     Ok(())
 }
 
-fn read_all_from_stream(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+fn write_all_to_stream(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    stream.write_all(&(message.len() as u64).to_le_bytes())?;
+    stream.write_all(message)
+}
+
+/// Streams a length-prefixed message into a writer without holding it in memory.
+fn copy_from_stream(stream: &mut impl Read, writer: &mut impl Write) -> io::Result<u64> {
+    let mut size = [0u8; 8];
+    stream.read_exact(&mut size)?;
+    let size = u64::from_le_bytes(size);
+
+    let copied = io::copy(&mut stream.take(size), writer)?;
+    if copied != size {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the connection to the daemon was lost"));
+    }
+    Ok(size)
+}
+
+/// Streams a length-prefixed message into a file, removing the file if the download fails.
+fn download_to_file(stream: &mut impl Read, path: &Path) -> io::Result<u64> {
+    let mut file = std::fs::File::create(path)?;
+    copy_from_stream(stream, &mut file).inspect_err(|_| {
+        let _ = std::fs::remove_file(path);
+    })
+}
+
+/// Reads the result of a request which a daemon may fail:
+/// an empty message followed by the error message on a failure.
+fn read_result_from_stream(stream: &mut impl Read) -> Result<Vec<u8>, JokerError> {
+    let result = read_all_from_stream(stream).map_err(JokerError::Connection)?;
+    if result.is_empty() {
+        let message = read_all_from_stream(stream).map_err(JokerError::Connection)?;
+        return Err(JokerError::Rejected(String::from_utf8_lossy(&message).into_owned()));
+    }
+    Ok(result)
+}
+
+fn read_all_from_stream(stream: &mut impl Read) -> io::Result<Vec<u8>> {
     let mut size_of_message = [0u8; 8];
     stream.read_exact(&mut size_of_message[..])?;
     let size_of_message = u64::from_le_bytes(size_of_message);
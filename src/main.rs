@@ -4,6 +4,9 @@ fn main() {
     let mut command = cli();
     match execute(&mut command) {
         Ok(_) => {},
-        Err(err) => {println!("Execution was stopped because of the previous error: {}", err)},
+        Err(err) => {
+            println!("Execution was stopped because of the previous error: {}", err);
+            std::process::exit(err.exit_code());
+        },
     }
 }
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::daemon::Daemon;
+use crate::transport::{Connection, Transport};
+
+/// A transport which never touches the network, for testing code built on
+/// `JokerClient`. Every connection reads the next scripted response, or nothing
+/// if none is left, and the bytes written to it are recorded as a request.
+///
+/// ```
+/// use joker::client::JokerClient;
+/// use joker::daemon::Requests;
+/// use joker::mock::MockTransport;
+///
+/// let transport = MockTransport::new();
+/// transport.respond_message(b"listening on :8080");
+/// let client = JokerClient::new("test", "127.0.0.1:8080".parse().unwrap())
+///     .with_transport(transport.clone());
+///
+/// assert_eq!(client.logs("app")?, "listening on :8080");
+/// assert_eq!(transport.requests()[0][0], Requests::Logs as u8);
+/// # Ok::<(), joker::errors::JokerError>(())
+/// ```
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    responses: VecDeque<Vec<u8>>,
+    requests: Vec<Vec<u8>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    /// Queues the raw bytes a connection reads.
+    pub fn respond(&self, response: impl Into<Vec<u8>>) -> &Self {
+        self.state().responses.push_back(response.into());
+        self
+    }
+
+    /// Queues a length-prefixed message, which is how the daemon answers most requests.
+    pub fn respond_message(&self, message: &[u8]) -> &Self {
+        self.respond(length_prefixed(message))
+    }
+
+    /// Queues a refusal of a request which a daemon may fail, with its reason.
+    pub fn respond_error(&self, message: &str) -> &Self {
+        let mut response = length_prefixed(&[]);
+        response.extend(length_prefixed(message.as_bytes()));
+        self.respond(response)
+    }
+
+    /// The bytes written over every connection so far, starting with the type of the request.
+    pub fn requests(&self) -> Vec<Vec<u8>> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        // A panicking test shouldn't hide the requests from the other ones
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Transport for MockTransport {
+    fn connect(&self, _daemon: &Daemon) -> io::Result<Box<dyn Connection>> {
+        let mut state = self.state();
+        let response = state.responses.pop_front().unwrap_or_default();
+        state.requests.push(Vec::new());
+
+        Ok(Box::new(MockConnection {
+            response: Cursor::new(response),
+            index: state.requests.len() - 1,
+            state: self.state.clone(),
+        }))
+    }
+}
+
+struct MockConnection {
+    response: Cursor<Vec<u8>>,
+    /// The request of this connection among the recorded ones.
+    index: usize,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl Read for MockConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.response.read(buf)
+    }
+}
+
+impl Write for MockConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.requests[self.index].extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn length_prefixed(message: &[u8]) -> Vec<u8> {
+    let mut data = (message.len() as u64).to_le_bytes().to_vec();
+    data.extend_from_slice(message);
+    data
+}
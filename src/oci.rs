@@ -0,0 +1,214 @@
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use serde_json::{json, Map, Value};
+use crate::container::{self, IdMapping, RunOptions, KNOWN_CAPABILITIES};
+use crate::errors::InvalidBundleError;
+use crate::image::{self, Descriptor, ImageConfig, Manifest};
+use crate::store::Store;
+
+/// The runtime spec of an OCI bundle.
+pub const SPEC_FILE: &str = "config.json";
+
+/// The capability sets of an OCI process which `--cap-add` extends.
+/// `--cap-drop` removes a capability from the ambient and inheritable sets too.
+const ADDED_CAPABILITY_SETS: &[&str] = &["bounding", "effective", "permitted"];
+const CAPABILITY_SETS: &[&str] = &["bounding", "effective", "permitted", "inheritable", "ambient"];
+
+/// An OCI runtime bundle: a `config.json` spec and the root filesystem it points to.
+pub struct Bundle {
+    pub name: String,
+    pub spec: Value,
+    rootfs: Rootfs,
+}
+
+/// Where the root filesystem of a bundle comes from.
+enum Rootfs {
+    /// A bundle directory with the root filesystem at a relative path.
+    Directory { bundle: PathBuf, path: PathBuf },
+    /// Layers of an image of the local store, applied one over another.
+    Layers { store: Store, layers: Vec<Descriptor> },
+}
+
+impl Bundle {
+    /// Checks whether a path looks like an OCI bundle.
+    pub fn is_bundle(path: &Path) -> bool {
+        path.is_dir() && path.join(SPEC_FILE).is_file()
+    }
+
+    /// Reads the spec of a bundle and checks the parts of it the daemon relies on.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let error = |message: &str| InvalidBundleError { message: format!("{}: {}", path.display(), message) };
+
+        let name = path.canonicalize()?
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| error("bad bundle path"))?
+            .to_owned();
+
+        let spec: Value = serde_json::from_slice(&fs::read(path.join(SPEC_FILE))?)?;
+
+        if !spec["ociVersion"].is_string() {
+            return Err(error("the spec has no ociVersion").into());
+        }
+        let has_args = spec["process"]["args"].as_array().is_some_and(|args| !args.is_empty());
+        if !has_args {
+            return Err(error("the spec has no process.args").into());
+        }
+
+        let rootfs = PathBuf::from(spec["root"]["path"].as_str().unwrap_or("rootfs"));
+        // The root filesystem is packed with the bundle, so it must lie inside of it
+        if !rootfs.components().all(|component| matches!(component, Component::Normal(_))) {
+            return Err(error("root.path must be relative to the bundle").into());
+        }
+        if !path.join(&rootfs).is_dir() {
+            return Err(error("root.path is not a directory").into());
+        }
+
+        Ok(Bundle { name, spec, rootfs: Rootfs::Directory { bundle: path.to_owned(), path: rootfs } })
+    }
+
+    /// Makes a bundle of an image of the local store.
+    /// The spec runs the entrypoint of the image with its environment.
+    pub fn from_image(name: &str, store: &Store, manifest_digest: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let manifest: Manifest = serde_json::from_slice(&store.read_blob(manifest_digest)?)?;
+        let config: ImageConfig = serde_json::from_slice(&store.read_blob(&manifest.config.digest)?)?;
+
+        let spec = image::runtime_spec(&config);
+        if spec["process"]["args"].as_array().is_none_or(|args| args.is_empty()) {
+            return Err(InvalidBundleError { message: format!("image {} has no entrypoint", name) }.into());
+        }
+
+        Ok(Bundle {
+            name: name.to_owned(),
+            spec,
+            rootfs: Rootfs::Layers { store: store.clone(), layers: manifest.layers },
+        })
+    }
+
+    /// Applies the options of the `run` command which have an equivalent
+    /// in the OCI spec: environment, capabilities, id mappings
+    /// and a read-only root filesystem.
+    pub fn apply(&mut self, options: &RunOptions) -> Result<(), Box<dyn std::error::Error>> {
+        let process = object_at(&mut self.spec, &["process"])?;
+
+        if !options.env.is_empty() {
+            let mut env = process.get("env").and_then(Value::as_array).cloned().unwrap_or_default();
+            for var in &options.env {
+                let prefix = format!("{}=", var.key);
+                env.retain(|v| !v.as_str().is_some_and(|v| v.starts_with(&prefix)));
+                env.push(json!(var.to_string()));
+            }
+            process.insert("env".to_owned(), json!(env));
+        }
+
+        for cap in container::normalize_capabilities("--cap-drop", &options.cap_drop)? {
+            for set in CAPABILITY_SETS {
+                let caps = process.get_mut("capabilities").and_then(|caps| caps.get_mut(set));
+                if let Some(list) = caps.and_then(Value::as_array_mut) {
+                    list.retain(|c| cap != "ALL" && c.as_str() != Some(cap.as_str()));
+                }
+            }
+        }
+        for cap in container::normalize_capabilities("--cap-add", &options.cap_add)? {
+            let added = if cap == "ALL" { KNOWN_CAPABILITIES.to_vec() } else { vec![cap.as_str()] };
+            let capabilities = object_at(&mut self.spec, &["process", "capabilities"])?;
+            for set in ADDED_CAPABILITY_SETS {
+                let caps = capabilities.entry(*set).or_insert_with(|| json!([]));
+                if !caps.is_array() {
+                    *caps = json!([]);
+                }
+                let list = caps.as_array_mut().expect("the capability set was just made an array");
+                for &cap in &added {
+                    if !list.iter().any(|c| c.as_str() == Some(cap)) {
+                        list.push(json!(cap));
+                    }
+                }
+            }
+        }
+
+        if options.read_only {
+            object_at(&mut self.spec, &["root"])?.insert("readonly".to_owned(), json!(true));
+        }
+
+        let id_mappings = [("uidMappings", &options.uid_maps), ("gidMappings", &options.gid_maps)];
+        for (key, mappings) in id_mappings {
+            if mappings.is_empty() {
+                continue;
+            }
+            let mappings = mappings.iter()
+                .map(|&IdMapping { container_id, host_id, count }| json!({
+                    "containerID": container_id,
+                    "hostID": host_id,
+                    "size": count,
+                }))
+                .collect();
+            object_at(&mut self.spec, &["linux"])?.insert(key.to_owned(), mappings);
+            self.add_namespace("user")?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a namespace to the spec, unless the process already gets one.
+    fn add_namespace(&mut self, namespace: &str) -> Result<(), InvalidBundleError> {
+        let namespaces = object_at(&mut self.spec, &["linux"])?
+            .entry("namespaces")
+            .or_insert_with(|| json!([]));
+        if !namespaces.is_array() {
+            *namespaces = json!([]);
+        }
+        let list = namespaces.as_array_mut().expect("the namespaces were just made an array");
+        if !list.iter().any(|n| n["type"] == namespace) {
+            list.push(json!({ "type": namespace }));
+        }
+        Ok(())
+    }
+
+    /// The layers of the image a bundle is made of, bottom first.
+    /// `None` for a bundle directory.
+    pub fn image_layers(&self) -> Option<&[Descriptor]> {
+        match &self.rootfs {
+            Rootfs::Directory { .. } => None,
+            Rootfs::Layers { layers, .. } => Some(layers),
+        }
+    }
+
+    /// Packs the spec and the root filesystem into a tar archive.
+    /// Symlinks of the root filesystem are kept as they are.
+    pub fn pack(&self) -> io::Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.follow_symlinks(false);
+
+        let spec = serde_json::to_vec_pretty(&self.spec)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(spec.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, SPEC_FILE, spec.as_slice())?;
+
+        match &self.rootfs {
+            Rootfs::Directory { bundle, path } => builder.append_dir_all(path, bundle.join(path))?,
+            Rootfs::Layers { store, layers } => image::flatten_layers(store, layers, Path::new("rootfs"), &mut builder)?,
+        }
+
+        builder.into_inner()
+    }
+}
+
+/// Gets the object at a path of a spec, adding the missing objects on the way.
+/// A part of the path which holds something else than an object is an error.
+fn object_at<'a>(spec: &'a mut Value, path: &[&str]) -> Result<&'a mut Map<String, Value>, InvalidBundleError> {
+    let error = |depth: usize| InvalidBundleError {
+        message: format!("{} of the spec is not an object", if depth == 0 { "the root".to_owned() } else { path[..depth].join(".") }),
+    };
+
+    let mut object = spec.as_object_mut().ok_or_else(|| error(0))?;
+    for (depth, key) in path.iter().enumerate() {
+        object = object.entry(*key)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .ok_or_else(|| error(depth + 1))?;
+    }
+    Ok(object)
+}
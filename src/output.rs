@@ -0,0 +1,145 @@
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use serde::Serialize;
+use crate::errors::InvalidOptionError;
+
+/// How the commands print their results, set by the global `--format` option:
+/// aligned columns with a header, JSON, or tab-separated values without a header.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Table,
+    Json,
+    Plain,
+}
+
+impl FromStr for Format {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "plain" => Ok(Format::Plain),
+            _ => Err(InvalidOptionError { option: "--format".to_owned(), value: s.to_owned() }),
+        }
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Table => write!(f, "table"),
+            Format::Json => write!(f, "json"),
+            Format::Plain => write!(f, "plain"),
+        }
+    }
+}
+
+static FORMAT: AtomicU8 = AtomicU8::new(Format::Table as u8);
+
+/// Sets the format of everything printed afterwards.
+pub fn set_format(format: Format) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+pub fn format() -> Format {
+    match FORMAT.load(Ordering::Relaxed) {
+        value if value == Format::Json as u8 => Format::Json,
+        value if value == Format::Plain as u8 => Format::Plain,
+        _ => Format::Table,
+    }
+}
+
+/// Prints a message about the progress of a command. With `--format json` it goes
+/// to stderr, so that stdout holds nothing but the JSON of the result.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        match $crate::output::format() {
+            $crate::output::Format::Json => eprintln!($($arg)*),
+            _ => println!($($arg)*),
+        }
+    };
+}
+
+/// Prints a list of items: as a table with the headers, as a JSON array
+/// of the items themselves, or as a tab-separated line per item.
+pub fn print_list<T: Serialize>(
+    headers: &[&str],
+    items: &[T],
+    row: impl Fn(&T) -> Vec<String>,
+) -> serde_json::Result<()> {
+    match format() {
+        Format::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        Format::Plain => {
+            for item in items {
+                println!("{}", row(item).join("\t"));
+            }
+        }
+        Format::Table => {
+            for line in table(headers, &items.iter().map(row).collect::<Vec<_>>()) {
+                println!("{}", line);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Aligns rows into the lines of a table with the headers.
+pub(crate) fn table(headers: &[&str], rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths = headers.iter().map(|header| header.len()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+
+    let headers = headers.iter().map(|header| header.to_string()).collect::<Vec<_>>();
+    std::iter::once(&headers).chain(rows)
+        .map(|row| format_row(row, &widths))
+        .collect()
+}
+
+/// Prints a single item: as `Key: value` lines, skipping the fields without
+/// a value, or as the JSON of the item itself.
+pub fn print_record<T: Serialize>(item: &T, fields: &[(&str, Option<String>)]) -> serde_json::Result<()> {
+    match format() {
+        Format::Json => println!("{}", serde_json::to_string_pretty(item)?),
+        Format::Table | Format::Plain => {
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    println!("{}: {}", key, value);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints a text such as the output of a container: as is, or as a JSON object
+/// with the text under the key.
+pub fn print_text(key: &str, text: &str) -> serde_json::Result<()> {
+    match format() {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&serde_json::json!({ key: text }))?),
+        Format::Table | Format::Plain => println!("{}", text),
+    }
+
+    Ok(())
+}
+
+fn format_row(values: &[String], widths: &[usize]) -> String {
+    let mut line = String::new();
+    for (index, value) in values.iter().enumerate() {
+        if index + 1 == values.len() {
+            line.push_str(value);
+        } else {
+            line.push_str(&format!("{:<width$} ", value, width = widths[index]));
+        }
+    }
+    line
+}
@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// External subcommands are executables named `joker-<name>` on the `PATH`.
+pub const PLUGIN_PREFIX: &str = "joker-";
+
+/// Finds the executable of a plugin, the first one on the `PATH`.
+pub fn find(name: &str) -> Option<PathBuf> {
+    if name.is_empty() || name.contains(std::path::is_separator) {
+        return None;
+    }
+    let paths = env::var_os("PATH")?;
+
+    env::split_paths(&paths)
+        .map(|directory| directory.join(format!("{}{}", PLUGIN_PREFIX, name)))
+        .find(|path| is_executable(path))
+}
+
+/// Lists the plugins on the `PATH` by their names. A plugin shadowed
+/// by one with the same name earlier on the `PATH` is skipped.
+pub fn list() -> BTreeMap<String, PathBuf> {
+    let mut plugins = BTreeMap::new();
+    let Some(paths) = env::var_os("PATH") else {
+        return plugins;
+    };
+
+    for directory in env::split_paths(&paths) {
+        let Ok(entries) = std::fs::read_dir(&directory) else { continue };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let Some(name) = file_name.strip_prefix(PLUGIN_PREFIX) else { continue };
+            if !name.is_empty() && !plugins.contains_key(name) && is_executable(&entry.path()) {
+                plugins.insert(name.to_owned(), entry.path());
+            }
+        }
+    }
+
+    plugins
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
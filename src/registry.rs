@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Display;
+use std::io::Read;
+use std::str::FromStr;
+use base64::Engine;
+use crate::errors::InvalidOptionError;
+
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+pub const DEFAULT_TAG: &str = "latest";
+
+/// The host serving the API of the default registry.
+const DOCKER_HUB_API: &str = "registry-1.docker.io";
+
+/// Environment variables with the credentials of a registry.
+pub const USERNAME_VAR: &str = "JOKER_REGISTRY_USERNAME";
+pub const PASSWORD_VAR: &str = "JOKER_REGISTRY_PASSWORD";
+
+/// A reference to an image of a registry, such as `alpine`,
+/// `ghcr.io/owner/app:1.2` or `alpine@sha256:...`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: String,
+    pub digest: Option<String>,
+}
+
+impl FromStr for ImageReference {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "image reference".to_owned(), value: s.to_owned() };
+
+        let (name, digest) = match s.trim().split_once('@') {
+            Some((name, digest)) if digest.starts_with("sha256:") => (name, Some(digest.to_owned())),
+            Some(_) => return Err(error()),
+            None => (s.trim(), None),
+        };
+
+        // A tag is separated by the last colon, unless it belongs to the port of a registry
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((rest, tag)) if !tag.contains('/') => (rest, tag.to_owned()),
+            _ => (name, DEFAULT_TAG.to_owned()),
+        };
+
+        // The first component is a registry if it looks like a host
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_owned(), rest.to_owned())
+            }
+            Some(_) => (DEFAULT_REGISTRY.to_owned(), name.to_owned()),
+            None => (DEFAULT_REGISTRY.to_owned(), format!("library/{}", name)),
+        };
+
+        let valid_repository = !repository.is_empty() && repository.split('/').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+        });
+        let valid_tag = !tag.is_empty() && tag.len() <= 128
+            && tag.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+        if !valid_repository || !valid_tag {
+            return Err(error());
+        }
+
+        Ok(ImageReference { registry, repository, tag, digest })
+    }
+}
+
+impl Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}:{}", self.registry, self.repository, self.tag)?;
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+impl ImageReference {
+    /// The name of the image in the local store, without a digest.
+    pub fn tag_name(&self) -> String {
+        format!("{}/{}:{}", self.registry, self.repository, self.tag)
+    }
+
+    /// The name the image is recorded by in the local store: its tag,
+    /// or its digest when it was requested by one.
+    pub fn store_name(&self) -> String {
+        match &self.digest {
+            Some(digest) => format!("{}/{}@{}", self.registry, self.repository, digest),
+            None => self.tag_name(),
+        }
+    }
+
+    /// The tag or the digest a manifest is requested by.
+    pub fn manifest_reference(&self) -> &str {
+        self.digest.as_deref().unwrap_or(&self.tag)
+    }
+}
+
+/// A manifest received from a registry.
+pub struct RegistryManifest {
+    pub media_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A client of the OCI distribution API of a single repository.
+/// Handles the bearer token authentication used by Docker Hub, GHCR
+/// and most other registries, as well as the basic one.
+pub struct RegistryClient {
+    agent: ureq::Agent,
+    origin: String,
+    base_url: String,
+    repository: String,
+    actions: &'static str,
+    authorization: Option<String>,
+}
+
+impl RegistryClient {
+    /// Creates a client for the repository of a reference. The actions
+    /// (`pull` or `pull,push`) are requested as the scope of a token.
+    pub fn new(reference: &ImageReference, actions: &'static str) -> Self {
+        let host = match reference.registry.as_str() {
+            DEFAULT_REGISTRY => DOCKER_HUB_API,
+            host => host,
+        };
+        // Local registries are usually served without TLS
+        let scheme = match host.starts_with("localhost") || host.starts_with("127.0.0.1") {
+            true => "http",
+            false => "https",
+        };
+
+        RegistryClient {
+            agent: ureq::AgentBuilder::new().redirects(5).build(),
+            origin: format!("{}://{}", scheme, host),
+            base_url: format!("{}://{}/v2/{}", scheme, host, reference.repository),
+            repository: reference.repository.clone(),
+            actions,
+            authorization: None,
+        }
+    }
+
+    /// Sends a request, authenticating and retrying once if the registry asks for it.
+    fn send(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Option<&[u8]>,
+    ) -> Result<ureq::Response, Box<dyn std::error::Error>> {
+        // Upload locations are given by the registry, either absolute or relative to its origin
+        let url = if url.starts_with("http") {
+            url.to_owned()
+        } else if url.starts_with("/v2/") {
+            format!("{}{}", self.origin, url)
+        } else {
+            format!("{}{}", self.base_url, url)
+        };
+
+        for attempt in 0..2 {
+            let mut request = self.agent.request(method, &url);
+            for (name, value) in headers {
+                request = request.set(name, value);
+            }
+            if let Some(authorization) = &self.authorization {
+                request = request.set("Authorization", authorization);
+            }
+
+            let response = match body {
+                Some(body) => request.send_bytes(body),
+                None => request.call(),
+            };
+
+            match response {
+                Ok(response) => return Ok(response),
+                // A missing blob is an answer rather than an error
+                Err(ureq::Error::Status(404, response)) if method == "HEAD" => return Ok(response),
+                Err(ureq::Error::Status(401, response)) if attempt == 0 => {
+                    let challenge = response.header("WWW-Authenticate").unwrap_or_default().to_owned();
+                    self.authenticate(&challenge)?;
+                }
+                Err(ureq::Error::Status(status, response)) => {
+                    let message = response.into_string().unwrap_or_default();
+                    return Err(format!("Registry responded with {} to {} {}: {}", status, method, url, message.trim()).into());
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Err(format!("Registry rejected the credentials for {}.", self.repository).into())
+    }
+
+    /// Answers an authentication challenge of a registry.
+    fn authenticate(&mut self, challenge: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let credentials = match (std::env::var(USERNAME_VAR), std::env::var(PASSWORD_VAR)) {
+            (Ok(username), Ok(password)) => Some(base64::engine::general_purpose::STANDARD
+                .encode(format!("{}:{}", username, password))),
+            _ => None,
+        };
+
+        let (scheme, parameters) = challenge.split_once(' ').unwrap_or((challenge, ""));
+        if scheme.eq_ignore_ascii_case("basic") {
+            let credentials = credentials.ok_or_else(|| {
+                format!("Registry requires credentials: set {} and {}.", USERNAME_VAR, PASSWORD_VAR)
+            })?;
+            self.authorization = Some(format!("Basic {}", credentials));
+            return Ok(());
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(format!("Unsupported registry authentication: {}", challenge).into());
+        }
+
+        let parameters = parse_challenge(parameters);
+        let realm = parameters.get("realm").ok_or("Registry didn't provide a token realm.")?;
+
+        let mut request = self.agent.get(realm)
+            .query("scope", &format!("repository:{}:{}", self.repository, self.actions));
+        if let Some(service) = parameters.get("service") {
+            request = request.query("service", service);
+        }
+        if let Some(credentials) = &credentials {
+            request = request.set("Authorization", &format!("Basic {}", credentials));
+        }
+
+        let response: serde_json::Value = serde_json::from_reader(request.call()?.into_reader())?;
+        let token = response["token"].as_str()
+            .or_else(|| response["access_token"].as_str())
+            .ok_or("Registry didn't provide a token.")?;
+        self.authorization = Some(format!("Bearer {}", token));
+
+        Ok(())
+    }
+
+    /// Fetches a manifest by a tag or a digest.
+    pub fn get_manifest(&mut self, reference: &str) -> Result<RegistryManifest, Box<dyn std::error::Error>> {
+        let accept = crate::image::MANIFEST_TYPES.join(", ");
+        let response = self.send("GET", &format!("/manifests/{}", reference), &[("Accept", &accept)], None)?;
+
+        let media_type = response.content_type().to_owned();
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+
+        Ok(RegistryManifest { media_type, data })
+    }
+
+    /// Fetches a blob, returning a stream of its contents.
+    pub fn get_blob(&mut self, digest: &str) -> Result<Box<dyn Read + Send + Sync>, Box<dyn std::error::Error>> {
+        let response = self.send("GET", &format!("/blobs/{}", digest), &[], None)?;
+        Ok(response.into_reader())
+    }
+
+    /// Checks whether the repository already has a blob.
+    pub fn has_blob(&mut self, digest: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let response = self.send("HEAD", &format!("/blobs/{}", digest), &[], None)?;
+        Ok(response.status() == 200)
+    }
+
+    /// Uploads a blob in a single request.
+    pub fn put_blob(&mut self, digest: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self.send("POST", "/blobs/uploads/", &[("Content-Length", "0")], Some(&[]))?;
+        let location = response.header("Location")
+            .ok_or("Registry didn't provide an upload location.")?
+            .to_owned();
+
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let url = format!("{}{}digest={}", location, separator, digest);
+        self.send("PUT", &url, &[("Content-Type", "application/octet-stream")], Some(data))?;
+
+        Ok(())
+    }
+
+    /// Uploads a manifest under a tag.
+    pub fn put_manifest(&mut self, tag: &str, media_type: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.send("PUT", &format!("/manifests/{}", tag), &[("Content-Type", media_type)], Some(data))?;
+        Ok(())
+    }
+}
+
+/// Parses the `key="value"` pairs of an authentication challenge.
+fn parse_challenge(parameters: &str) -> HashMap<String, String> {
+    let mut result = HashMap::new();
+    let mut rest = parameters.trim();
+
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        result.insert(key, value.to_owned());
+        rest = after.trim_start_matches(',').trim();
+    }
+
+    result
+}
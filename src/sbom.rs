@@ -0,0 +1,49 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde_json::Value;
+
+pub const SPDX_JSON: &str = "application/spdx+json";
+pub const CYCLONEDX_JSON: &str = "application/vnd.cyclonedx+json";
+
+/// The media types of the SBOM documents the daemon stores.
+pub const SBOM_TYPES: &[&str] = &[SPDX_JSON, CYCLONEDX_JSON];
+
+/// A software bill of materials of a container, in the SPDX or the CycloneDX JSON format.
+#[derive(Clone, Debug)]
+pub struct Sbom {
+    pub media_type: String,
+    pub data: String,
+}
+
+impl Sbom {
+    /// Reads an SBOM document and detects its format.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Sbom::parse(fs::read_to_string(path)?).map_err(|message| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+        })
+    }
+
+    pub fn parse(data: String) -> Result<Self, String> {
+        let document: Value = serde_json::from_str(&data).map_err(|err| format!("bad SBOM: {}", err))?;
+
+        let media_type = if document["spdxVersion"].is_string() {
+            SPDX_JSON
+        } else if document["bomFormat"] == "CycloneDX" {
+            CYCLONEDX_JSON
+        } else {
+            return Err("neither an SPDX nor a CycloneDX JSON document".to_owned());
+        };
+
+        Ok(Sbom { media_type: media_type.to_owned(), data })
+    }
+
+    /// Finds the SBOM kept next to a container, e.g. `app.sbom.json` for `app`.
+    pub fn find(container_path: &str) -> io::Result<Option<Self>> {
+        let path = format!("{}.sbom.json", container_path);
+        match Path::new(&path).is_file() {
+            true => Sbom::read(Path::new(&path)).map(Some),
+            false => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use crate::daemon::{Daemon, DaemonStatus};
+use crate::errors::InvalidOptionError;
+
+/// A label a daemon must have to be scheduled on, written as `KEY=VALUE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Constraint {
+    pub key: String,
+    pub value: String,
+}
+
+impl Constraint {
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        labels.get(&self.key) == Some(&self.value)
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = InvalidOptionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || InvalidOptionError { option: "--constraint".to_owned(), value: s.to_owned() };
+
+        match s.trim().split_once('=') {
+            Some((key, value)) if !key.is_empty() => Ok(Constraint { key: key.to_owned(), value: value.to_owned() }),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// The CPUs of a daemon which aren't busy, by its load average.
+pub fn idle_cpus(status: &DaemonStatus) -> f64 {
+    (status.cpus as f64 - status.load_average).max(0.0)
+}
+
+/// Compares the free resources of two daemons: the one with more available
+/// memory wins, and more idle CPUs break a tie.
+fn compare(a: &DaemonStatus, b: &DaemonStatus) -> Ordering {
+    a.memory_available.cmp(&b.memory_available)
+        .then_with(|| idle_cpus(a).total_cmp(&idle_cpus(b)))
+}
+
+/// Picks the least loaded of the daemons which answered. On a tie the first one wins.
+pub fn pick(candidates: Vec<(Daemon, DaemonStatus)>) -> Option<(Daemon, DaemonStatus)> {
+    candidates.into_iter().reduce(|best, candidate| match compare(&candidate.1, &best.1) {
+        Ordering::Greater => candidate,
+        _ => best,
+    })
+}
+
+/// The name of a replica of a container: the name of the container and the index of the replica,
+/// counted from 1.
+pub fn replica_name(name: &str, index: u32) -> String {
+    format!("{}-{}", name, index)
+}
+
+/// The index of a replica of a container by its name, if the name is one of a replica.
+pub fn replica_index(name: &str, replica_name: &str) -> Option<u32> {
+    let index = replica_name.strip_prefix(name)?.strip_prefix('-')?.parse().ok()?;
+
+    // `app-01` isn't a replica, `app-1` is
+    (index > 0 && self::replica_name(name, index) == replica_name).then_some(index)
+}
+
+/// Picks the daemon for a new replica: the one with the fewest replicas. On a tie the first one wins.
+pub fn spread(replicas: &[usize]) -> Option<usize> {
+    replicas.iter().enumerate()
+        .min_by_key(|&(_, &count)| count)
+        .map(|(daemon, _)| daemon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_replica_names() {
+        assert_eq!(replica_index("app", "app-1"), Some(1));
+        assert_eq!(replica_index("app", "app-12"), Some(12));
+        assert_eq!(replica_index("app-1", "app-1-2"), Some(2));
+        for name in ["app-01", "app-0", "app", "app-", "app-x", "app--1", "app-+1", "app-1-2", "other-1"] {
+            assert_eq!(replica_index("app", name), None, "{}", name);
+        }
+        assert_eq!(replica_index("app", &replica_name("app", 7)), Some(7));
+    }
+
+    #[test]
+    fn spreads_replicas_over_the_daemons_with_the_fewest() {
+        assert_eq!(spread(&[]), None);
+        assert_eq!(spread(&[2, 0, 1]), Some(1));
+        assert_eq!(spread(&[1, 1, 1]), Some(0));
+
+        // Placing them one by one evens the daemons out
+        let mut replicas = vec![2, 0, 1];
+        for _ in 0..4 {
+            let daemon = spread(&replicas).unwrap();
+            replicas[daemon] += 1;
+        }
+        assert_eq!(replicas, [3, 2, 2]);
+    }
+
+    #[test]
+    fn compares_available_memory_then_idle_cpus() {
+        let status = |memory_available, cpus, load_average| DaemonStatus { memory_available, cpus, load_average, ..Default::default() };
+        assert_eq!(compare(&status(2, 1, 0.0), &status(1, 8, 0.0)), Ordering::Greater);
+        assert_eq!(compare(&status(1, 4, 3.5), &status(1, 4, 1.0)), Ordering::Less);
+        assert_eq!(compare(&status(1, 2, 4.0), &status(1, 4, 8.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn parses_constraints() {
+        assert_eq!("zone=eu".parse::<Constraint>().ok(), Some(Constraint { key: "zone".to_owned(), value: "eu".to_owned() }));
+        assert_eq!("zone=".parse::<Constraint>().ok(), Some(Constraint { key: "zone".to_owned(), value: String::new() }));
+        assert!("=eu".parse::<Constraint>().is_err());
+        assert!("zone".parse::<Constraint>().is_err());
+    }
+}
@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use serde::Deserialize;
+use crate::container::{
+    self, Asset, ContainerConfig, Device, EnvVar, HookFailure, IdMapping, Label, Mount, NetworkMode, PortMapping, RestartPolicy, Runtime,
+    SecretMount,
+};
+use crate::errors::{ConfigParseError, InvalidOptionError};
+use crate::interpolation;
+
+/// The version of the `.joker` schema this CLI reads.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A `.joker` config written as TOML. It's turned into the `Key: value`
+/// entries of a `ContainerConfig`, which is what the daemon receives.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct JokerConfig {
+    pub version: Option<u32>,
+    pub name: Option<String>,
+    /// The arguments the binary is executed with.
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub labels: BTreeMap<String, String>,
+    pub limits: Limits,
+    pub mounts: Vec<String>,
+    /// Local files shipped with the binary, as SOURCE:CONTAINER_PATH.
+    pub assets: Vec<String>,
+    pub secrets: Vec<String>,
+    pub read_only: Option<bool>,
+    pub publish: Vec<String>,
+    pub devices: Vec<String>,
+    pub network: Option<String>,
+    pub network_aliases: Vec<String>,
+    pub capabilities: Option<Vec<String>>,
+    pub cap_add: Vec<String>,
+    pub cap_drop: Vec<String>,
+    pub no_new_privileges: Option<bool>,
+    pub uid_mappings: Vec<String>,
+    pub gid_mappings: Vec<String>,
+    pub restart: Restart,
+    /// A duration like `30m` after which the daemon kills the container.
+    pub timeout: Option<String>,
+    pub healthcheck: Healthcheck,
+    pub runtime: Option<String>,
+    pub hooks: Hooks,
+    pub namespaces: Namespaces,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// A size like `512M`.
+    pub memory: Option<String>,
+    pub cpus: Option<f64>,
+    pub pids: Option<u64>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Restart {
+    pub policy: Option<String>,
+    pub max_retries: Option<u32>,
+    pub backoff: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Healthcheck {
+    pub command: Option<String>,
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub retries: Option<u32>,
+    pub restart_on_unhealthy: Option<bool>,
+}
+
+/// Commands run around the lifecycle of a container. `pre_run` and `post_run`
+/// are run on the local machine, the `daemon_` ones and `on_exit` on the daemon host.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hooks {
+    pub pre_run: Option<String>,
+    pub post_run: Option<String>,
+    pub daemon_pre_run: Option<String>,
+    pub daemon_post_run: Option<String>,
+    pub on_exit: Option<String>,
+    /// `fatal` or `warn`.
+    pub failure: Option<String>,
+}
+
+/// The names of the namespaces shared with other containers of a daemon.
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Namespaces {
+    pub ipc: Option<String>,
+    pub user: Option<String>,
+    pub mount: Option<String>,
+    pub pid: Option<String>,
+    pub network: Option<String>,
+    pub time: Option<String>,
+    pub uts: Option<String>,
+    pub cgroup: Option<String>,
+}
+
+/// Whether a `.joker` file is written as TOML rather than as `Key: value` lines:
+/// a TOML config starts with its `version`.
+pub fn is_toml(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .and_then(|line| line.split_once('='))
+        .is_some_and(|(key, _)| key.trim() == "version")
+}
+
+/// Parses a `.joker` config in either of its formats, expanding the variables
+/// of its values with the values of the local environment.
+pub fn parse_config(text: &str) -> Result<ContainerConfig, ConfigParseError> {
+    match is_toml(text) {
+        true => JokerConfig::parse(text)?.to_config(text),
+        false => ContainerConfig::parse(&interpolate_entries(text)?),
+    }
+}
+
+/// Expands the variables of the values of a config of `Key: value` lines.
+/// A value can't be expanded into several lines, which would be entries of their own.
+fn interpolate_entries(text: &str) -> Result<String, ConfigParseError> {
+    let mut result = String::with_capacity(text.len());
+    for (index, line) in text.lines().enumerate() {
+        match line.split_once(':') {
+            Some((key, value)) => {
+                let value = interpolation::interpolate(value, index + 1)
+                    .map_err(|err| ConfigParseError { line: err.line, message: err.message })?;
+                if value.contains(['\n', '\r']) {
+                    return Err(ConfigParseError { line: index + 1, message: format!("{} can't span several lines", key.trim()) });
+                }
+                result.push_str(key);
+                result.push(':');
+                result.push_str(&value);
+            }
+            None => result.push_str(line),
+        }
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+/// A starter `.joker` config for `joker init`, with the commonly tuned keys
+/// commented out at their defaults. It parses as is.
+pub fn starter_config(name: &str) -> String {
+    format!(r#"version = {version}
+name = "{name}"
+# args = ["--port", "${{PORT:-8080}}"]
+# runtime = "native"
+# Named volumes or host directories, as SOURCE:CONTAINER_PATH[:ro|rw]
+# mounts = ["{name}-data:/data"]
+# Local files shipped with the binary, as SOURCE:CONTAINER_PATH
+# assets = ["./static:/srv/static"]
+# publish = ["8080:8080"]
+# network = "bridge"
+
+[env]
+# RUST_LOG = "info"
+
+[limits]
+# memory = "512M"
+# cpus = 1.0
+# pids = 128
+
+[restart]
+# policy = "on-failure"
+# max_retries = 3
+# backoff = "1s"
+
+[healthcheck]
+# command = "/{name} --check"
+# interval = "30s"
+# timeout = "30s"
+# retries = 3
+"#, version = SCHEMA_VERSION, name = name)
+}
+
+impl JokerConfig {
+    /// Parses a TOML config and expands the variables of its values.
+    pub fn parse(text: &str) -> Result<Self, ConfigParseError> {
+        let syntax_error = |err: toml::de::Error| ConfigParseError {
+            line: err.span().map(|span| line_at(text, span.start)).unwrap_or(1),
+            message: err.message().trim_end_matches(['.', '\n']).to_owned(),
+        };
+        let config: JokerConfig = toml::from_str(text).map_err(syntax_error)?;
+
+        match config.version {
+            Some(SCHEMA_VERSION) => {}
+            Some(version) => return Err(ConfigParseError {
+                line: line_of(text, "version"),
+                message: format!("unsupported schema version {}, expected {}", version, SCHEMA_VERSION),
+            }),
+            None => return Err(ConfigParseError { line: 1, message: "missing schema version".to_owned() }),
+        }
+
+        let mut values = toml::Value::Table(toml::from_str(text).map_err(syntax_error)?);
+        interpolation::interpolate_toml(&mut values, &|path| line_of(text, path))
+            .map_err(|err| ConfigParseError { line: err.line, message: err.message })?;
+
+        // A line break in any value would start an entry of its own in the config sent to the daemon
+        if let Some(path) = find_line_break(&values, "") {
+            return Err(ConfigParseError { line: line_of(text, &path), message: format!("{} can't span several lines", path) });
+        }
+
+        values.try_into().map_err(syntax_error)
+    }
+
+    /// Checks every value and turns the config into the entries the daemon reads.
+    /// The text of the config is needed only to point at the line of a bad value.
+    pub fn to_config(&self, text: &str) -> Result<ContainerConfig, ConfigParseError> {
+        let mut config = ContainerConfig::default();
+        let error = |path: &str, err: InvalidOptionError| ConfigParseError {
+            line: line_of(text, path),
+            message: err.to_string().trim_end_matches('.').to_owned(),
+        };
+
+        if let Some(name) = &self.name {
+            if !container::is_valid_name(name) {
+                let err = InvalidOptionError { option: "name".to_owned(), value: name.clone() };
+                return Err(error("name", err));
+            }
+            config.set(container::CONTAINER_NAME_KEY, name.clone());
+        }
+
+        for (key, path, value) in [
+            (container::IPC_NAMESPACE_KEY, "namespaces.ipc", &self.namespaces.ipc),
+            (container::USER_NAMESPACE_KEY, "namespaces.user", &self.namespaces.user),
+            (container::MOUNT_NAMESPACE_KEY, "namespaces.mount", &self.namespaces.mount),
+            (container::PID_NAMESPACE_KEY, "namespaces.pid", &self.namespaces.pid),
+            (container::NETWORK_NAMESPACE_KEY, "namespaces.network", &self.namespaces.network),
+            (container::TIME_NAMESPACE_KEY, "namespaces.time", &self.namespaces.time),
+            (container::UTS_NAMESPACE_KEY, "namespaces.uts", &self.namespaces.uts),
+            (container::CGROUP_KEY, "namespaces.cgroup", &self.namespaces.cgroup),
+        ] {
+            if let Some(value) = value {
+                if !container::is_valid_name(value) {
+                    let err = InvalidOptionError { option: path.to_owned(), value: value.clone() };
+                    return Err(error(path, err));
+                }
+                config.set(key, value.clone());
+            }
+        }
+
+        if !self.args.is_empty() {
+            config.set(container::ARGS_KEY, serde_json::to_string(&self.args).unwrap_or_default());
+        }
+
+        for (key, value) in &self.env {
+            let var = format!("{}={}", key, value).parse::<EnvVar>()
+                .map_err(|err| error(&format!("env.{}", key), err))?;
+            config.push(container::ENV_KEY, var.to_string());
+        }
+
+        for (key, value) in &self.labels {
+            let label = format!("{}={}", key, value).parse::<Label>()
+                .map_err(|err| error(&format!("labels.{}", key), err))?;
+            config.push(container::LABEL_KEY, label.to_string());
+        }
+
+        if let Some(memory) = &self.limits.memory {
+            let bytes = container::parse_size("limits.memory", memory).map_err(|err| error("limits.memory", err))?;
+            config.set(container::MEMORY_LIMIT_KEY, bytes.to_string());
+        }
+        if let Some(cpus) = self.limits.cpus {
+            if !cpus.is_finite() || cpus <= 0.0 {
+                let err = InvalidOptionError { option: "limits.cpus".to_owned(), value: cpus.to_string() };
+                return Err(error("limits.cpus", err));
+            }
+            config.set(container::CPU_LIMIT_KEY, cpus.to_string());
+        }
+        if let Some(pids) = self.limits.pids {
+            if pids == 0 {
+                let err = InvalidOptionError { option: "limits.pids".to_owned(), value: pids.to_string() };
+                return Err(error("limits.pids", err));
+            }
+            config.set(container::PIDS_LIMIT_KEY, pids.to_string());
+        }
+
+        push_all::<Mount>(&mut config, container::MOUNT_KEY, &self.mounts).map_err(|err| error("mounts", err))?;
+        push_all::<Asset>(&mut config, container::ASSET_KEY, &self.assets).map_err(|err| error("assets", err))?;
+        push_all::<SecretMount>(&mut config, container::SECRET_KEY, &self.secrets).map_err(|err| error("secrets", err))?;
+        push_all::<PortMapping>(&mut config, container::PUBLISH_KEY, &self.publish).map_err(|err| error("publish", err))?;
+        push_all::<Device>(&mut config, container::DEVICE_KEY, &self.devices).map_err(|err| error("devices", err))?;
+
+        if let Some(network) = &self.network {
+            let network = network.parse::<NetworkMode>().map_err(|err| error("network", err))?;
+            config.set(container::NETWORK_KEY, network.to_string());
+        }
+        if let Some(network_aliases) = join_list("network_aliases", &self.network_aliases, container::is_valid_name)
+            .map_err(|err| error("network_aliases", err))? {
+            config.set(container::NETWORK_ALIASES_KEY, network_aliases);
+        }
+
+        let is_capability = |capability: &str| capability == "ALL" || container::KNOWN_CAPABILITIES.contains(&capability);
+        for (key, path, capabilities) in [
+            (container::CAPABILITIES_KEY, "capabilities", self.capabilities.as_deref()),
+            (container::CAPABILITIES_ADD_KEY, "cap_add", Some(self.cap_add.as_slice())),
+            (container::CAPABILITIES_DROP_KEY, "cap_drop", Some(self.cap_drop.as_slice())),
+        ] {
+            let Some(capabilities) = capabilities else { continue };
+            let capabilities = capabilities.iter().map(|capability| capability.to_uppercase()).collect::<Vec<_>>();
+            match join_list(path, &capabilities, is_capability).map_err(|err| error(path, err))? {
+                Some(capabilities) => config.set(key, capabilities),
+                // An empty list of capabilities drops all of them, unlike an omitted one
+                None if key == container::CAPABILITIES_KEY => config.set(key, String::new()),
+                None => {}
+            }
+        }
+        if let Some(no_new_privileges) = self.no_new_privileges {
+            config.set(container::NO_NEW_PRIVILEGES_KEY, no_new_privileges.to_string());
+        }
+
+        for (key, path, mappings) in [
+            (container::UID_MAPPINGS_KEY, "uid_mappings", &self.uid_mappings),
+            (container::GID_MAPPINGS_KEY, "gid_mappings", &self.gid_mappings),
+        ] {
+            let mappings = mappings.iter()
+                .map(|mapping| mapping.parse::<IdMapping>().map(|mapping| mapping.to_string()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| error(path, err))?;
+            if !mappings.is_empty() {
+                config.set(key, mappings.join(", "));
+            }
+        }
+
+        if let Some(read_only) = self.read_only {
+            config.set(container::READ_ONLY_ROOTFS_KEY, read_only.to_string());
+        }
+
+        if let Some(policy) = &self.restart.policy {
+            policy.parse::<RestartPolicy>().map_err(|err| error("restart.policy", err))?;
+            config.set(container::RESTART_POLICY_KEY, policy.clone());
+        }
+        if let Some(max_retries) = self.restart.max_retries {
+            config.set(container::RESTART_MAX_RETRIES_KEY, max_retries.to_string());
+        }
+        if let Some(backoff) = &self.restart.backoff {
+            let backoff = container::parse_duration("restart.backoff", backoff).map_err(|err| error("restart.backoff", err))?;
+            config.set(container::RESTART_BACKOFF_KEY, container::format_duration(backoff));
+        }
+
+        if let Some(timeout) = &self.timeout {
+            let timeout = container::parse_duration("timeout", timeout).map_err(|err| error("timeout", err))?;
+            config.set(container::TIMEOUT_KEY, container::format_duration(timeout));
+        }
+
+        if let Some(command) = &self.healthcheck.command {
+            config.set(container::HEALTHCHECK_COMMAND_KEY, command.clone());
+        }
+        for (key, path, value) in [
+            (container::HEALTHCHECK_INTERVAL_KEY, "healthcheck.interval", &self.healthcheck.interval),
+            (container::HEALTHCHECK_TIMEOUT_KEY, "healthcheck.timeout", &self.healthcheck.timeout),
+        ] {
+            if let Some(value) = value {
+                let duration = container::parse_duration(path, value).map_err(|err| error(path, err))?;
+                config.set(key, container::format_duration(duration));
+            }
+        }
+        if let Some(retries) = self.healthcheck.retries {
+            config.set(container::HEALTHCHECK_RETRIES_KEY, retries.to_string());
+        }
+        if let Some(restart) = self.healthcheck.restart_on_unhealthy {
+            config.set(container::RESTART_ON_UNHEALTHY_KEY, restart.to_string());
+        }
+
+        for (key, value) in [
+            (container::PRE_RUN_HOOK_KEY, &self.hooks.pre_run),
+            (container::POST_RUN_HOOK_KEY, &self.hooks.post_run),
+            (container::DAEMON_PRE_RUN_HOOK_KEY, &self.hooks.daemon_pre_run),
+            (container::DAEMON_POST_RUN_HOOK_KEY, &self.hooks.daemon_post_run),
+            (container::ON_EXIT_HOOK_KEY, &self.hooks.on_exit),
+        ] {
+            if let Some(command) = value {
+                config.set(key, command.clone());
+            }
+        }
+        if let Some(failure) = &self.hooks.failure {
+            let failure = failure.parse::<HookFailure>().map_err(|err| error("hooks.failure", err))?;
+            config.set(container::HOOK_FAILURE_KEY, failure.to_string());
+        }
+
+        if let Some(runtime) = &self.runtime {
+            let runtime = runtime.parse::<Runtime>().map_err(|err| error("runtime", err))?;
+            config.set(container::RUNTIME_KEY, runtime.to_string());
+        }
+
+        Ok(config)
+    }
+}
+
+/// Joins the items of a list into a single entry, none if the list is empty.
+fn join_list(path: &str, items: &[String], valid: impl Fn(&str) -> bool) -> Result<Option<String>, InvalidOptionError> {
+    if let Some(item) = items.iter().find(|item| !valid(item)) {
+        return Err(InvalidOptionError { option: path.to_owned(), value: item.clone() });
+    }
+    Ok(Some(items.join(", ")).filter(|items| !items.is_empty()))
+}
+
+fn push_all<T: FromStr<Err = InvalidOptionError> + ToString>(
+    config: &mut ContainerConfig,
+    key: &str,
+    values: &[String],
+) -> Result<(), InvalidOptionError> {
+    for value in values {
+        config.push(key, value.parse::<T>()?.to_string());
+    }
+    Ok(())
+}
+
+/// Finds the dotted path of a string value with a line break, if any.
+pub(crate) fn find_line_break(value: &toml::Value, path: &str) -> Option<String> {
+    match value {
+        toml::Value::String(string) if string.contains(['\n', '\r']) => Some(path.to_owned()),
+        toml::Value::Array(items) => items.iter().find_map(|item| find_line_break(item, path)),
+        toml::Value::Table(table) => table.iter().find_map(|(key, value)| match path.is_empty() {
+            true => find_line_break(value, key),
+            false => find_line_break(value, &format!("{}.{}", path, key)),
+        }),
+        _ => None,
+    }
+}
+
+fn line_at(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+/// Finds the line a dotted key like `limits.memory` is written at, either under
+/// its `[limits]` table or as an inline table. Falls back to the first line.
+pub(crate) fn line_of(text: &str, path: &str) -> usize {
+    let (table, key) = path.rsplit_once('.').unwrap_or(("", path));
+    let mut current = String::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            current = line.trim_matches(['[', ']']).trim().to_owned();
+            continue;
+        }
+        let Some((name, _)) = line.split_once('=') else { continue };
+        let name = name.trim().trim_matches('"');
+
+        let found = (current == table && name == key)
+            || (current.is_empty() && (name == path || (!table.is_empty() && name == table)));
+        if found {
+            return index + 1;
+        }
+    }
+
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> ConfigParseError {
+        parse_config(text).err().expect("the config should be rejected")
+    }
+
+    #[test]
+    fn tells_toml_from_key_value_lines() {
+        assert!(is_toml("# web\n\nversion = 1\nname = \"web\"\n"));
+        assert!(!is_toml("Container name: web\n"));
+        assert!(!is_toml("name = \"web\"\nversion = 1\n"));
+    }
+
+    #[test]
+    fn reads_a_v1_config_into_entries() {
+        let config = parse_config(r#"version = 1
+name = "web"
+args = ["--port", "8080"]
+publish = ["8080:80"]
+
+[env]
+RUST_LOG = "info"
+
+[limits]
+memory = "512M"
+
+[restart]
+policy = "on-failure"
+backoff = "1s"
+"#).unwrap();
+
+        assert_eq!(config.get(container::CONTAINER_NAME_KEY), Some("web"));
+        assert_eq!(config.get(container::ARGS_KEY), Some(r#"["--port","8080"]"#));
+        assert_eq!(config.get(container::ENV_KEY), Some("RUST_LOG=info"));
+        assert_eq!(config.get(container::MEMORY_LIMIT_KEY), Some("536870912"));
+        assert_eq!(config.get(container::RESTART_POLICY_KEY), Some("on-failure"));
+        assert!(config.get(container::PUBLISH_KEY).is_some());
+    }
+
+    #[test]
+    fn the_starter_config_parses() {
+        assert!(parse_config(&starter_config("web")).is_ok());
+    }
+
+    #[test]
+    fn an_unknown_key_is_reported_at_its_line() {
+        let err = error("version = 1\nname = \"web\"\n\n[limits]\nmemroy = \"512M\"\n");
+        assert_eq!(err.line, 5);
+        assert!(err.message.contains("memroy"), "{}", err.message);
+    }
+
+    #[test]
+    fn a_bad_value_is_reported_at_its_line() {
+        let err = error("version = 1\n\n[limits]\nmemory = \"lots\"\n");
+        assert_eq!(err.line, 4);
+
+        let err = error("version = 2\n");
+        assert_eq!((err.line, err.message.as_str()), (1, "unsupported schema version 2, expected 1"));
+    }
+
+    #[test]
+    fn rejects_values_with_line_breaks() {
+        let err = error("version = 1\n\n[env]\nGREETING = \"hello\\nworld\"\n");
+        assert_eq!((err.line, err.message.as_str()), (4, "env.GREETING can't span several lines"));
+
+        let err = error("version = 1\nargs = [\"a\", \"b\\rc\"]\n");
+        assert_eq!((err.line, err.message.as_str()), (2, "args can't span several lines"));
+    }
+
+    #[test]
+    fn finds_the_line_of_dotted_keys() {
+        let text = "version = 1\nlimits = { memory = \"1G\" }\n\n[restart]\npolicy = \"no\"\n";
+        assert_eq!(line_of(text, "restart.policy"), 5);
+        assert_eq!(line_of(text, "limits.memory"), 2);
+        assert_eq!(line_of(text, "missing"), 1);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// A signal of the local terminal which an attached session forwards to its containers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+    /// The terminal was resized to this size.
+    WindowChange(WindowSize),
+}
+
+/// The size of a terminal in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub columns: u16,
+}
+
+/// The size as the daemon reads it, `<columns>x<rows>`, e.g. `80x24`.
+impl Display for WindowSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.columns, self.rows)
+    }
+}
+
+/// The name the daemon knows a signal by.
+impl Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::Interrupt => write!(f, "SIGINT"),
+            Signal::Terminate => write!(f, "SIGTERM"),
+            Signal::WindowChange(_) => write!(f, "SIGWINCH"),
+        }
+    }
+}
+
+/// Whether SIGINT, SIGTERM and SIGWINCH were caught and not forwarded yet.
+static CAUGHT: [AtomicBool; 3] = [AtomicBool::new(false), AtomicBool::new(false), AtomicBool::new(false)];
+
+/// Catches the signals to forward instead of letting them end joker, until it's dropped,
+/// which puts back the handlers from before. Only the first Ctrl-C is caught, so a second
+/// one ends joker if the containers don't stop. Nothing is caught on platforms other than Unix.
+pub struct Forwarding {
+    #[cfg(unix)]
+    previous: Vec<(libc::c_int, libc::sigaction)>,
+}
+
+/// Starts catching the signals of an attached session.
+pub fn forward() -> Forwarding {
+    catch(3)
+}
+
+/// Starts catching Ctrl-C and SIGTERM only, and leaves the resizes to a terminal
+/// library which listens to them itself.
+pub fn catch_termination() -> Forwarding {
+    catch(2)
+}
+
+/// Catches the first signals of `CAUGHT`.
+fn catch(count: usize) -> Forwarding {
+    for flag in &CAUGHT {
+        flag.store(false, Ordering::SeqCst);
+    }
+
+    #[cfg(unix)]
+    {
+        let previous = unix::NUMBERS[..count].iter()
+            .filter_map(|&number| unix::catch(number).map(|action| (number, action)))
+            .collect();
+        Forwarding { previous }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = count;
+        Forwarding {}
+    }
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for (number, action) in &self.previous {
+            // SAFETY: the action is the one `sigaction` returned for the same signal
+            unsafe {
+                libc::sigaction(*number, action, std::ptr::null_mut());
+            }
+        }
+    }
+}
+
+/// Waits for the time given or until a signal is caught, and returns the signals caught.
+/// A resize is returned with the new size of the terminal, and dropped if it has none.
+pub fn wait(timeout: Duration) -> Vec<Signal> {
+    const STEP: Duration = Duration::from_millis(50);
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        let caught = CAUGHT.iter()
+            .enumerate()
+            .filter(|(_, flag)| flag.swap(false, Ordering::SeqCst))
+            .filter_map(|(index, _)| match index {
+                0 => Some(Signal::Interrupt),
+                1 => Some(Signal::Terminate),
+                _ => window_size().map(Signal::WindowChange),
+            })
+            .collect::<Vec<_>>();
+
+        let left = deadline.saturating_duration_since(Instant::now());
+        if !caught.is_empty() || left.is_zero() {
+            return caught;
+        }
+        std::thread::sleep(left.min(STEP));
+    }
+}
+
+/// Returns the size of the terminal joker prints to, if it prints to one.
+pub fn window_size() -> Option<WindowSize> {
+    #[cfg(unix)]
+    {
+        let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+        // SAFETY: TIOCGWINSZ only writes a `winsize` to the pointer given
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        (result == 0 && size.ws_row > 0 && size.ws_col > 0).then_some(WindowSize { rows: size.ws_row, columns: size.ws_col })
+    }
+    #[cfg(not(unix))]
+    None
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::atomic::Ordering;
+    use super::CAUGHT;
+
+    /// The signals caught, in the order of `CAUGHT`.
+    pub const NUMBERS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGWINCH];
+
+    /// Installs `handle` for a signal and returns the action it replaced.
+    pub fn catch(number: libc::c_int) -> Option<libc::sigaction> {
+        // SAFETY: both structs are plain data which `sigaction` fills in or reads,
+        // and the handler only stores to atomics, which is async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // The default action is back after the first Ctrl-C, so a second one ends joker
+            action.sa_flags = match number {
+                libc::SIGINT => libc::SA_RESETHAND | libc::SA_RESTART,
+                _ => libc::SA_RESTART,
+            };
+            libc::sigemptyset(&mut action.sa_mask);
+
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            (libc::sigaction(number, &action, &mut previous) == 0).then_some(previous)
+        }
+    }
+
+    extern "C" fn handle(number: libc::c_int) {
+        if let Some(index) = NUMBERS.iter().position(|&caught| caught == number) {
+            CAUGHT[index].store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dropping_the_forwarding_restores_the_previous_handler() {
+        extern "C" fn previous(_: libc::c_int) {}
+
+        // SAFETY: the handlers are plain functions and `sigaction` only reads and fills in the structs
+        let handler = |number| unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(number, std::ptr::null(), &mut action);
+            action.sa_sigaction
+        };
+        // SAFETY: SIGWINCH is ignored by default, so a handler doing nothing changes nothing
+        unsafe {
+            libc::signal(libc::SIGWINCH, previous as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+
+        let forwarding = forward();
+        assert_ne!(handler(libc::SIGWINCH), previous as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        drop(forwarding);
+        assert_eq!(handler(libc::SIGWINCH), previous as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+
+    #[test]
+    fn a_window_size_is_sent_as_columns_by_rows() {
+        assert_eq!(WindowSize { rows: 24, columns: 80 }.to_string(), "80x24");
+        assert_eq!(Signal::WindowChange(WindowSize { rows: 24, columns: 80 }).to_string(), "SIGWINCH");
+    }
+}
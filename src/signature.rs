@@ -0,0 +1,106 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Serialize, Deserialize};
+use crate::store;
+
+/// The default file of the signing key. Its public key is written next to it, as `.pub`.
+pub const KEY_PATH: &str = "joker.key";
+
+/// The media types of the attachments sent along with a container.
+pub const SIGNATURE_TYPE: &str = "application/vnd.joker.signature.v1+json";
+
+/// A signature of the digest of a binary or of an image manifest.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Signature {
+    pub digest: String,
+    /// The public key of the signer, in base64.
+    pub key: String,
+    /// The ed25519 signature of the digest, in base64.
+    pub signature: String,
+}
+
+impl Signature {
+    /// Signs a digest, as `sha256:<hex>`.
+    pub fn sign(key: &SigningKey, digest: &str) -> Self {
+        Signature {
+            digest: digest.to_owned(),
+            key: STANDARD.encode(key.verifying_key().as_bytes()),
+            signature: STANDARD.encode(key.sign(digest.as_bytes()).to_bytes()),
+        }
+    }
+
+    /// Checks the signature against the key it names.
+    pub fn verify(&self) -> bool {
+        let Ok(key) = decode_public_key(&self.key) else { return false };
+        let Ok(signature) = STANDARD.decode(&self.signature) else { return false };
+        let Ok(signature) = ed25519_dalek::Signature::from_slice(&signature) else { return false };
+        key.verify(self.digest.as_bytes(), &signature).is_ok()
+    }
+
+    pub fn key_id(&self) -> String {
+        key_id(&self.key)
+    }
+}
+
+/// A short identifier of a public key: the beginning of the sha256 digest of its base64 form.
+pub fn key_id(public_key: &str) -> String {
+    let digest = store::digest(public_key.as_bytes());
+    digest["sha256:".len().."sha256:".len() + 16].to_owned()
+}
+
+pub fn decode_public_key(public_key: &str) -> io::Result<VerifyingKey> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Bad ed25519 public key.");
+    let bytes: [u8; 32] = STANDARD.decode(public_key.trim())
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| invalid())
+}
+
+/// Generates a signing key from the randomness of the operating system.
+pub fn generate_key() -> io::Result<SigningKey> {
+    let mut seed = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut seed)?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Writes a signing key readable only by its owner, and its public key next to it.
+pub fn write_key(key: &SigningKey, path: &Path, public_path: &Path) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    io::Write::write_all(&mut options.open(path)?, STANDARD.encode(key.to_bytes()).as_bytes())?;
+
+    fs::write(public_path, STANDARD.encode(key.verifying_key().as_bytes()))
+}
+
+pub fn read_key(path: &Path) -> io::Result<SigningKey> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Bad ed25519 key in {}.", path.display()));
+    let bytes: [u8; 32] = STANDARD.decode(fs::read_to_string(path)?.trim())
+        .map_err(|_| invalid())?
+        .try_into()
+        .map_err(|_| invalid())?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+/// Reads the signatures of a file written by `joker sign`, none if there's no such file.
+pub fn read_signatures(path: &Path) -> io::Result<Vec<Signature>> {
+    match File::open(path) {
+        Ok(file) => Ok(serde_json::from_reader(file)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Adds a signature to a list, replacing an older one of the same key.
+pub fn add_signature(signatures: &mut Vec<Signature>, signature: Signature) {
+    signatures.retain(|old| old.key != signature.key);
+    signatures.push(signature);
+}
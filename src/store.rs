@@ -0,0 +1,367 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::env;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::image::{self, Descriptor, Index, Manifest};
+use crate::registry::ImageReference;
+use crate::signature::{self, Signature};
+
+/// The directory of the store under the data directory of the user.
+const STORE_DIR: &str = "store";
+
+/// Finds the local store: `$XDG_DATA_HOME/joker/store`, or `~/.local/share/joker/store`.
+/// Without a home directory it falls back to `store` in the working directory.
+pub fn store_path() -> PathBuf {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")));
+    match data_home {
+        Some(data_home) => data_home.join("joker").join(STORE_DIR),
+        None => PathBuf::from(STORE_DIR),
+    }
+}
+
+/// An image of a store, as listed by `joker images`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ImageInfo {
+    pub reference: String,
+    pub digest: String,
+    /// `image` for images with a root filesystem, `joker` for joker artifacts.
+    pub kind: String,
+    /// The total size of the blobs of the image.
+    pub size: u64,
+}
+
+const TAGS_FILE: &str = "tags.json";
+const SIGNATURES_DIR: &str = "signatures";
+
+/// The files of an OCI image layout, the format of archives made by `joker save`.
+const LAYOUT_FILE: &str = "oci-layout";
+const LAYOUT_INDEX: &str = "index.json";
+const LAYOUT_VERSION: &str = "1.0.0";
+
+/// A local store of images: blobs addressed by their sha256 digests
+/// and a database of tags pointing to image manifests.
+#[derive(Clone)]
+pub struct Store {
+    root: PathBuf,
+}
+
+impl Store {
+    /// Opens the store at the default location, creating it if needed.
+    pub fn open() -> io::Result<Self> {
+        Store::open_at(store_path())
+    }
+
+    /// Opens the store at the default location for a lookup,
+    /// without creating it: `None` until something is stored.
+    pub fn open_existing() -> Option<Self> {
+        let root = store_path();
+        root.is_dir().then_some(Store { root })
+    }
+
+    pub fn open_at(root: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(root.join("blobs").join("sha256"))?;
+        Ok(Store { root })
+    }
+
+    /// Returns the path of a blob, which may not exist yet.
+    pub fn blob_path(&self, digest: &str) -> io::Result<PathBuf> {
+        let hex = digest.strip_prefix("sha256:")
+            .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported digest {}.", digest)))?;
+        Ok(self.root.join("blobs").join("sha256").join(hex))
+    }
+
+    pub fn has_blob(&self, digest: &str) -> bool {
+        self.blob_path(digest).is_ok_and(|path| path.is_file())
+    }
+
+    pub fn read_blob(&self, digest: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.blob_path(digest)?)
+    }
+
+    pub fn open_blob(&self, digest: &str) -> io::Result<File> {
+        File::open(self.blob_path(digest)?)
+    }
+
+    /// Stores a blob and returns its digest.
+    pub fn write_blob(&self, data: &[u8]) -> io::Result<String> {
+        let digest = digest(data);
+        self.write_blob_from(&digest, data)?;
+        Ok(digest)
+    }
+
+    /// Streams a blob into the store, checking that its contents
+    /// match the expected digest. A partial blob is never left in the store.
+    pub fn write_blob_from(&self, expected_digest: &str, mut reader: impl Read) -> io::Result<u64> {
+        let path = self.blob_path(expected_digest)?;
+        let partial = path.with_extension("partial");
+
+        let mut file = File::create(&partial)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut size = 0;
+
+        let result = loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(read) => {
+                    hasher.update(&buffer[..read]);
+                    if let Err(err) = file.write_all(&buffer[..read]) {
+                        break Err(err);
+                    }
+                    size += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => break Err(err),
+            }
+        };
+
+        let actual_digest = format!("sha256:{:x}", hasher.finalize());
+        let result = result.and_then(|_| match actual_digest == expected_digest {
+            true => Ok(()),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Digest mismatch: expected {}, got {}.", expected_digest, actual_digest),
+            )),
+        });
+
+        match result {
+            Ok(()) => {
+                fs::rename(&partial, &path)?;
+                Ok(size)
+            }
+            Err(err) => {
+                let _ = fs::remove_file(&partial);
+                Err(err)
+            }
+        }
+    }
+
+    /// Returns all the tags of the store with the digests of their manifests.
+    pub fn tags(&self) -> io::Result<BTreeMap<String, String>> {
+        match File::open(self.root.join(TAGS_FILE)) {
+            Ok(file) => Ok(serde_json::from_reader(file)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn get_tag(&self, reference: &str) -> io::Result<Option<String>> {
+        Ok(self.tags()?.remove(reference))
+    }
+
+    /// Finds the manifest of an image of the store by its reference.
+    /// A digest may name an index pulled before, which points to the manifest of this platform.
+    pub fn resolve(&self, reference: &ImageReference) -> io::Result<Option<String>> {
+        if let Some(digest) = self.get_tag(&reference.store_name())? {
+            return Ok(Some(digest));
+        }
+        match &reference.digest {
+            Some(digest) => Ok(self.has_blob(digest).then(|| digest.clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// Points a tag to a manifest.
+    pub fn set_tag(&self, reference: &str, manifest_digest: &str) -> io::Result<()> {
+        let mut tags = self.tags()?;
+        tags.insert(reference.to_owned(), manifest_digest.to_owned());
+        self.write_tags(&tags)
+    }
+
+    /// Removes a tag. Returns the digest of the manifest it pointed to.
+    pub fn remove_tag(&self, reference: &str) -> io::Result<Option<String>> {
+        let mut tags = self.tags()?;
+        let digest = tags.remove(reference);
+        if digest.is_some() {
+            self.write_tags(&tags)?;
+        }
+        Ok(digest)
+    }
+
+    /// Lists the tagged images of the store.
+    pub fn images(&self) -> io::Result<Vec<ImageInfo>> {
+        self.tags()?
+            .into_iter()
+            .map(|(reference, digest)| {
+                let manifest = self.read_manifest(&digest)?;
+                let kind = match manifest.artifact_type.as_deref() {
+                    Some(image::JOKER_ARTIFACT) => "joker",
+                    _ => "image",
+                };
+                let size = std::iter::once(&manifest.config)
+                    .chain(&manifest.layers)
+                    .map(|blob| blob.size)
+                    .sum();
+                Ok(ImageInfo { reference, digest, kind: kind.to_owned(), size })
+            })
+            .collect()
+    }
+
+    pub fn read_manifest(&self, digest: &str) -> io::Result<Manifest> {
+        Ok(serde_json::from_slice(&self.read_blob(digest)?)?)
+    }
+
+    /// Removes the blobs which no tagged manifest refers to.
+    /// The index an image was pulled by, named in its tag, is kept too.
+    /// Returns the number of removed blobs and their total size.
+    pub fn collect_garbage(&self) -> io::Result<(usize, u64)> {
+        let mut referenced = HashSet::new();
+        for (reference, digest) in self.tags()? {
+            if let Some((_, pulled_digest)) = reference.rsplit_once('@') {
+                referenced.insert(pulled_digest.to_owned());
+            }
+            let manifest = self.read_manifest(&digest)?;
+            referenced.insert(manifest.config.digest);
+            referenced.extend(manifest.layers.into_iter().map(|layer| layer.digest));
+            referenced.insert(digest);
+        }
+
+        let mut removed = (0, 0);
+        for entry in fs::read_dir(self.root.join("blobs").join("sha256"))? {
+            let entry = entry?;
+            let digest = format!("sha256:{}", entry.file_name().to_string_lossy());
+            if !referenced.contains(&digest) {
+                removed.0 += 1;
+                removed.1 += entry.metadata()?.len();
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Writes images to an archive in the OCI image layout. Every image
+    /// is a tag of the store with the digest of its manifest.
+    pub fn save(&self, images: &BTreeMap<String, String>, writer: impl Write) -> io::Result<()> {
+        let mut builder = tar::Builder::new(writer);
+        let mut index = Index { schema_version: 2, media_type: Some(image::OCI_INDEX.to_owned()), manifests: Vec::new() };
+        let mut saved = HashSet::new();
+
+        let layout = serde_json::to_vec(&serde_json::json!({ "imageLayoutVersion": LAYOUT_VERSION }))?;
+        append_file(&mut builder, LAYOUT_FILE, layout.len() as u64, layout.as_slice())?;
+
+        for (reference, digest) in images {
+            let manifest_data = self.read_blob(digest)?;
+            let manifest: Manifest = serde_json::from_slice(&manifest_data)?;
+
+            let blobs = std::iter::once(digest)
+                .chain(std::iter::once(&manifest.config.digest))
+                .chain(manifest.layers.iter().map(|layer| &layer.digest));
+            for blob in blobs {
+                if saved.insert(blob.clone()) {
+                    let file = self.open_blob(blob)?;
+                    let path = format!("blobs/sha256/{}", &blob["sha256:".len()..]);
+                    append_file(&mut builder, &path, file.metadata()?.len(), file)?;
+                }
+            }
+
+            let media_type = manifest.media_type.as_deref().unwrap_or(image::OCI_MANIFEST);
+            let mut descriptor = Descriptor::new(media_type, &manifest_data);
+            descriptor.annotations.insert(image::REF_NAME_ANNOTATION.to_owned(), reference.clone());
+            index.manifests.push(descriptor);
+        }
+
+        let index = serde_json::to_vec_pretty(&index)?;
+        append_file(&mut builder, LAYOUT_INDEX, index.len() as u64, index.as_slice())?;
+        builder.into_inner()?.flush()
+    }
+
+    /// Loads the images of an archive in the OCI image layout, checking the digests
+    /// of all the blobs. Returns the tags of the loaded images with their manifests.
+    pub fn load(&self, reader: impl Read) -> io::Result<BTreeMap<String, String>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut index = None;
+
+        for entry in tar::Archive::new(reader).entries()? {
+            let mut entry = entry?;
+            // archives of other tools have entries for the directories too
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = entry.path()?.to_string_lossy().trim_start_matches("./").to_owned();
+
+            if let Some(hex) = path.strip_prefix("blobs/sha256/") {
+                self.write_blob_from(&format!("sha256:{}", hex), &mut entry)?;
+            } else if path == LAYOUT_INDEX {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                index = Some(serde_json::from_slice::<Index>(&data)?);
+            }
+        }
+
+        let index = index.ok_or_else(|| invalid(format!("The archive has no {}.", LAYOUT_INDEX)))?;
+        let mut loaded = BTreeMap::new();
+        for manifest in index.manifests {
+            let reference = manifest.annotations.get(image::REF_NAME_ANNOTATION)
+                .and_then(|name| name.parse::<ImageReference>().ok())
+                .ok_or_else(|| invalid(format!("Manifest {} has no image reference.", manifest.digest)))?;
+
+            // An image is only tagged if the archive has all of its blobs
+            let image = self.read_manifest(&manifest.digest)?;
+            if let Some(blob) = std::iter::once(&image.config).chain(&image.layers).find(|blob| !self.has_blob(&blob.digest)) {
+                return Err(invalid(format!("The archive lacks blob {} of {}.", blob.digest, reference)));
+            }
+
+            self.set_tag(&reference.store_name(), &manifest.digest)?;
+            loaded.insert(reference.store_name(), manifest.digest);
+        }
+
+        Ok(loaded)
+    }
+
+    /// Returns the signatures of a manifest made by `joker sign`.
+    pub fn signatures(&self, manifest_digest: &str) -> io::Result<Vec<Signature>> {
+        signature::read_signatures(&self.signatures_path(manifest_digest)?)
+    }
+
+    pub fn write_signatures(&self, manifest_digest: &str, signatures: &[Signature]) -> io::Result<()> {
+        let path = self.signatures_path(manifest_digest)?;
+        fs::create_dir_all(self.root.join(SIGNATURES_DIR))?;
+        serde_json::to_writer_pretty(File::create(path)?, signatures)?;
+        Ok(())
+    }
+
+    fn signatures_path(&self, manifest_digest: &str) -> io::Result<PathBuf> {
+        let blob = self.blob_path(manifest_digest)?;
+        let hex = blob.file_name().unwrap_or_default().to_string_lossy();
+        Ok(self.root.join(SIGNATURES_DIR).join(format!("{}.json", hex)))
+    }
+
+    fn write_tags(&self, tags: &BTreeMap<String, String>) -> io::Result<()> {
+        // Written aside and renamed, so that an interrupted write doesn't lose all the tags
+        let partial = self.root.join(format!("{}.partial", TAGS_FILE));
+        serde_json::to_writer_pretty(File::create(&partial)?, tags)?;
+        fs::rename(partial, self.root.join(TAGS_FILE))
+    }
+}
+
+/// Appends a regular file to an archive.
+fn append_file(builder: &mut tar::Builder<impl Write>, path: &str, size: u64, data: impl Read) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+/// Computes the sha256 digest of the data, as `sha256:<hex>`.
+pub fn digest(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+/// Computes the digest of a stream too large to be read into memory. Returns its size too.
+pub fn digest_reader(mut reader: impl Read) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut reader, &mut hasher)?;
+    Ok((format!("sha256:{:x}", hasher.finalize()), size))
+}
@@ -0,0 +1,120 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The name of the unit `joker daemon install-service` writes.
+pub const UNIT_NAME: &str = "joker-daemon.service";
+
+/// The sandboxing of a system unit. The containers inherit it and bind mount paths of the
+/// host, so what would break setuid binaries in images or hide `/tmp` and `/home` is left out.
+const HARDENING: &[&str] = &[
+    "ProtectSystem=full",
+    "ProtectKernelModules=yes",
+    "ProtectKernelLogs=yes",
+    "ProtectClock=yes",
+    "RestrictRealtime=yes",
+    "LockPersonality=yes",
+    "RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK",
+];
+
+/// Returns a unit which runs the daemon, an executable with its arguments, and restarts it
+/// when it fails. A system unit is sandboxed and gets the cgroups of its containers
+/// delegated. A user unit can't be sandboxed by a user manager, so it isn't.
+pub fn unit(executable: &Path, args: &[String], user: bool) -> String {
+    let command = std::iter::once(executable.to_string_lossy().into_owned())
+        .chain(args.iter().cloned())
+        .map(|word| quote(&word))
+        .collect::<Vec<_>>();
+
+    let mut lines = vec![
+        "[Unit]".to_owned(),
+        "Description=joker container daemon".to_owned(),
+        "Documentation=https://github.com/Joker-containers/joker".to_owned(),
+        "After=network-online.target".to_owned(),
+        "Wants=network-online.target".to_owned(),
+        String::new(),
+        "[Service]".to_owned(),
+        "Type=exec".to_owned(),
+        format!("ExecStart={}", command.join(" ")),
+        "Restart=on-failure".to_owned(),
+        "RestartSec=2s".to_owned(),
+    ];
+    if !user {
+        lines.push("Delegate=yes".to_owned());
+        // Stopping the daemon leaves its containers to it, not to systemd
+        lines.push("KillMode=process".to_owned());
+        lines.push("StateDirectory=joker".to_owned());
+        lines.extend(HARDENING.iter().map(|&line| line.to_owned()));
+    }
+    lines.extend([
+        String::new(),
+        "[Install]".to_owned(),
+        format!("WantedBy={}", if user { "default.target" } else { "multi-user.target" }),
+    ]);
+
+    lines.join("\n") + "\n"
+}
+
+/// Returns where systemd looks for the units of the administrator: `/etc/systemd/system`,
+/// or `systemd/user` of the XDG config directory for a user unit.
+pub fn unit_path(user: bool) -> Option<PathBuf> {
+    if !user {
+        return Some(Path::new("/etc/systemd/system").join(UNIT_NAME));
+    }
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("systemd").join("user").join(UNIT_NAME))
+}
+
+/// Quotes a word of a command line of a unit if it needs to be, and escapes
+/// `%`, which starts a specifier of systemd.
+fn quote(word: &str) -> String {
+    let word = word.replace('%', "%%");
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';')) {
+        return word;
+    }
+
+    format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_system_unit_serves_sandboxed() {
+        let unit = unit(Path::new("/usr/local/bin/jokerd"), &["--port".to_owned(), "7788".to_owned()], false);
+        assert!(unit.contains("\nExecStart=/usr/local/bin/jokerd --port 7788\n"));
+        assert!(unit.contains("\nDelegate=yes\n"));
+        for line in HARDENING {
+            assert!(unit.contains(&format!("\n{}\n", line)), "{}", line);
+        }
+        assert!(unit.ends_with("[Install]\nWantedBy=multi-user.target\n"));
+    }
+
+    #[test]
+    fn a_user_unit_isnt_sandboxed() {
+        let unit = unit(Path::new("/home/me/bin/jokerd"), &[], true);
+        assert!(unit.contains("\nExecStart=/home/me/bin/jokerd\n"));
+        assert!(!unit.contains("ProtectSystem"));
+        assert!(!unit.contains("Delegate"));
+        assert!(unit.ends_with("WantedBy=default.target\n"));
+    }
+
+    #[test]
+    fn quotes_executables_systemd_would_split() {
+        assert_eq!(quote("/opt/joker/bin/joker"), "/opt/joker/bin/joker");
+        assert_eq!(quote("/opt/my tools/joker"), "\"/opt/my tools/joker\"");
+        assert_eq!(quote("/opt/100%/joker"), "/opt/100%%/joker");
+        assert_eq!(quote("/opt/\"x\"/joker"), "\"/opt/\\\"x\\\"/joker\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+
+    #[test]
+    fn user_units_go_to_the_config_directory() {
+        assert_eq!(unit_path(false), Some(PathBuf::from("/etc/systemd/system/joker-daemon.service")));
+        assert!(unit_path(true).is_none_or(|path| path.ends_with("systemd/user/joker-daemon.service")));
+    }
+}
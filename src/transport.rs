@@ -0,0 +1,48 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use crate::daemon::Daemon;
+
+/// A connection to a daemon which carries a single request and its response.
+pub trait Connection: Read + Write {}
+
+impl<T: Read + Write> Connection for T {}
+
+/// The way a `JokerClient` reaches daemons. Every request is made over a new connection.
+pub trait Transport: Send + Sync {
+    fn connect(&self, daemon: &Daemon) -> io::Result<Box<dyn Connection>>;
+}
+
+/// Connects to daemons over TCP, like the CLI does. With a timeout, connecting,
+/// reading and writing fail instead of waiting for a daemon which doesn't answer.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpTransport {
+    pub timeout: Option<Duration>,
+}
+
+impl Transport for TcpTransport {
+    fn connect(&self, daemon: &Daemon) -> io::Result<Box<dyn Connection>> {
+        let tcp_stream = match self.timeout {
+            Some(timeout) => connect_timeout(&daemon.socket_address, timeout)?,
+            None => TcpStream::connect(&daemon.socket_address)?,
+        };
+        tcp_stream.set_nodelay(true)?;
+        tcp_stream.set_read_timeout(self.timeout)?;
+        tcp_stream.set_write_timeout(self.timeout)?;
+
+        Ok(Box::new(tcp_stream))
+    }
+}
+
+/// Tries every address a host resolves to, like `TcpStream::connect` does.
+fn connect_timeout(address: &impl ToSocketAddrs, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, timeout) {
+            Ok(tcp_stream) => return Ok(tcp_stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")))
+}
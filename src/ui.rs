@@ -0,0 +1,645 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::IsTerminal;
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use crate::client::{self, JokerClient};
+use crate::container::{ContainerInfo, StatsSample};
+use crate::daemon::DaemonStatus;
+use crate::errors::JokerError;
+use crate::signals::{self, Signal};
+
+/// How many events the dashboard keeps.
+const EVENTS: usize = 100;
+
+/// How much of the output of a container the logs view keeps.
+const LOG_BYTES: usize = 1 << 20;
+
+/// How often the keys are checked between two refreshes.
+const KEY_INTERVAL: Duration = Duration::from_millis(100);
+
+const HELP: &str = "↑↓ select  s stop  r restart  l logs  i inspect  q quit";
+
+/// What a refresh learnt about a daemon.
+struct DaemonState {
+    status: Result<DaemonStatus, String>,
+    containers: Vec<ContainerInfo>,
+    /// The latest samples of the running containers, by their names.
+    stats: HashMap<String, StatsSample>,
+}
+
+/// What the dashboard asks the background worker to do, with the index of a daemon.
+#[derive(Debug, PartialEq)]
+enum Job {
+    Stop { daemon: usize, container: String },
+    Restart { daemon: usize, container: String },
+    /// The output of a container after the `offset` bytes the view has.
+    Logs { daemon: usize, container: String, offset: u64 },
+    Inspect { daemon: usize, container: String },
+}
+
+/// What the background worker sends back to the dashboard.
+enum Update {
+    Refreshed(Vec<DaemonState>),
+    Event(String),
+    /// The output after the offset asked for and the length of the whole output.
+    Logs { daemon: usize, container: String, output: Result<(Vec<u8>, u64), String> },
+    Inspected { daemon: usize, container: String, info: Result<ContainerInfo, String> },
+}
+
+/// What the dashboard shows besides the daemons and the containers.
+enum View {
+    Dashboard,
+    Logs { daemon: usize, container: String, output: Vec<u8>, offset: u64, error: Option<String> },
+    Inspect { daemon: usize, container: String, lines: Vec<String> },
+}
+
+struct Dashboard {
+    clients: Vec<JokerClient>,
+    /// In the order of the clients, empty until the first refresh.
+    daemons: Vec<DaemonState>,
+    /// Unix timestamps with what happened, the latest last.
+    events: VecDeque<(u64, String)>,
+    /// The index of the selected container among the containers of every daemon.
+    selected: usize,
+    view: View,
+    refreshed: Option<u64>,
+    jobs: mpsc::Sender<Job>,
+}
+
+/// Shows the daemons, their containers with their latest stats and what changed
+/// between the refreshes until `q` is pressed. The daemons are refreshed and the
+/// keys acted on by a background worker, so an unreachable daemon doesn't freeze the keys.
+/// Propagates the error down the stack trace.
+pub fn run(clients: Vec<JokerClient>, interval: Duration) -> Result<(), JokerError> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(JokerError::Other("Error: joker ui needs a terminal.".into()));
+    }
+
+    let (jobs, received_jobs) = mpsc::channel();
+    let (sender, updates) = mpsc::channel();
+    let background = clients.clone();
+    std::thread::spawn(move || work(&background, interval, &received_jobs, &sender));
+
+    let mut dashboard = Dashboard::new(clients, jobs);
+    let mut screen = Screen::enter().map_err(|err| JokerError::Other(err.into()))?;
+    let _forwarding = signals::catch_termination();
+    let mut redraw = true;
+    loop {
+        for update in updates.try_iter() {
+            dashboard.apply(update);
+            redraw = true;
+        }
+
+        if signals::wait(Duration::ZERO).iter().any(|signal| matches!(signal, Signal::Interrupt | Signal::Terminate)) {
+            break;
+        }
+
+        if redraw {
+            screen.0.draw(|frame| dashboard.draw(frame)).map_err(|err| JokerError::Other(err.into()))?;
+            redraw = false;
+        }
+
+        if !event::poll(KEY_INTERVAL).map_err(|err| JokerError::Other(err.into()))? {
+            continue;
+        }
+        match event::read().map_err(|err| JokerError::Other(err.into()))? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                // Ctrl-C doesn't send a signal in raw mode
+                let code = match key.code {
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char('q'),
+                    code => code,
+                };
+                if code == KeyCode::Char('q') && matches!(dashboard.view, View::Dashboard) {
+                    break;
+                }
+                dashboard.press(code);
+                redraw = true;
+            }
+            Event::Resize(..) => redraw = true,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The terminal in raw mode on its alternate screen, until it's dropped.
+struct Screen(DefaultTerminal);
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        ratatui::try_init().map(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
+
+/// Refreshes the daemons every interval and does the jobs of the dashboard in between,
+/// until the dashboard is gone.
+fn work(clients: &[JokerClient], interval: Duration, jobs: &mpsc::Receiver<Job>, updates: &mpsc::Sender<Update>) {
+    let mut next_refresh = Instant::now();
+    loop {
+        let left = next_refresh.saturating_duration_since(Instant::now());
+        let update = match jobs.recv_timeout(left) {
+            Ok(job) => do_job(clients, job),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                next_refresh = Instant::now() + interval;
+                Update::Refreshed(refresh(clients))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+
+        // the dashboard is gone once the receiver is dropped
+        if updates.send(update).is_err() {
+            break;
+        }
+    }
+}
+
+fn do_job(clients: &[JokerClient], job: Job) -> Update {
+    match job {
+        Job::Stop { daemon, container } => action_event(&clients[daemon], "stop", &container, clients[daemon].stop(&container)),
+        Job::Restart { daemon, container } => action_event(&clients[daemon], "restart", &container, clients[daemon].restart(&container)),
+        Job::Logs { daemon, container, offset } => {
+            let output = clients[daemon].logs_from(&container, offset).map_err(|err| err.to_string());
+            Update::Logs { daemon, container, output }
+        }
+        Job::Inspect { daemon, container } => {
+            let info = clients[daemon].inspect(&container).map_err(|err| err.to_string());
+            Update::Inspected { daemon, container, info }
+        }
+    }
+}
+
+fn action_event(client: &JokerClient, verb: &str, container: &str, result: Result<(), JokerError>) -> Update {
+    Update::Event(match result {
+        Ok(()) => format!("asked {} to {} {}", client.daemon().name, verb, container),
+        Err(err) => format!("couldn't {} {}/{}: {}", verb, client.daemon().name, container, err),
+    })
+}
+
+/// Pings every daemon and lists its containers with their latest stats, all daemons at once.
+fn refresh(clients: &[JokerClient]) -> Vec<DaemonState> {
+    let results = client::broadcast(clients, |client| {
+        let status = client.ping().map(|(status, _)| status).map_err(|err| err.to_string());
+        let containers = match status {
+            Ok(_) => client.list().unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        // daemons without stats just show no usage
+        let stats = containers.iter()
+            .filter(|container| container.status == "running")
+            .filter_map(|container| {
+                let sample = client.stats(&container.name, None).ok()?.pop()?;
+                Some((container.name.clone(), sample))
+            })
+            .collect();
+
+        Ok(DaemonState { status, containers, stats })
+    });
+
+    results.into_iter()
+        .map(|result| result.unwrap_or_else(|err| DaemonState {
+            status: Err(err),
+            containers: Vec::new(),
+            stats: HashMap::new(),
+        }))
+        .collect()
+}
+
+impl Dashboard {
+    fn new(clients: Vec<JokerClient>, jobs: mpsc::Sender<Job>) -> Self {
+        Dashboard {
+            clients,
+            daemons: Vec::new(),
+            events: VecDeque::new(),
+            selected: 0,
+            view: View::Dashboard,
+            refreshed: None,
+            jobs,
+        }
+    }
+
+    /// Takes what the background worker sent.
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Refreshed(daemons) => {
+                self.update(daemons);
+                self.reload_view();
+            }
+            Update::Event(event) => self.push_event(event),
+            Update::Logs { daemon, container, output } => {
+                if let View::Logs { daemon: shown, container: name, output: shown_output, offset, error } = &mut self.view {
+                    if *shown != daemon || *name != container {
+                        return;
+                    }
+                    let (output, length) = match output {
+                        Ok(output) => output,
+                        Err(err) => {
+                            *error = Some(err);
+                            return;
+                        }
+                    };
+                    // Output shorter than what the view has belongs to a new run and comes whole
+                    if length < *offset {
+                        shown_output.clear();
+                    }
+                    shown_output.extend_from_slice(&output);
+                    let extra = shown_output.len().saturating_sub(LOG_BYTES);
+                    shown_output.drain(..extra);
+                    *offset = length;
+                    *error = None;
+                }
+            }
+            Update::Inspected { daemon, container, info } => {
+                let stats = self.daemons.get(daemon).and_then(|state| state.stats.get(&container)).cloned();
+                if let View::Inspect { daemon: shown, container: name, lines } = &mut self.view {
+                    if *shown == daemon && *name == container {
+                        *lines = match info {
+                            Ok(info) => inspect_lines(&info, stats.as_ref()),
+                            Err(err) => vec![err],
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Takes the result of a refresh and records what changed since the previous one.
+    fn update(&mut self, daemons: Vec<DaemonState>) {
+        let first = self.daemons.is_empty();
+        let mut events = Vec::new();
+
+        for (index, state) in daemons.iter().enumerate() {
+            let name = &self.clients[index].daemon().name;
+            let previous = self.daemons.get(index);
+
+            match (previous.map(|previous| &previous.status), &state.status) {
+                (Some(Ok(_)) | None, Err(err)) => events.push(format!("daemon {}: {}", name, err)),
+                (Some(Err(_)), Ok(_)) => events.push(format!("daemon {} is back", name)),
+                _ => {}
+            }
+            if first || state.status.is_err() {
+                continue;
+            }
+
+            let before = previous.map(|previous| previous.containers.as_slice()).unwrap_or_default();
+            for container in &state.containers {
+                match before.iter().find(|old| old.name == container.name) {
+                    None => events.push(format!("{}/{} appeared, {}", name, container.name, container.status)),
+                    Some(old) if old.restart_count < container.restart_count => {
+                        events.push(format!("{}/{} restarted, {} restarts so far", name, container.name, container.restart_count));
+                    }
+                    Some(old) if old.status != container.status => events.push(match container.exit_code {
+                        Some(code) if container.status != "running" => {
+                            format!("{}/{} is {} with code {}", name, container.name, container.status, code)
+                        }
+                        _ => format!("{}/{} is {}", name, container.name, container.status),
+                    }),
+                    Some(old) if old.health != container.health => {
+                        if let Some(health) = &container.health {
+                            events.push(format!("{}/{} is {}", name, container.name, health));
+                        }
+                    }
+                    Some(_) => {}
+                }
+            }
+            for old in before {
+                if !state.containers.iter().any(|container| container.name == old.name) {
+                    events.push(format!("{}/{} was removed", name, old.name));
+                }
+            }
+        }
+
+        self.daemons = daemons;
+        self.refreshed = Some(now());
+        for event in events {
+            self.push_event(event);
+        }
+        self.selected = self.selected.min(self.rows().len().saturating_sub(1));
+    }
+
+    fn push_event(&mut self, event: String) {
+        if self.events.len() == EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back((now(), event));
+    }
+
+    /// The containers of every daemon with the index of their daemon, in the order they're shown.
+    fn rows(&self) -> Vec<(usize, &ContainerInfo)> {
+        self.daemons.iter().enumerate()
+            .flat_map(|(index, state)| state.containers.iter().map(move |container| (index, container)))
+            .collect()
+    }
+
+    fn selection(&self) -> Option<(usize, String)> {
+        self.rows().get(self.selected).map(|(index, container)| (*index, container.name.clone()))
+    }
+
+    fn press(&mut self, key: KeyCode) {
+        if !matches!(self.view, View::Dashboard) {
+            if matches!(key, KeyCode::Char('q') | KeyCode::Esc) {
+                self.view = View::Dashboard;
+            }
+            return;
+        }
+
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.selected = (self.selected + 1).min(self.rows().len().saturating_sub(1)),
+            KeyCode::Char(action @ ('s' | 'r')) => {
+                let Some((daemon, container)) = self.selection() else { return };
+                let _ = self.jobs.send(match action {
+                    's' => Job::Stop { daemon, container },
+                    _ => Job::Restart { daemon, container },
+                });
+            }
+            KeyCode::Char('l') => {
+                let Some((daemon, container)) = self.selection() else { return };
+                self.view = View::Logs { daemon, container, output: Vec::new(), offset: 0, error: None };
+                self.reload_view();
+            }
+            KeyCode::Char('i') => {
+                let Some((daemon, container)) = self.selection() else { return };
+                self.view = View::Inspect { daemon, container, lines: Vec::new() };
+                self.reload_view();
+            }
+            _ => {}
+        }
+    }
+
+    /// Asks the worker for what the view shows again: only the output written since
+    /// the last time for the logs.
+    fn reload_view(&mut self) {
+        let job = match &self.view {
+            View::Dashboard => return,
+            View::Logs { daemon, container, offset, .. } => Job::Logs { daemon: *daemon, container: container.clone(), offset: *offset },
+            View::Inspect { daemon, container, .. } => Job::Inspect { daemon: *daemon, container: container.clone() },
+        };
+        // a worker which is gone has ended the dashboard too
+        let _ = self.jobs.send(job);
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [body, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let help = match self.view {
+            View::Dashboard => HELP,
+            _ => "esc back",
+        };
+        frame.render_widget(Paragraph::new(help), footer);
+
+        match &self.view {
+            View::Dashboard => self.draw_dashboard(frame, body),
+            View::Logs { daemon, container, output, error, .. } => {
+                let mut text = vec![Line::from(format!("Logs of {}/{}", self.clients[*daemon].daemon().name, container)).bold()];
+                text.extend(error.iter().map(|err| Line::from(err.clone()).reversed()));
+                let output = String::from_utf8_lossy(output);
+                let lines = output.lines().collect::<Vec<_>>();
+                let shown = lines.len().saturating_sub((body.height as usize).saturating_sub(text.len()));
+                text.extend(lines[shown..].iter().map(|line| Line::from(line.to_string())));
+                frame.render_widget(Paragraph::new(text), body);
+            }
+            View::Inspect { daemon, container, lines } => {
+                let mut text = vec![Line::from(format!("{}/{}", self.clients[*daemon].daemon().name, container)).bold()];
+                text.extend(lines.iter().map(|line| Line::from(line.clone())));
+                frame.render_widget(Paragraph::new(text), body);
+            }
+        }
+    }
+
+    fn draw_dashboard(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.rows();
+        // the events keep a few lines and the containers scroll in the rest
+        let events = self.events.len().clamp(1, 5);
+        let [title, daemons_area, containers_area, events_area] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length(self.clients.len() as u16 + 2),
+            Constraint::Min(2),
+            Constraint::Length(events as u16 + 2),
+        ]).areas(area);
+
+        let refreshed = self.refreshed.map_or_else(|| "refreshing...".to_owned(), |time| format!("refreshed at {}", clock(time)));
+        let title_text = format!("joker ui  {} daemons, {} containers, {}", self.clients.len(), rows.len(), refreshed);
+        frame.render_widget(Paragraph::new(title_text), title);
+
+        let daemons = self.clients.iter().enumerate()
+            .map(|(index, client)| {
+                let daemon = client.daemon();
+                match self.daemons.get(index).map(|state| &state.status) {
+                    Some(Ok(status)) => vec![
+                        daemon.name.clone(),
+                        daemon.socket_address.to_string(),
+                        Some(status.version.clone()).filter(|version| !version.is_empty()).unwrap_or_else(|| "-".to_owned()),
+                        status.containers_running.to_string(),
+                        match status.cpus {
+                            0 => "-".to_owned(),
+                            cpus => format!("{:.2} / {}", status.load_average, cpus),
+                        },
+                        match status.memory_total {
+                            0 => "-".to_owned(),
+                            total => format!("{} free of {}", crate::format_size(status.memory_available), crate::format_size(total)),
+                        },
+                        crate::format_uptime(status.uptime),
+                    ],
+                    // the error itself is in the events
+                    Some(Err(_)) => vec![daemon.name.clone(), daemon.socket_address.to_string(), "unreachable".to_owned()],
+                    None => vec![daemon.name.clone(), daemon.socket_address.to_string(), "...".to_owned()],
+                }
+            })
+            .collect::<Vec<_>>();
+        let headers = ["DAEMON", "ADDRESS", "VERSION", "RUNNING", "LOAD", "MEMORY", "UPTIME"];
+        frame.render_widget(table(&headers, &daemons), daemons_area);
+
+        let containers = rows.iter()
+            .map(|(index, container)| {
+                let stats = self.daemons[*index].stats.get(&container.name);
+                vec![
+                    self.clients[*index].daemon().name.clone(),
+                    container.name.clone(),
+                    container.status.clone(),
+                    container.health.clone().unwrap_or_else(|| "-".to_owned()),
+                    container.restart_count.to_string(),
+                    stats.map_or_else(|| "-".to_owned(), |stats| format!("{:.1}%", stats.cpu_percent)),
+                    stats.map_or_else(|| "-".to_owned(), |stats| crate::format_size(stats.memory)),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let headers = ["DAEMON", "CONTAINER", "STATUS", "HEALTH", "RESTARTS", "CPU", "MEMORY"];
+        let mut state = TableState::default().with_selected((!rows.is_empty()).then_some(self.selected));
+        frame.render_stateful_widget(table(&headers, &containers).row_highlight_style(Style::new().reversed()), containers_area, &mut state);
+        if rows.is_empty() {
+            let below_header = Rect { y: containers_area.y + 1, height: containers_area.height.saturating_sub(1), ..containers_area };
+            frame.render_widget(Paragraph::new("no containers"), below_header);
+        }
+
+        let mut text = vec![Line::default(), Line::from("EVENTS").bold()];
+        text.extend(self.events.iter().rev().take(events).map(|(time, event)| Line::from(format!("{} {}", clock(*time), event))));
+        frame.render_widget(Paragraph::new(text), events_area);
+    }
+}
+
+/// A table with a bold header and columns as wide as their widest cell.
+fn table(headers: &[&'static str], rows: &[Vec<String>]) -> Table<'static> {
+    let widths = headers.iter().enumerate()
+        .map(|(column, header)| {
+            let width = rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .fold(header.len(), usize::max);
+            Constraint::Length(width as u16)
+        })
+        .collect::<Vec<_>>();
+
+    Table::new(rows.iter().map(|row| Row::new(row.clone())), widths)
+        .header(Row::new(headers.to_vec()).bold())
+}
+
+fn inspect_lines(info: &ContainerInfo, stats: Option<&StatsSample>) -> Vec<String> {
+    let mut lines = vec![format!("Status: {}", info.status)];
+    lines.extend(info.pid.map(|pid| format!("PID: {}", pid)));
+    lines.extend(info.exit_code.map(|exit_code| format!("Exit code: {}", exit_code)));
+    lines.extend(Some(&info.runtime).filter(|runtime| !runtime.is_empty()).map(|runtime| format!("Runtime: {}", runtime)));
+    lines.push(format!("Restart policy: {}", info.restart_policy));
+    lines.push(format!("Restart count: {}", info.restart_count));
+    if let Some(health) = &info.health {
+        lines.push(format!("Health: {}", health));
+        lines.push(format!("Failing streak: {}", info.failing_streak));
+    }
+    for (key, value) in &info.labels {
+        lines.push(format!("Label: {}={}", key, value));
+    }
+    if let Some(stats) = stats {
+        lines.push(format!("CPU: {:.1}%", stats.cpu_percent));
+        lines.push(format!("Memory: {}", crate::format_size(stats.memory)));
+        lines.push(format!("Disk IO: {} read, {} written", crate::format_size(stats.read_bytes), crate::format_size(stats.write_bytes)));
+        lines.push(format!("Network IO: {} received, {} sent", crate::format_size(stats.rx_bytes), crate::format_size(stats.tx_bytes)));
+    }
+
+    lines
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// The UTC time of the day of a Unix timestamp.
+fn clock(timestamp: u64) -> String {
+    format!("{:02}:{:02}:{:02}", timestamp % 86400 / 3600, timestamp % 3600 / 60, timestamp % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use super::*;
+
+    fn dashboard() -> (Dashboard, mpsc::Receiver<Job>) {
+        let (jobs, received) = mpsc::channel();
+        let clients = vec![
+            JokerClient::new("a", "127.0.0.1:7101".parse().unwrap()),
+            JokerClient::new("b", "127.0.0.1:7102".parse().unwrap()),
+        ];
+        (Dashboard::new(clients, jobs), received)
+    }
+
+    fn container(name: &str, status: &str, restart_count: u32) -> ContainerInfo {
+        ContainerInfo { name: name.to_owned(), status: status.to_owned(), restart_count, ..Default::default() }
+    }
+
+    fn up(containers: Vec<ContainerInfo>) -> DaemonState {
+        DaemonState { status: Ok(DaemonStatus::default()), containers, stats: HashMap::new() }
+    }
+
+    fn down() -> DaemonState {
+        DaemonState { status: Err("Connection refused".to_owned()), containers: Vec::new(), stats: HashMap::new() }
+    }
+
+    fn events(dashboard: &Dashboard) -> Vec<&str> {
+        dashboard.events.iter().map(|(_, event)| event.as_str()).collect()
+    }
+
+    fn screen(dashboard: &Dashboard) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 24)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer[(x, y)].symbol()).collect::<String>() + "\n")
+            .collect()
+    }
+
+    #[test]
+    fn records_what_changed_between_refreshes() {
+        let (mut dashboard, _jobs) = dashboard();
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 0), container("job", "running", 0)]), down()]));
+        assert_eq!(events(&dashboard), ["daemon b: Connection refused"]);
+
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 1), container("db", "running", 0)]), up(Vec::new())]));
+        assert_eq!(&events(&dashboard)[1..], [
+            "a/web restarted, 1 restarts so far",
+            "a/db appeared, running",
+            "a/job was removed",
+            "daemon b is back",
+        ]);
+    }
+
+    #[test]
+    fn actions_are_left_to_the_worker() {
+        let (mut dashboard, jobs) = dashboard();
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 0)]), up(vec![container("db", "running", 0)])]));
+
+        dashboard.press(KeyCode::Down);
+        dashboard.press(KeyCode::Char('s'));
+        dashboard.press(KeyCode::Up);
+        dashboard.press(KeyCode::Char('r'));
+        assert_eq!(jobs.try_iter().collect::<Vec<_>>(), [
+            Job::Stop { daemon: 1, container: "db".to_owned() },
+            Job::Restart { daemon: 0, container: "web".to_owned() },
+        ]);
+    }
+
+    #[test]
+    fn the_logs_view_asks_only_for_new_output() {
+        let (mut dashboard, jobs) = dashboard();
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 0)]), up(Vec::new())]));
+
+        dashboard.press(KeyCode::Char('l'));
+        dashboard.apply(Update::Logs { daemon: 0, container: "web".to_owned(), output: Ok((b"listening\n".to_vec(), 10)) });
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 0)]), up(Vec::new())]));
+        assert_eq!(jobs.try_iter().collect::<Vec<_>>(), [
+            Job::Logs { daemon: 0, container: "web".to_owned(), offset: 0 },
+            Job::Logs { daemon: 0, container: "web".to_owned(), offset: 10 },
+        ]);
+        assert!(screen(&dashboard).contains("listening"));
+
+        // a new run of the container starts over
+        dashboard.apply(Update::Logs { daemon: 0, container: "web".to_owned(), output: Ok((b"again\n".to_vec(), 6)) });
+        let screen = screen(&dashboard);
+        assert!(screen.contains("again") && !screen.contains("listening"));
+    }
+
+    #[test]
+    fn draws_the_daemons_and_their_containers() {
+        let (mut dashboard, _jobs) = dashboard();
+        assert!(screen(&dashboard).contains("refreshing..."));
+
+        dashboard.apply(Update::Refreshed(vec![up(vec![container("web", "running", 2)]), down()]));
+        let screen = screen(&dashboard);
+        assert!(screen.contains("1 containers"));
+        assert!(screen.contains("unreachable"));
+        assert!(screen.lines().any(|line| line.contains("web") && line.contains("running")));
+        assert!(screen.contains("daemon b: Connection refused"));
+        assert!(screen.contains(HELP));
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::path::PathBuf;
+use joker::client::JokerClient;
+use joker::daemon::{Requests, Selection};
+use joker::errors::JokerError;
+use joker::mock::MockTransport;
+use joker::signals::{Signal, WindowSize};
+
+fn client(transport: &MockTransport) -> JokerClient {
+    JokerClient::new("test", "127.0.0.1:7788".parse().unwrap()).with_transport(transport.clone())
+}
+
+/// A directory of its own for a test, as the tests run at once.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("joker-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Appends a message prefixed with its length, like the protocol frames most of them.
+fn message(request: &mut Vec<u8>, data: &[u8]) {
+    request.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    request.extend_from_slice(data);
+}
+
+fn read_message<'a>(data: &mut &'a [u8]) -> &'a [u8] {
+    let (length, rest) = data.split_at(8);
+    let (message, rest) = rest.split_at(u64::from_le_bytes(length.try_into().unwrap()) as usize);
+    *data = rest;
+    message
+}
+
+#[test]
+fn run_sends_the_binary_and_its_config() {
+    let dir = test_dir("run");
+    let binary = dir.join("app");
+    fs::write(&binary, b"\x7fELF binary").unwrap();
+    fs::write(dir.join("app.joker"), "Container name: web\nEnv: PORT=8080\n").unwrap();
+
+    let transport = MockTransport::new();
+    let names = client(&transport).run(&[binary.to_str().unwrap()], &Default::default(), None).unwrap();
+    assert_eq!(names, ["web"]);
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    let (request, mut rest) = requests[0].split_first().unwrap();
+    assert_eq!(*request, Requests::Run as u8);
+    assert_eq!(read_message(&mut rest), b"app");
+    assert_eq!(read_message(&mut rest), b"\x7fELF binary");
+    // the config gets the defaults of the options too
+    let config = String::from_utf8(read_message(&mut rest).to_vec()).unwrap();
+    assert!(config.lines().any(|line| line == "Container name: web"), "{}", config);
+    assert!(config.lines().any(|line| line == "Env: PORT=8080"), "{}", config);
+    assert_eq!(read_message(&mut rest), b"[]");
+    assert!(rest.is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn logs_are_read_for_a_container() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"listening on :8080\n");
+
+    assert_eq!(client(&transport).logs("web").unwrap(), "listening on :8080\n");
+
+    let mut expected = vec![Requests::Logs as u8];
+    message(&mut expected, b"web");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn logs_from_asks_only_for_the_output_after_an_offset() {
+    let transport = MockTransport::new();
+    let mut response = Vec::new();
+    message(&mut response, b"done\n");
+    message(&mut response, b"24");
+    transport.respond(response);
+
+    let (output, length) = client(&transport).logs_from("job", 19).unwrap();
+    assert_eq!((output.as_slice(), length), (&b"done\n"[..], 24));
+
+    let mut expected = vec![Requests::LogsFrom as u8];
+    message(&mut expected, b"job");
+    message(&mut expected, b"19");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn trace_returns_the_events_of_the_daemon() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"web started\nweb exited with 0\n");
+
+    assert_eq!(client(&transport).trace().unwrap(), "web started\nweb exited with 0\n");
+    assert_eq!(transport.requests(), [vec![Requests::Trace as u8]]);
+}
+
+#[test]
+fn stop_names_the_container() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"ok");
+
+    client(&transport).stop("web").unwrap();
+
+    let mut expected = vec![Requests::Stop as u8];
+    message(&mut expected, b"web");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn a_resize_is_sent_with_the_size_of_the_terminal() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"ok");
+
+    client(&transport).signal("web", Signal::WindowChange(WindowSize { rows: 40, columns: 120 })).unwrap();
+
+    let mut expected = vec![Requests::Signal as u8];
+    message(&mut expected, b"web");
+    message(&mut expected, b"SIGWINCH");
+    message(&mut expected, b"120x40");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn images_are_listed_and_removed_on_the_daemon() {
+    let transport = MockTransport::new();
+    transport.respond_message(br#"[{"reference":"docker.io/library/alpine:3","digest":"sha256:ab","kind":"image","size":3}]"#);
+    transport.respond_message(b"Removed docker.io/library/alpine:3.");
+
+    let client = client(&transport);
+    let images = client.images().unwrap();
+    assert_eq!(images.len(), 1);
+    assert_eq!((images[0].reference.as_str(), images[0].size), ("docker.io/library/alpine:3", 3));
+    assert_eq!(client.remove_image("docker.io/library/alpine:3").unwrap(), "Removed docker.io/library/alpine:3.");
+
+    let mut remove = vec![Requests::RemoveImage as u8];
+    message(&mut remove, b"docker.io/library/alpine:3");
+    assert_eq!(transport.requests(), [vec![Requests::Images as u8], remove]);
+}
+
+#[test]
+fn trusted_keys_are_checked_before_they_are_sent() {
+    const KEY: &str = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+    let transport = MockTransport::new();
+    transport.respond_message(format!(r#"["{}"]"#, KEY).as_bytes());
+    transport.respond_message(b"Trusted.");
+    transport.respond_message(b"Untrusted.");
+
+    let client = client(&transport);
+    assert_eq!(client.trusted_keys().unwrap(), [KEY]);
+    assert!(matches!(client.trust("not a key"), Err(JokerError::Parse(_))));
+    assert_eq!(client.trust(KEY).unwrap(), "Trusted.");
+    assert_eq!(client.untrust("c9fc2f15f2240165").unwrap(), "Untrusted.");
+
+    let mut add = vec![Requests::TrustAdd as u8];
+    message(&mut add, KEY.as_bytes());
+    let mut remove = vec![Requests::TrustRemove as u8];
+    message(&mut remove, b"c9fc2f15f2240165");
+    assert_eq!(transport.requests(), [vec![Requests::TrustList as u8], add, remove]);
+}
+
+#[test]
+fn volumes_are_named_by_their_only_message() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"Created volume data.");
+    transport.respond_message(b"data\ncache");
+    transport.respond_message(b"Removed volume data.");
+
+    let client = client(&transport);
+    assert_eq!(client.create_volume("data").unwrap(), "Created volume data.");
+    assert_eq!(client.volumes().unwrap(), "data\ncache");
+    assert_eq!(client.remove_volume("data").unwrap(), "Removed volume data.");
+    assert!(matches!(client.create_volume("../data"), Err(JokerError::Parse(_))));
+
+    let mut create = vec![Requests::VolumeCreate as u8];
+    message(&mut create, b"data");
+    let mut remove = vec![Requests::VolumeRemove as u8];
+    message(&mut remove, b"data");
+    assert_eq!(transport.requests(), [create, vec![Requests::VolumeList as u8], remove]);
+}
+
+#[test]
+fn a_secret_is_sent_with_its_value_only_on_creation() {
+    let transport = MockTransport::new();
+    transport.respond_message(b"Created secret token.");
+    transport.respond_message(b"token");
+    transport.respond_message(b"Removed secret token.");
+
+    let client = client(&transport);
+    assert_eq!(client.create_secret("token", b"hunter2").unwrap(), "Created secret token.");
+    assert!(client.create_secret("token", b"").is_err());
+    assert_eq!(client.secrets().unwrap(), "token");
+    assert_eq!(client.remove_secret("token").unwrap(), "Removed secret token.");
+
+    let mut create = vec![Requests::SecretCreate as u8];
+    message(&mut create, b"token");
+    message(&mut create, b"hunter2");
+    let mut remove = vec![Requests::SecretRemove as u8];
+    message(&mut remove, b"token");
+    assert_eq!(transport.requests(), [create, vec![Requests::SecretList as u8], remove]);
+}
+
+#[test]
+fn stacks_are_listed_and_taken_down() {
+    let transport = MockTransport::new();
+    transport.respond_message(br#"[{"name":"shop","containers":["db","web"]}]"#);
+    transport.respond_message(b"Removed stack shop.");
+
+    let client = client(&transport);
+    let stacks = client.stacks().unwrap();
+    assert_eq!((stacks[0].name.as_str(), stacks[0].containers.as_slice()), ("shop", &["db".to_owned(), "web".to_owned()][..]));
+    assert_eq!(client.stack_down("shop").unwrap(), "Removed stack shop.");
+
+    let mut down = vec![Requests::StackDown as u8];
+    message(&mut down, b"shop");
+    assert_eq!(transport.requests(), [vec![Requests::StackList as u8], down]);
+}
+
+#[test]
+fn inspect_returns_the_state_of_a_container() {
+    let transport = MockTransport::new();
+    transport.respond_message(br#"{"name":"web","status":"exited","exit_code":1,"restart_count":2}"#);
+
+    let info = client(&transport).inspect("web").unwrap();
+    assert_eq!((info.status.as_str(), info.exit_code, info.restart_count), ("exited", Some(1), 2));
+
+    let mut expected = vec![Requests::Inspect as u8];
+    message(&mut expected, b"web");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn the_sbom_of_a_container_may_be_missing() {
+    let transport = MockTransport::new();
+    transport.respond_message(br#"{"bomFormat":"CycloneDX"}"#);
+    transport.respond_error("Error: web was run without an SBOM.");
+
+    let client = client(&transport);
+    assert_eq!(client.sbom("web").unwrap(), br#"{"bomFormat":"CycloneDX"}"#);
+    assert!(matches!(client.sbom("web"), Err(JokerError::Rejected(_))));
+}
+
+#[test]
+fn a_config_is_sent_with_its_name() {
+    let transport = MockTransport::new();
+
+    client(&transport).send_config("app.cfg", b"Port: 8080\n").unwrap();
+
+    let mut expected = vec![Requests::Send as u8];
+    message(&mut expected, b"app.cfg");
+    message(&mut expected, b"Port: 8080\n");
+    assert_eq!(transport.requests(), [expected]);
+}
+
+#[test]
+fn a_refused_request_is_rejected_with_its_reason() {
+    let transport = MockTransport::new();
+    transport.respond_error("Error: no such container web.");
+
+    match client(&transport).stop("web") {
+        Err(JokerError::Rejected(message)) => assert_eq!(message, "Error: no such container web."),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
+
+#[test]
+fn a_closed_connection_is_a_connection_error() {
+    let transport = MockTransport::new();
+
+    let err = client(&transport).logs("web").unwrap_err();
+    assert!(matches!(err, JokerError::Connection(_)), "got {:?}", err);
+    assert_eq!(err.exit_code(), 4);
+}
+
+#[test]
+fn a_selection_reads_and_writes_its_own_config() {
+    let config = test_dir("selection").join("config.cfg");
+    fs::write(&config, r#"{"current_daemon":{"name":"a","socket_address":"127.0.0.1:7101"},"daemons":{"b":"127.0.0.1:7102"}}"#).unwrap();
+
+    let selection = Selection { config: Some(config.clone()), daemon: Some("b".to_owned()) };
+    assert_eq!(JokerClient::from_selection(&selection).unwrap().daemon().socket_address.to_string(), "127.0.0.1:7102");
+
+    let selection = Selection { config: Some(config), daemon: None };
+    assert_eq!(selection.current_daemon().unwrap().name, "a");
+    selection.checkout("b").unwrap();
+    assert_eq!(selection.current_daemon().unwrap().name, "b");
+    assert_eq!(selection.find_daemon("a").unwrap().socket_address.to_string(), "127.0.0.1:7101");
+}